@@ -10,7 +10,6 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use clap::Parser;
-use dtls::extension::extension_use_srtp::SrtpProtectionProfile;
 use log::{error, info};
 use opentelemetry::{/*global,*/ metrics::MeterProvider, KeyValue};
 use opentelemetry_sdk::metrics::{PeriodicReader, SdkMeterProvider};
@@ -93,7 +92,8 @@ fn init_meter_provider(
             let _worker = worker;
             let exporter = MetricsExporterBuilder::default()
                 .with_encoder(|writer, data| {
-                    Ok(serde_json::to_writer_pretty(writer, &data).unwrap())
+                    serde_json::to_writer_pretty(writer, &data).unwrap();
+                    Ok(())
                 })
                 .build();
             let reader = PeriodicReader::builder(exporter, runtime::TokioCurrentThread)
@@ -111,8 +111,7 @@ fn init_meter_provider(
         });
     });
 
-    let meter_provider = rx.recv().unwrap();
-    meter_provider
+    rx.recv().unwrap()
 }
 
 fn main() -> anyhow::Result<()> {
@@ -145,23 +144,13 @@ fn main() -> anyhow::Result<()> {
 
     let key_pair = rcgen::KeyPair::generate(&rcgen::PKCS_ECDSA_P256_SHA256)?;
     let certificates = vec![RTCCertificate::from_key_pair(key_pair)?];
-    let dtls_handshake_config = Arc::new(
-        dtls::config::ConfigBuilder::default()
-            .with_certificates(
-                certificates
-                    .iter()
-                    .map(|c| c.dtls_certificate.clone())
-                    .collect(),
-            )
-            .with_srtp_protection_profiles(vec![SrtpProtectionProfile::Srtp_Aes128_Cm_Hmac_Sha1_80])
-            .with_extended_master_secret(dtls::config::ExtendedMasterSecretType::Require)
-            .build(false, None)?,
-    );
+    let dtls_config_builder = dtls::config::ConfigBuilder::default()
+        .with_extended_master_secret(dtls::config::ExtendedMasterSecretType::Require);
     let sctp_endpoint_config = Arc::new(sctp::EndpointConfig::default());
     let sctp_server_config = Arc::new(sctp::ServerConfig::default());
     let server_config = Arc::new(
         ServerConfig::new(certificates)
-            .with_dtls_handshake_config(dtls_handshake_config)
+            .with_dtls_config_builder(dtls_config_builder)
             .with_sctp_endpoint_config(sctp_endpoint_config)
             .with_sctp_server_config(sctp_server_config),
     );
@@ -203,8 +192,8 @@ fn main() -> anyhow::Result<()> {
                         let demuxer_handler = DemuxerHandler::new();
                         let stun_handler = StunHandler::new();
                         // DTLS
-                        let dtls_handler = DtlsHandler::new(local_addr, Rc::clone(&server_states_moved));
-                        let sctp_handler = SctpHandler::new(local_addr, Rc::clone(&server_states_moved));
+                        let dtls_handler = DtlsHandler::new(Rc::clone(&server_states_moved));
+                        let sctp_handler = SctpHandler::new(Rc::clone(&server_states_moved));
                         let data_channel_handler = DataChannelHandler::new();
                         // SRTP
                         let srtp_handler = SrtpHandler::new(Rc::clone(&server_states_moved));
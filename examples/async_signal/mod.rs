@@ -10,7 +10,7 @@ use log::{debug, error, info};
 use sfu::{RTCSessionDescription, ServerStates};
 use std::cell::RefCell;
 use std::collections::HashMap;
-use std::io::{Error, ErrorKind};
+use std::io::Error;
 use std::net::SocketAddr;
 use std::rc::Rc;
 use std::sync::Arc;
@@ -145,7 +145,7 @@ async fn remote_handler(
         return Ok(response);
     }
     let session_id = path[2].parse::<u64>().unwrap();
-    let mut sorted_ports: Vec<u16> = media_port_thread_map.keys().map(|x| *x).collect();
+    let mut sorted_ports: Vec<u16> = media_port_thread_map.keys().copied().collect();
     sorted_ports.sort();
     assert!(!sorted_ports.is_empty());
     let port = sorted_ports[(session_id as usize) % sorted_ports.len()];
@@ -347,10 +347,7 @@ pub fn handle_signaling_message(
                     endpoint_id,
                 })
                 .map_err(|_| {
-                    Error::new(
-                        ErrorKind::Other,
-                        "failed to send back signaling message response".to_string(),
-                    )
+                    Error::other("failed to send back signaling message response".to_string())
                 })?)
         }
         SignalingProtocolMessage::Offer {
@@ -405,10 +402,7 @@ pub fn handle_signaling_message(
                 reason: Bytes::from("Invalid Request"),
             })
             .map_err(|_| {
-                Error::new(
-                    ErrorKind::Other,
-                    "failed to send back signaling message response".to_string(),
-                )
+                Error::other("failed to send back signaling message response".to_string())
             })?),
     }
 }
@@ -443,10 +437,7 @@ fn handle_offer_message(
                 answer_sdp,
             })
             .map_err(|_| {
-                Error::new(
-                    ErrorKind::Other,
-                    "failed to send back signaling message response".to_string(),
-                )
+                Error::other("failed to send back signaling message response".to_string())
             })?),
         Err(err) => Ok(response_tx
             .send(SignalingProtocolMessage::Err {
@@ -455,10 +446,7 @@ fn handle_offer_message(
                 reason: Bytes::from(err.to_string()),
             })
             .map_err(|_| {
-                Error::new(
-                    ErrorKind::Other,
-                    "failed to send back signaling message response".to_string(),
-                )
+                Error::other("failed to send back signaling message response".to_string())
             })?),
     }
 }
@@ -487,10 +475,7 @@ fn handle_answer_message(
                 endpoint_id,
             })
             .map_err(|_| {
-                Error::new(
-                    ErrorKind::Other,
-                    "failed to send back signaling message response".to_string(),
-                )
+                Error::other("failed to send back signaling message response".to_string())
             })?),
         Err(err) => Ok(response_tx
             .send(SignalingProtocolMessage::Err {
@@ -499,10 +484,7 @@ fn handle_answer_message(
                 reason: Bytes::from(err.to_string()),
             })
             .map_err(|_| {
-                Error::new(
-                    ErrorKind::Other,
-                    "failed to send back signaling message response".to_string(),
-                )
+                Error::other("failed to send back signaling message response".to_string())
             })?),
     }
 }
@@ -525,10 +507,7 @@ fn handle_leave_message(
                 endpoint_id,
             })
             .map_err(|_| {
-                Error::new(
-                    ErrorKind::Other,
-                    "failed to send back signaling message response".to_string(),
-                )
+                Error::other("failed to send back signaling message response".to_string())
             })?),
         Err(err) => Ok(response_tx
             .send(SignalingProtocolMessage::Err {
@@ -537,10 +516,7 @@ fn handle_leave_message(
                 reason: Bytes::from(err.to_string()),
             })
             .map_err(|_| {
-                Error::new(
-                    ErrorKind::Other,
-                    "failed to send back signaling message response".to_string(),
-                )
+                Error::other("failed to send back signaling message response".to_string())
             })?),
     }
 }
@@ -1,5 +1,4 @@
 use clap::Parser;
-use dtls::extension::extension_use_srtp::SrtpProtectionProfile;
 use log::info;
 use opentelemetry::{/*global,*/ KeyValue};
 use opentelemetry_sdk::metrics::{PeriodicReader, SdkMeterProvider};
@@ -9,7 +8,7 @@ use rouille::Server;
 use sfu::{RTCCertificate, ServerConfig};
 use std::collections::HashMap;
 use std::io::Write;
-use std::net::{IpAddr, UdpSocket};
+use std::net::{IpAddr, TcpListener, UdpSocket};
 use std::str::FromStr;
 use std::sync::mpsc::{self};
 use std::sync::Arc;
@@ -57,6 +56,8 @@ struct Cli {
     media_port_min: u16,
     #[arg(long, default_value_t = 3495)]
     media_port_max: u16,
+    #[arg(long)]
+    public_ip: Option<IpAddr>,
 
     #[arg(short, long)]
     force_local_loop: bool,
@@ -84,7 +85,8 @@ fn init_meter_provider(
             let worker = wait_group.add(1);
             let exporter = MetricsExporterBuilder::default()
                 .with_encoder(|writer, data| {
-                    Ok(serde_json::to_writer_pretty(writer, &data).unwrap())
+                    serde_json::to_writer_pretty(writer, &data).unwrap();
+                    Ok(())
                 })
                 .build();
             let reader = PeriodicReader::builder(exporter, runtime::TokioCurrentThread)
@@ -103,8 +105,7 @@ fn init_meter_provider(
         });
     });
 
-    let meter_provider = rx.recv().unwrap();
-    meter_provider
+    rx.recv().unwrap()
 }
 
 fn main() -> anyhow::Result<()> {
@@ -142,27 +143,15 @@ fn main() -> anyhow::Result<()> {
 
     let key_pair = rcgen::KeyPair::generate(&rcgen::PKCS_ECDSA_P256_SHA256)?;
     let certificates = vec![RTCCertificate::from_key_pair(key_pair)?];
-    let dtls_handshake_config = Arc::new(
-        dtls::config::ConfigBuilder::default()
-            .with_certificates(
-                certificates
-                    .iter()
-                    .map(|c| c.dtls_certificate.clone())
-                    .collect(),
-            )
-            .with_srtp_protection_profiles(vec![SrtpProtectionProfile::Srtp_Aes128_Cm_Hmac_Sha1_80])
-            .with_extended_master_secret(dtls::config::ExtendedMasterSecretType::Require)
-            .build(false, None)?,
-    );
+    let dtls_config_builder = dtls::config::ConfigBuilder::default()
+        .with_extended_master_secret(dtls::config::ExtendedMasterSecretType::Require);
     let sctp_endpoint_config = Arc::new(sctp::EndpointConfig::default());
     let sctp_server_config = Arc::new(sctp::ServerConfig::default());
-    let server_config = Arc::new(
-        ServerConfig::new(certificates)
-            .with_dtls_handshake_config(dtls_handshake_config)
-            .with_sctp_endpoint_config(sctp_endpoint_config)
-            .with_sctp_server_config(sctp_server_config)
-            .with_idle_timeout(Duration::from_secs(30)),
-    );
+    // advertise public IP in candidates when running behind NAT
+    let nat_1to1_ips = cli
+        .public_ip
+        .map(|public_ip| HashMap::from([(host_addr, public_ip)]))
+        .unwrap_or_default();
     let (stop_meter_tx, stop_meter_rx) = async_broadcast::broadcast::<()>(1);
     let wait_group = WaitGroup::new();
     let meter_provider = init_meter_provider(stop_meter_rx, wait_group.clone());
@@ -175,15 +164,33 @@ fn main() -> anyhow::Result<()> {
         // Spin up a UDP socket for the RTC. All WebRTC traffic is going to be multiplexed over this single
         // server socket. Clients are identified via their respective remote (UDP) socket address.
         let socket = UdpSocket::bind(format!("{host_addr}:{port}"))
-            .expect(&format!("binding to {host_addr}:{port}"));
+            .unwrap_or_else(|_| panic!("binding to {host_addr}:{port}"));
+        // ICE-TCP connections are accepted on an ephemeral port, advertised as passive TCP candidate
+        let tcp_listener = TcpListener::bind(format!("{host_addr}:0"))
+            .unwrap_or_else(|_| panic!("binding to {host_addr}:0"));
+        let server_config = Arc::new(
+            ServerConfig::new(certificates.clone())
+                .with_nat_1to1_ips(nat_1to1_ips.clone())
+                .with_dtls_config_builder(dtls_config_builder.clone())
+                .with_sctp_endpoint_config(Arc::clone(&sctp_endpoint_config))
+                .with_sctp_server_config(Arc::clone(&sctp_server_config))
+                .with_idle_timeout(Duration::from_secs(30))
+                .with_emit_events(true)
+                .with_tcp_addrs(vec![tcp_listener.local_addr()?]),
+        );
 
         media_port_thread_map.insert(port, signaling_tx);
-        let server_config = server_config.clone();
         let meter_provider = meter_provider.clone();
         // The run loop is on a separate thread to the web server.
         std::thread::spawn(move || {
-            if let Err(err) = sync_run(stop_rx, socket, signaling_rx, server_config, meter_provider)
-            {
+            if let Err(err) = sync_run(
+                stop_rx,
+                socket,
+                tcp_listener,
+                signaling_rx,
+                server_config,
+                meter_provider,
+            ) {
                 eprintln!("run_sfu got error: {}", err);
             }
             worker.done();
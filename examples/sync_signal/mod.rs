@@ -8,19 +8,22 @@ use retty::channel::{InboundPipeline, Pipeline};
 use retty::transport::{TaggedBytesMut, TransportContext};
 use rouille::{Request, Response, ResponseBody};
 use sfu::{
-    DataChannelHandler, DemuxerHandler, DtlsHandler, ExceptionHandler, GatewayHandler,
-    InterceptorHandler, RTCSessionDescription, SctpHandler, ServerConfig, ServerStates,
-    SrtpHandler, StunHandler,
+    DataChannelHandler, DataChannelOptions, DemuxerHandler, DtlsHandler, ExceptionHandler,
+    GatewayHandler, InterceptorHandler, RTCSessionDescription, SctpHandler, ServerConfig,
+    ServerEvent, ServerStates, SrtpHandler, StunHandler, TcpFramingHandler,
 };
 use std::cell::RefCell;
 use std::collections::HashMap;
-use std::io::{Error, ErrorKind, Read};
-use std::net::{SocketAddr, UdpSocket};
+use std::io::{Error, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, UdpSocket};
 use std::rc::Rc;
 use std::sync::mpsc::{Receiver, SyncSender};
 use std::sync::{mpsc, Arc};
 use std::time::{Duration, Instant};
 
+/// label of the data channel opened by server toward each endpoint
+pub const SERVER_DATA_CHANNEL_LABEL: &str = "server";
+
 // Handle a web request.
 pub fn web_request(
     request: &Request,
@@ -37,7 +40,7 @@ pub fn web_request(
     }
 
     let session_id = path[2].parse::<u64>().unwrap();
-    let mut sorted_ports: Vec<u16> = media_port_thread_map.keys().map(|x| *x).collect();
+    let mut sorted_ports: Vec<u16> = media_port_thread_map.keys().copied().collect();
     sorted_ports.sort();
     assert!(!sorted_ports.is_empty());
     let port = sorted_ports[(session_id as usize) % sorted_ports.len()];
@@ -90,11 +93,12 @@ pub fn web_request(
     }
 }
 
-/// This is the "main run loop" that handles all clients, reads and writes UdpSocket traffic,
-/// and forwards media data between clients.
+/// This is the "main run loop" that handles all clients, reads and writes UdpSocket traffic and
+/// ICE-TCP connections accepted by TcpListener, and forwards media data between clients.
 pub fn sync_run(
     stop_rx: crossbeam_channel::Receiver<()>,
     socket: UdpSocket,
+    tcp_listener: TcpListener,
     rx: Receiver<SignalingMessage>,
     server_config: Arc<ServerConfig>,
     meter_provider: SdkMeterProvider,
@@ -106,8 +110,12 @@ pub fn sync_run(
     )?));
 
     println!("listening {}...", socket.local_addr()?);
+    println!("listening tcp {}...", tcp_listener.local_addr()?);
+
+    tcp_listener.set_nonblocking(true)?;
+    let mut tcp_streams: HashMap<SocketAddr, TcpStream> = HashMap::new();
 
-    let pipeline = build_pipeline(socket.local_addr()?, server_states.clone());
+    let pipeline = build_pipeline(server_states.clone());
 
     let mut buf = vec![0; 2000];
 
@@ -122,7 +130,12 @@ pub fn sync_run(
             }
         };
 
-        write_socket_output(&socket, &pipeline)?;
+        write_socket_output(&socket, &tcp_listener, &mut tcp_streams, &pipeline)?;
+
+        accept_tcp_streams(&tcp_listener, &mut tcp_streams);
+        for input in read_tcp_input(&tcp_listener, &mut tcp_streams, &mut buf) {
+            pipeline.read(input);
+        }
 
         // Spawn new incoming signal message from the signaling server thread.
         if let Ok(signal_message) = rx.try_recv() {
@@ -136,9 +149,13 @@ pub fn sync_run(
         let mut eto = Instant::now() + Duration::from_millis(100);
         pipeline.poll_timeout(&mut eto);
 
-        let delay_from_now = eto
+        let mut delay_from_now = eto
             .checked_duration_since(Instant::now())
             .unwrap_or(Duration::from_secs(0));
+        if !tcp_streams.is_empty() {
+            // TCP streams are polled between UDP reads
+            delay_from_now = delay_from_now.min(Duration::from_millis(10));
+        }
         if delay_from_now.is_zero() {
             pipeline.handle_timeout(Instant::now());
             continue;
@@ -154,6 +171,15 @@ pub fn sync_run(
 
         // Drive time forward in all clients.
         pipeline.handle_timeout(Instant::now());
+
+        let events: Vec<ServerEvent> =
+            std::iter::from_fn(|| server_states.borrow_mut().poll_event()).collect();
+        for event in events {
+            log::info!("server event: {:?}", event);
+            if let Err(err) = handle_server_event(&server_states, event) {
+                error!("handle_server_event got error:{}", err);
+            }
+        }
     }
     pipeline.transport_inactive();
 
@@ -164,30 +190,139 @@ pub fn sync_run(
     Ok(())
 }
 
+/// server opens a data channel to each endpoint whose signaling data channel is ready, and
+/// greets on it once the endpoint acks it
+fn handle_server_event(
+    server_states: &Rc<RefCell<ServerStates>>,
+    event: ServerEvent,
+) -> anyhow::Result<()> {
+    match event {
+        ServerEvent::DataChannelReady {
+            session_id,
+            endpoint_id,
+            ..
+        } => server_states.borrow_mut().open_data_channel(
+            session_id,
+            endpoint_id,
+            SERVER_DATA_CHANNEL_LABEL.to_string(),
+            DataChannelOptions::default(),
+        )?,
+        ServerEvent::DataChannelOpened {
+            session_id,
+            endpoint_id,
+            label,
+        } if label == SERVER_DATA_CHANNEL_LABEL => {
+            server_states.borrow_mut().send_data_channel_message(
+                session_id,
+                endpoint_id,
+                &label,
+                BytesMut::from(&b"welcome"[..]),
+                false,
+            )?
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
 fn write_socket_output(
     socket: &UdpSocket,
+    tcp_listener: &TcpListener,
+    tcp_streams: &mut HashMap<SocketAddr, TcpStream>,
     pipeline: &Rc<Pipeline<TaggedBytesMut, TaggedBytesMut>>,
 ) -> anyhow::Result<()> {
+    let tcp_local_addr = tcp_listener.local_addr()?;
     while let Some(transmit) = pipeline.poll_transmit() {
-        socket.send_to(&transmit.message, transmit.transport.peer_addr)?;
+        let peer_addr = transmit.transport.peer_addr;
+        if transmit.transport.local_addr != tcp_local_addr {
+            socket.send_to(&transmit.message, peer_addr)?;
+        } else if let Some(stream) = tcp_streams.get_mut(&peer_addr) {
+            // a partially written frame breaks RFC 4571 framing, so the stream is closed
+            if let Err(err) = stream.write_all(&transmit.message) {
+                error!("TcpStream write to {} failed: {}", peer_addr, err);
+                tcp_streams.remove(&peer_addr);
+            }
+        }
     }
 
     Ok(())
 }
 
+fn accept_tcp_streams(
+    tcp_listener: &TcpListener,
+    tcp_streams: &mut HashMap<SocketAddr, TcpStream>,
+) {
+    loop {
+        match tcp_listener.accept() {
+            Ok((stream, peer_addr)) => {
+                if let Err(err) = stream.set_nonblocking(true) {
+                    error!(
+                        "TcpStream set_nonblocking for {} failed: {}",
+                        peer_addr, err
+                    );
+                    continue;
+                }
+                tcp_streams.insert(peer_addr, stream);
+            }
+            Err(e) => match e.kind() {
+                ErrorKind::WouldBlock => break,
+                _ => panic!("TcpListener accept failed: {e:?}"),
+            },
+        }
+    }
+}
+
+fn read_tcp_input(
+    tcp_listener: &TcpListener,
+    tcp_streams: &mut HashMap<SocketAddr, TcpStream>,
+    buf: &mut [u8],
+) -> Vec<TaggedBytesMut> {
+    let mut inputs = vec![];
+    let mut closed = vec![];
+    for (&peer_addr, stream) in tcp_streams.iter_mut() {
+        loop {
+            match stream.read(buf) {
+                Ok(0) => {
+                    closed.push(peer_addr);
+                    break;
+                }
+                Ok(n) => inputs.push(TaggedBytesMut {
+                    now: Instant::now(),
+                    transport: TransportContext {
+                        local_addr: tcp_listener.local_addr().unwrap(),
+                        peer_addr,
+                        ecn: None,
+                    },
+                    message: BytesMut::from(&buf[..n]),
+                }),
+                Err(e) => {
+                    if e.kind() != ErrorKind::WouldBlock {
+                        error!("TcpStream read from {} failed: {}", peer_addr, e);
+                        closed.push(peer_addr);
+                    }
+                    break;
+                }
+            }
+        }
+    }
+    // transports of closed streams are removed by idle timeout
+    for peer_addr in closed {
+        tcp_streams.remove(&peer_addr);
+    }
+    inputs
+}
+
 fn read_socket_input(socket: &UdpSocket, buf: &mut [u8]) -> Option<TaggedBytesMut> {
     match socket.recv_from(buf) {
-        Ok((n, peer_addr)) => {
-            return Some(TaggedBytesMut {
-                now: Instant::now(),
-                transport: TransportContext {
-                    local_addr: socket.local_addr().unwrap(),
-                    peer_addr,
-                    ecn: None,
-                },
-                message: BytesMut::from(&buf[..n]),
-            });
-        }
+        Ok((n, peer_addr)) => Some(TaggedBytesMut {
+            now: Instant::now(),
+            transport: TransportContext {
+                local_addr: socket.local_addr().unwrap(),
+                peer_addr,
+                ecn: None,
+            },
+            message: BytesMut::from(&buf[..n]),
+        }),
 
         Err(e) => match e.kind() {
             // Expected error for set_read_timeout(). One for windows, one for the rest.
@@ -198,16 +333,17 @@ fn read_socket_input(socket: &UdpSocket, buf: &mut [u8]) -> Option<TaggedBytesMu
 }
 
 fn build_pipeline(
-    local_addr: SocketAddr,
     server_states: Rc<RefCell<ServerStates>>,
 ) -> Rc<Pipeline<TaggedBytesMut, TaggedBytesMut>> {
     let pipeline: Pipeline<TaggedBytesMut, TaggedBytesMut> = Pipeline::new();
 
+    // RFC 4571 framing of ICE-TCP connections, UDP packets bypass it
+    let tcp_framing_handler = TcpFramingHandler::new(Rc::clone(&server_states));
     let demuxer_handler = DemuxerHandler::new();
     let stun_handler = StunHandler::new();
     // DTLS
-    let dtls_handler = DtlsHandler::new(local_addr, Rc::clone(&server_states));
-    let sctp_handler = SctpHandler::new(local_addr, Rc::clone(&server_states));
+    let dtls_handler = DtlsHandler::new(Rc::clone(&server_states));
+    let sctp_handler = SctpHandler::new(Rc::clone(&server_states));
     let data_channel_handler = DataChannelHandler::new();
     // SRTP
     let srtp_handler = SrtpHandler::new(Rc::clone(&server_states));
@@ -216,6 +352,7 @@ fn build_pipeline(
     let gateway_handler = GatewayHandler::new(Rc::clone(&server_states));
     let exception_handler = ExceptionHandler::new();
 
+    pipeline.add_back(tcp_framing_handler);
     pipeline.add_back(demuxer_handler);
     pipeline.add_back(stun_handler);
     // DTLS
@@ -309,10 +446,7 @@ pub fn handle_signaling_message(
                 reason: Bytes::from("Invalid Request"),
             })
             .map_err(|_| {
-                Error::new(
-                    ErrorKind::Other,
-                    "failed to send back signaling message response".to_string(),
-                )
+                Error::other("failed to send back signaling message response".to_string())
             })?),
     }
 }
@@ -349,10 +483,7 @@ fn handle_offer_message(
                 answer_sdp,
             })
             .map_err(|_| {
-                Error::new(
-                    ErrorKind::Other,
-                    "failed to send back signaling message response".to_string(),
-                )
+                Error::other("failed to send back signaling message response".to_string())
             })?),
         Err(err) => Ok(response_tx
             .send(SignalingProtocolMessage::Err {
@@ -361,10 +492,7 @@ fn handle_offer_message(
                 reason: Bytes::from(err.to_string()),
             })
             .map_err(|_| {
-                Error::new(
-                    ErrorKind::Other,
-                    "failed to send back signaling message response".to_string(),
-                )
+                Error::other("failed to send back signaling message response".to_string())
             })?),
     }
 }
@@ -387,10 +515,7 @@ fn handle_leave_message(
                 endpoint_id,
             })
             .map_err(|_| {
-                Error::new(
-                    ErrorKind::Other,
-                    "failed to send back signaling message response".to_string(),
-                )
+                Error::other("failed to send back signaling message response".to_string())
             })?),
         Err(err) => Ok(response_tx
             .send(SignalingProtocolMessage::Err {
@@ -399,10 +524,7 @@ fn handle_leave_message(
                 reason: Bytes::from(err.to_string()),
             })
             .map_err(|_| {
-                Error::new(
-                    ErrorKind::Other,
-                    "failed to send back signaling message response".to_string(),
-                )
+                Error::other("failed to send back signaling message response".to_string())
             })?),
     }
 }
@@ -8,13 +8,17 @@ use crate::description::{
     rtp_extensions_from_media_description,
     rtp_transceiver::{PayloadType, RTCPFeedback, TYPE_RTCP_FB_TRANSPORT_CC},
     rtp_transceiver_direction::RTCRtpTransceiverDirection,
+    SDES_REPAIR_RTP_STREAM_ID_URI,
 };
 
 //TODO: use crate::stats::stats_collector::StatsCollector;
 //use crate::stats::CodecStats;
 //use crate::stats::StatsReportType::Codec;
+use crate::interceptors::nack::generator::Generator;
+use crate::interceptors::nack::responder::Responder;
 use crate::interceptors::report::receiver_report::ReceiverReport;
 use crate::interceptors::report::sender_report::SenderReport;
+use crate::interceptors::twcc::receiver::Receiver;
 use crate::interceptors::Registry;
 use sdp::description::session::SessionDescription;
 use shared::error::{Error, Result};
@@ -111,6 +115,7 @@ impl Default for MediaConfig {
 
         let _ = media_config.register_default_codecs();
         let _ = media_config.register_default_interceptors();
+        let _ = media_config.configure_simulcast_extension_headers();
 
         media_config
     }
@@ -126,7 +131,7 @@ impl MediaConfig {
     /// register_default_codecs is not safe for concurrent use.
     pub fn register_default_codecs(&mut self) -> Result<()> {
         // Default Audio Codecs
-        for codec in vec![
+        for codec in [
             RTCRtpCodecParameters {
                 capability: RTCRtpCodecCapability {
                     mime_type: MIME_TYPE_OPUS.to_owned(),
@@ -193,7 +198,7 @@ impl MediaConfig {
                 parameter: "pli".to_owned(),
             },*/
         ];
-        for codec in vec![
+        for codec in [
             RTCRtpCodecParameters {
                 capability: RTCRtpCodecCapability {
                     mime_type: MIME_TYPE_VP8.to_owned(),
@@ -340,8 +345,8 @@ impl MediaConfig {
     pub fn register_default_interceptors(&mut self) -> Result<()> {
         self.configure_rtcp_reports();
 
-        /*TODO:self.configure_nack();
-        self.configure_twcc_receiver_only()?;*/
+        self.configure_nack();
+        self.configure_twcc_receiver_only()?;
 
         Ok(())
     }
@@ -956,11 +961,11 @@ impl MediaConfig {
             RTPCodecType::Video,
         );
 
-        /*TODO: let generator = Box::new(Generator::builder());
         let responder = Box::new(Responder::builder());
-        registry.add(responder);
-        registry.add(generator);
-        registry*/
+        self.registry.add(responder);
+
+        let generator = Box::new(Generator::builder());
+        self.registry.add(generator);
     }
 
     /// configure_twcc will setup everything necessary for adding
@@ -1062,9 +1067,28 @@ impl MediaConfig {
             None,
         )?;
 
-        /*TODO:
         let receiver = Box::new(Receiver::builder());
-        registry.add(receiver);*/
+        self.registry.add(receiver);
+
+        Ok(())
+    }
+
+    /// configure_simulcast_extension_headers registers the header extensions which carry
+    /// the mid and rid of simulcast encodings, so that their ssrcs can be bound to layers.
+    pub fn configure_simulcast_extension_headers(&mut self) -> Result<()> {
+        for uri in [
+            sdp::extmap::SDES_MID_URI,
+            sdp::extmap::SDES_RTP_STREAM_ID_URI,
+            SDES_REPAIR_RTP_STREAM_ID_URI,
+        ] {
+            self.register_header_extension(
+                RTCRtpHeaderExtensionCapability {
+                    uri: uri.to_owned(),
+                },
+                RTPCodecType::Video,
+                None,
+            )?;
+        }
 
         Ok(())
     }
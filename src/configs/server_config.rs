@@ -1,28 +1,74 @@
 use crate::configs::media_config::MediaConfig;
 use crate::server::certificate::RTCCertificate;
+use crate::types::{EndpointId, SessionId};
+use bytes::BytesMut;
+use dtls::extension::extension_use_srtp::SrtpProtectionProfile;
+use shared::error::Result;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
 use std::time::Duration;
 
+/// DataChannelRelayFilter is called with (session_id, from endpoint_id, to endpoint_id, label,
+/// payload) for every application data channel message relayed between endpoints, and returns
+/// the payload to be sent or None to drop it
+pub type DataChannelRelayFilter =
+    dyn Fn(SessionId, EndpointId, EndpointId, &str, &BytesMut) -> Option<BytesMut> + Send + Sync;
+
 /// ServerConfig provides customized parameters for SFU server
 pub struct ServerConfig {
     pub(crate) certificates: Vec<RTCCertificate>,
-    pub(crate) dtls_handshake_config: Arc<dtls::config::HandshakeConfig>,
+    pub(crate) dtls_config_builder: dtls::config::ConfigBuilder,
+    pub(crate) fingerprint_algorithm: Option<String>,
+    pub(crate) srtp_protection_profiles: Vec<SrtpProtectionProfile>,
+    pub(crate) srtp_require_aead: bool,
+    pub(crate) srtp_replay_protection_window: usize,
+    pub(crate) srtcp_replay_protection_window: usize,
     pub(crate) sctp_endpoint_config: Arc<sctp::EndpointConfig>,
     pub(crate) sctp_server_config: Arc<sctp::ServerConfig>,
     pub(crate) media_config: MediaConfig,
     pub(crate) idle_timeout: Duration,
+    pub(crate) ice_disconnected_timeout: Duration,
+    pub(crate) ice_failed_timeout: Duration,
+    pub(crate) auto_subscribe: bool,
+    pub(crate) keyframe_request_interval: Duration,
+    pub(crate) emit_events: bool,
+    pub(crate) datachannel_signaling: bool,
+    pub(crate) data_channel_relay_filter: Option<Arc<DataChannelRelayFilter>>,
+    pub(crate) host_addrs: Vec<SocketAddr>,
+    pub(crate) nat_1to1_ips: HashMap<IpAddr, IpAddr>,
+    pub(crate) tcp_addrs: Vec<SocketAddr>,
 }
 
 impl ServerConfig {
-    /// create new server config
+    /// create new server config, the first certificate is presented in DTLS handshake until it is
+    /// rotated by ServerStates::rotate_certificate
     pub fn new(certificates: Vec<RTCCertificate>) -> Self {
         Self {
             certificates,
             media_config: MediaConfig::default(),
             sctp_endpoint_config: Arc::new(sctp::EndpointConfig::default()),
             sctp_server_config: Arc::new(sctp::ServerConfig::default()),
-            dtls_handshake_config: Arc::new(dtls::config::HandshakeConfig::default()),
+            dtls_config_builder: dtls::config::ConfigBuilder::default(),
+            fingerprint_algorithm: None,
+            srtp_protection_profiles: vec![
+                SrtpProtectionProfile::Srtp_Aead_Aes_128_Gcm,
+                SrtpProtectionProfile::Srtp_Aes128_Cm_Hmac_Sha1_80,
+            ],
+            srtp_require_aead: false,
+            srtp_replay_protection_window: 64,
+            srtcp_replay_protection_window: 64,
             idle_timeout: Duration::from_secs(30),
+            ice_disconnected_timeout: Duration::from_secs(10),
+            ice_failed_timeout: Duration::from_secs(30),
+            auto_subscribe: true,
+            keyframe_request_interval: Duration::from_millis(500),
+            emit_events: false,
+            datachannel_signaling: true,
+            data_channel_relay_filter: None,
+            host_addrs: vec![],
+            nat_1to1_ips: HashMap::new(),
+            tcp_addrs: vec![],
         }
     }
 
@@ -47,18 +93,172 @@ impl ServerConfig {
         self
     }
 
-    /// build with provided dtls::config::HandshakeConfig
-    pub fn with_dtls_handshake_config(
+    /// build with provided dtls::config::ConfigBuilder. Its certificates are replaced by the
+    /// certificate presented in DTLS handshake, its SRTP protection profiles by the ones allowed
+    /// by the SRTP policy, and its client_auth is forced to RequireAnyClientCert, so that the
+    /// remote certificate is verified against SDP fingerprints.
+    pub fn with_dtls_config_builder(
         mut self,
-        dtls_handshake_config: Arc<dtls::config::HandshakeConfig>,
+        dtls_config_builder: dtls::config::ConfigBuilder,
     ) -> Self {
-        self.dtls_handshake_config = dtls_handshake_config;
+        self.dtls_config_builder = dtls_config_builder;
+        self
+    }
+
+    /// build with hash function algorithm of certificate fingerprints advertised in SDP, one of
+    /// "sha-256", "sha-384" and "sha-512". By default, the digest algorithm used in certificate
+    /// signature is used.
+    pub fn with_fingerprint_algorithm(mut self, fingerprint_algorithm: &str) -> Self {
+        self.fingerprint_algorithm = Some(fingerprint_algorithm.to_lowercase());
         self
     }
 
+    /// build with SRTP protection profiles in preference order, by default AEAD_AES_128_GCM and
+    /// then AES128_CM_HMAC_SHA1_80. AEAD_AES_256_GCM and AES128_CM_HMAC_SHA1_32 are not supported
+    /// by SRTP contexts yet, so ServerStates::new fails with them.
+    pub fn with_srtp_protection_profiles(
+        mut self,
+        srtp_protection_profiles: Vec<SrtpProtectionProfile>,
+    ) -> Self {
+        self.srtp_protection_profiles = srtp_protection_profiles;
+        self
+    }
+
+    /// build with SRTP require AEAD, which only allows AEAD_AES_128_GCM to be negotiated, RFC 7714.
+    /// AEAD_AES_256_GCM is not supported by SRTP contexts yet.
+    pub fn with_srtp_require_aead(mut self, srtp_require_aead: bool) -> Self {
+        self.srtp_require_aead = srtp_require_aead;
+        self
+    }
+
+    /// build with replay protection window size of incoming SRTP packets, RFC 3711 S3.3.2.
+    /// Zero disables replay protection.
+    pub fn with_srtp_replay_protection_window(
+        mut self,
+        srtp_replay_protection_window: usize,
+    ) -> Self {
+        self.srtp_replay_protection_window = srtp_replay_protection_window;
+        self
+    }
+
+    /// build with replay protection window size of incoming SRTCP packets, RFC 3711 S3.4.
+    /// Zero disables replay protection.
+    pub fn with_srtcp_replay_protection_window(
+        mut self,
+        srtcp_replay_protection_window: usize,
+    ) -> Self {
+        self.srtcp_replay_protection_window = srtcp_replay_protection_window;
+        self
+    }
+
+    /// srtp_protection_profiles returns SRTP protection profiles allowed by the policy in
+    /// preference order, which are offered in DTLS handshake
+    pub fn srtp_protection_profiles(&self) -> Vec<SrtpProtectionProfile> {
+        self.srtp_protection_profiles
+            .iter()
+            .filter(|profile| {
+                !self.srtp_require_aead
+                    || matches!(profile, SrtpProtectionProfile::Srtp_Aead_Aes_128_Gcm)
+            })
+            .copied()
+            .collect()
+    }
+
+    /// dtls_handshake_config builds dtls::config::HandshakeConfig presenting certificate
+    pub(crate) fn dtls_handshake_config(
+        &self,
+        certificate: &RTCCertificate,
+    ) -> Result<Arc<dtls::config::HandshakeConfig>> {
+        Ok(Arc::new(
+            self.dtls_config_builder
+                .clone()
+                .with_certificates(vec![certificate.dtls_certificate.clone()])
+                .with_srtp_protection_profiles(self.srtp_protection_profiles())
+                .with_client_auth(dtls::config::ClientAuthType::RequireAnyClientCert)
+                .build(false, None)?,
+        ))
+    }
+
     /// build with idle timeout
     pub fn with_idle_timeout(mut self, idle_timeout: Duration) -> Self {
         self.idle_timeout = idle_timeout;
         self
     }
+
+    /// build with ICE disconnected timeout, after which a transport without new consent from
+    /// STUN binding request is disconnected
+    pub fn with_ice_disconnected_timeout(mut self, ice_disconnected_timeout: Duration) -> Self {
+        self.ice_disconnected_timeout = ice_disconnected_timeout;
+        self
+    }
+
+    /// build with ICE failed timeout, after which consent of a transport is expired and the
+    /// transport is removed, RFC 7675 S5.1
+    pub fn with_ice_failed_timeout(mut self, ice_failed_timeout: Duration) -> Self {
+        self.ice_failed_timeout = ice_failed_timeout;
+        self
+    }
+
+    /// build with auto subscribe, which makes every endpoint subscribe to all tracks published
+    /// by other endpoints in the same session. Disable it to manage subscriptions by
+    /// ServerStates::subscribe and ServerStates::unsubscribe.
+    pub fn with_auto_subscribe(mut self, auto_subscribe: bool) -> Self {
+        self.auto_subscribe = auto_subscribe;
+        self
+    }
+
+    /// build with keyframe request interval, within which duplicated keyframe requests
+    /// from subscribers are throttled into one request to publisher.
+    pub fn with_keyframe_request_interval(mut self, keyframe_request_interval: Duration) -> Self {
+        self.keyframe_request_interval = keyframe_request_interval;
+        self
+    }
+
+    /// build with emit events, which queues ServerEvent for lifecycle moments.
+    /// Enable it only if the queued events are drained by ServerStates::poll_event.
+    pub fn with_emit_events(mut self, emit_events: bool) -> Self {
+        self.emit_events = emit_events;
+        self
+    }
+
+    /// build with data channel signaling, which sends renegotiation offers to endpoints over
+    /// their signaling data channel. Disable it to carry renegotiation over application's own
+    /// channel by ServerStates::poll_offer and ServerStates::accept_answer.
+    pub fn with_datachannel_signaling(mut self, datachannel_signaling: bool) -> Self {
+        self.datachannel_signaling = datachannel_signaling;
+        self
+    }
+
+    /// build with data channel relay filter, which lets application filter or rewrite messages
+    /// relayed between the same-labeled application data channels of endpoints
+    pub fn with_data_channel_relay_filter(
+        mut self,
+        data_channel_relay_filter: Arc<DataChannelRelayFilter>,
+    ) -> Self {
+        self.data_channel_relay_filter = Some(data_channel_relay_filter);
+        self
+    }
+
+    /// build with host addresses advertised as ICE host candidates in preference order, e.g.
+    /// IPv4 and IPv6 addresses of multiple interfaces, whose sockets should feed the same
+    /// pipeline. By default, only the local_addr of ServerStates is advertised.
+    pub fn with_host_addrs(mut self, host_addrs: Vec<SocketAddr>) -> Self {
+        self.host_addrs = host_addrs;
+        self
+    }
+
+    /// build with NAT 1:1 IP mapping from local IP to public IP, e.g. behind cloud NAT, which
+    /// advertises the public IP in host candidates instead of the local one bound by socket
+    pub fn with_nat_1to1_ips(mut self, nat_1to1_ips: HashMap<IpAddr, IpAddr>) -> Self {
+        self.nat_1to1_ips = nat_1to1_ips;
+        self
+    }
+
+    /// build with local addresses of TCP listeners advertised as passive ICE-TCP candidates.
+    /// TCP connections accepted on them should feed the same pipeline with TcpFramingHandler
+    /// in front of DemuxerHandler, so they must differ from the addresses of UDP sockets.
+    pub fn with_tcp_addrs(mut self, tcp_addrs: Vec<SocketAddr>) -> Self {
+        self.tcp_addrs = tcp_addrs;
+        self
+    }
 }
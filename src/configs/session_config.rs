@@ -5,13 +5,34 @@ use std::sync::Arc;
 pub(crate) struct SessionConfig {
     pub(crate) server_config: Arc<ServerConfig>,
     pub(crate) local_addr: SocketAddr,
+    // addresses advertised as ICE host candidates, mapped by NAT 1:1 IPs
+    pub(crate) candidate_addrs: Vec<SocketAddr>,
+    // addresses advertised as passive ICE-TCP candidates, mapped by NAT 1:1 IPs
+    pub(crate) tcp_candidate_addrs: Vec<SocketAddr>,
 }
 
 impl SessionConfig {
     pub(crate) fn new(server_config: Arc<ServerConfig>, local_addr: SocketAddr) -> Self {
+        let host_addrs = if server_config.host_addrs.is_empty() {
+            vec![local_addr]
+        } else {
+            server_config.host_addrs.clone()
+        };
+        let map_addr = |addr: &SocketAddr| {
+            if let Some(public_ip) = server_config.nat_1to1_ips.get(&addr.ip()) {
+                SocketAddr::new(*public_ip, addr.port())
+            } else {
+                *addr
+            }
+        };
+        let candidate_addrs = host_addrs.iter().map(map_addr).collect();
+        let tcp_candidate_addrs = server_config.tcp_addrs.iter().map(map_addr).collect();
+
         Self {
             server_config,
             local_addr,
+            candidate_addrs,
+            tcp_candidate_addrs,
         }
     }
 }
@@ -15,7 +15,7 @@ use crate::description::{
 };
 use crate::endpoint::candidate::RTCIceParameters;
 use crate::server::certificate::RTCDtlsFingerprint;
-use crate::types::Mid;
+use crate::types::{Mid, Rid};
 use sdp::description::common::{Address, ConnectionInformation};
 use sdp::description::media::{MediaName, RangedPort};
 use sdp::description::session::{
@@ -29,11 +29,14 @@ use serde::{Deserialize, Serialize};
 use shared::error::{Error, Result};
 use std::collections::HashMap;
 use std::io::{BufReader, Cursor};
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 use url::Url;
 
 pub(crate) const UNSPECIFIED_STR: &str = "Unspecified";
+pub(crate) const HOST_CANDIDATE_TYPE_PREFERENCE: u32 = 126;
 pub(crate) const SDP_ATTRIBUTE_RID: &str = "rid";
+pub(crate) const SDES_REPAIR_RTP_STREAM_ID_URI: &str =
+    "urn:ietf:params:rtp-hdrext:sdes:repaired-rtp-stream-id";
 
 /// RTCSessionDescription is used to expose local and remote session descriptions.
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
@@ -119,6 +122,23 @@ pub(crate) fn get_rids(media: &MediaDescription) -> HashMap<String, String> {
     rids
 }
 
+/// get_send_rids returns the rids of the encodings sent by the remote peer, in the offered order
+pub(crate) fn get_send_rids(media: &MediaDescription) -> Vec<Rid> {
+    let mut rids = vec![];
+    for attr in &media.attributes {
+        if attr.key.as_str() == SDP_ATTRIBUTE_RID {
+            if let Some(value) = &attr.value {
+                let split: Vec<&str> = value.split(' ').collect();
+                if split.len() >= 2 && split[1] == "send" && !rids.iter().any(|rid| rid == split[0])
+                {
+                    rids.push(split[0].to_owned());
+                }
+            }
+        }
+    }
+    rids
+}
+
 /// ICEGatheringState describes the state of the candidate gathering process.
 #[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
 pub enum RTCIceGatheringState {
@@ -139,12 +159,7 @@ pub enum RTCIceGatheringState {
     Complete,
 }
 
-fn append_candidate_if_new(
-    c: &SocketAddr,
-    component: u16,
-    m: MediaDescription,
-) -> MediaDescription {
-    let marshaled = format!("1 {} UDP 1 {} {} typ host", component, c.ip(), c.port());
+fn append_candidate_if_new(marshaled: String, m: MediaDescription) -> MediaDescription {
     for a in &m.attributes {
         if let Some(value) = &a.value {
             if &marshaled == value {
@@ -155,14 +170,64 @@ fn append_candidate_if_new(
     m.with_value_attribute("candidate".to_owned(), marshaled)
 }
 
+/// candidate_priority computes priority of host candidate, RFC 8445 S5.1.2.1
+fn candidate_priority(local_preference: u16, component: u16) -> u32 {
+    (HOST_CANDIDATE_TYPE_PREFERENCE << 24)
+        + ((local_preference as u32) << 8)
+        + (256 - component as u32)
+}
+
 pub(crate) fn add_candidate_to_media_descriptions(
-    candidate: &SocketAddr,
+    candidates: &[SocketAddr],
+    tcp_candidates: &[SocketAddr],
     mut m: MediaDescription,
     ice_gathering_state: RTCIceGatheringState,
 ) -> Result<MediaDescription> {
-    m = append_candidate_if_new(candidate, 1, m); // 1: RTP
+    // candidates share foundation only if they have the same base IP and protocol,
+    // RFC 8445 S5.1.1.3
+    let foundations: Vec<(&str, IpAddr)> = candidates
+        .iter()
+        .map(|c| ("UDP", c.ip()))
+        .chain(tcp_candidates.iter().map(|c| ("TCP", c.ip())))
+        .collect();
+    let foundation = |protocol: &str, c: &SocketAddr| {
+        foundations
+            .iter()
+            .position(|&(p, ip)| p == protocol && ip == c.ip())
+            .unwrap_or_default()
+            + 1
+    };
 
-    //TODO: m = append_candidate_if_new(candidate, 2, m); // 2: RTCP
+    // RTCP is multiplexed with RTP, so component 2 is not needed
+    for (i, c) in candidates.iter().enumerate() {
+        // earlier candidates are preferred, RFC 8421
+        let local_preference = u16::MAX.saturating_sub(i as u16);
+        m = append_candidate_if_new(
+            format!(
+                "{} 1 UDP {} {} {} typ host",
+                foundation("UDP", c),
+                candidate_priority(local_preference, 1),
+                c.ip(),
+                c.port()
+            ),
+            m,
+        );
+    }
+    for (i, c) in tcp_candidates.iter().enumerate() {
+        // direction preference of passive host candidate is 4, RFC 6544 S4.2,
+        // which keeps TCP candidates less preferred than UDP ones
+        let local_preference = (4 << 13) + 8191u16.saturating_sub(i as u16);
+        m = append_candidate_if_new(
+            format!(
+                "{} 1 TCP {} {} {} typ host tcptype passive",
+                foundation("TCP", c),
+                candidate_priority(local_preference, 1),
+                c.ip(),
+                c.port()
+            ),
+            m,
+        );
+    }
 
     if ice_gathering_state != RTCIceGatheringState::Complete {
         return Ok(m);
@@ -249,7 +314,8 @@ pub(crate) fn add_data_media_section(
 
     if params.should_add_candidates {
         media = add_candidate_to_media_descriptions(
-            &session_config.local_addr,
+            &session_config.candidate_addrs,
+            &session_config.tcp_candidate_addrs,
             media,
             params.ice_gathering_state,
         )?;
@@ -302,7 +368,8 @@ pub(crate) fn add_transceiver_sdp(
 
     if should_add_candidates {
         media = add_candidate_to_media_descriptions(
-            &session_config.local_addr,
+            &session_config.candidate_addrs,
+            &session_config.tcp_candidate_addrs,
             media,
             ice_gathering_state,
         )?;
@@ -343,9 +410,23 @@ pub(crate) fn add_transceiver_sdp(
         .media_config
         .get_rtp_parameters_by_kind(transceiver.kind, transceiver.direction);
     for rtp_extension in parameters.header_extensions {
+        let mut id = rtp_extension.id;
+        if rtp_extension.uri == sdp::extmap::TRANSPORT_CC_URI {
+            // keep the transport-cc id chosen by the remote peer, since TWCC feedback is built
+            // from the transport-wide sequence numbers carried with that id in its packets
+            let Some(remote_extension) = transceiver
+                .rtp_params
+                .header_extensions
+                .iter()
+                .find(|e| e.uri == rtp_extension.uri)
+            else {
+                continue;
+            };
+            id = remote_extension.id;
+        }
         let ext_url = Url::parse(rtp_extension.uri.as_str())?;
         media = media.with_extmap(ExtMap {
-            value: rtp_extension.id,
+            value: id,
             uri: Some(ext_url),
             ..Default::default()
         });
@@ -640,7 +721,7 @@ pub(crate) fn get_ssrcs(media: &MediaDescription) -> Result<Vec<SSRC>> {
                 let fields: Vec<&str> = value.split_whitespace().collect();
                 if !fields.is_empty() {
                     let ssrc = fields[0].parse::<u32>()?;
-                    if !ssrcs.iter().any(|&s| s == ssrc) {
+                    if !ssrcs.contains(&ssrc) {
                         ssrcs.push(ssrc);
                     };
                 }
@@ -650,16 +731,24 @@ pub(crate) fn get_ssrcs(media: &MediaDescription) -> Result<Vec<SSRC>> {
     Ok(ssrcs)
 }
 
-pub(crate) fn extract_fingerprint(desc: &SessionDescription) -> Result<(String, String)> {
-    let mut fingerprints = vec![];
-
-    if let Some(fingerprint) = desc.attribute("fingerprint") {
-        fingerprints.push(fingerprint.to_string());
-    }
+/// extract_fingerprint returns distinct session and media level fingerprints of desc, whose hash
+/// function algorithms are supported to verify remote DTLS certificate
+pub(crate) fn extract_fingerprint(desc: &SessionDescription) -> Result<Vec<RTCDtlsFingerprint>> {
+    let mut fingerprints: Vec<RTCDtlsFingerprint> = vec![];
 
-    for m in &desc.media_descriptions {
-        if let Some(fingerprint) = m.attribute("fingerprint").and_then(|o| o) {
-            fingerprints.push(fingerprint.to_string());
+    let attributes = desc.attributes.iter().chain(
+        desc.media_descriptions
+            .iter()
+            .flat_map(|m| m.attributes.iter()),
+    );
+    for fingerprint in attributes
+        .filter(|a| a.key == "fingerprint")
+        .filter_map(|a| a.value.as_deref())
+    {
+        let fingerprint = RTCDtlsFingerprint::try_from(fingerprint)
+            .map_err(|_| Error::Other("ErrSessionDescriptionInvalidFingerprint".to_string()))?;
+        if !fingerprints.contains(&fingerprint) {
+            fingerprints.push(fingerprint);
         }
     }
 
@@ -669,22 +758,14 @@ pub(crate) fn extract_fingerprint(desc: &SessionDescription) -> Result<(String,
         ));
     }
 
-    for m in 1..fingerprints.len() {
-        if fingerprints[m] != fingerprints[0] {
-            return Err(Error::Other(
-                "ErrSessionDescriptionConflictingFingerprints".to_string(),
-            ));
-        }
-    }
-
-    let parts: Vec<&str> = fingerprints[0].split(' ').collect();
-    if parts.len() != 2 {
+    fingerprints.retain(|fingerprint| fingerprint.is_supported());
+    if fingerprints.is_empty() {
         return Err(Error::Other(
-            "ErrSessionDescriptionInvalidFingerprint".to_string(),
+            "ErrSessionDescriptionUnsupportedFingerprint".to_string(),
         ));
     }
 
-    Ok((parts[1].to_owned(), parts[0].to_owned()))
+    Ok(fingerprints)
 }
 
 /*
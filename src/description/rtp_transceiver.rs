@@ -2,6 +2,7 @@ use crate::description::{
     rtp_codec::{RTCRtpParameters, RTPCodecType},
     rtp_transceiver_direction::RTCRtpTransceiverDirection,
 };
+use crate::types::Rid;
 
 /// SSRC represents a synchronization source
 /// A synchronization source is a randomly chosen
@@ -77,6 +78,8 @@ pub struct RTCRtpTransceiver {
     pub(crate) current_direction: RTCRtpTransceiverDirection,
 
     pub(crate) rtp_params: RTCRtpParameters,
+    // rids of simulcast encodings sent by the remote peer
+    pub(crate) rids: Vec<Rid>,
 
     pub(crate) kind: RTPCodecType,
 }
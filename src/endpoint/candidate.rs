@@ -1,4 +1,4 @@
-use crate::description::{RTCSessionDescription, UNSPECIFIED_STR};
+use crate::description::{extract_fingerprint, RTCSessionDescription, UNSPECIFIED_STR};
 use crate::server::certificate::RTCDtlsFingerprint;
 use crate::types::{EndpointId, SessionId, UserName};
 use base64::{prelude::BASE64_STANDARD, Engine};
@@ -100,6 +100,23 @@ pub(crate) struct RTCIceParameters {
     pub(crate) password: String,
 }
 
+impl RTCIceParameters {
+    /// generate creates random ICE username fragment and password
+    fn generate() -> Self {
+        let rng = SystemRandom::new();
+
+        let mut user = [0u8; 9];
+        let _ = rng.fill(&mut user);
+        let mut password = [0u8; 18];
+        let _ = rng.fill(&mut password);
+
+        Self {
+            username_fragment: BASE64_STANDARD.encode(&user[..]),
+            password: BASE64_STANDARD.encode(&password[..]),
+        }
+    }
+}
+
 /// DTLSParameters holds information relating to DTLS configuration.
 #[derive(Default, Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub(crate) struct DTLSParameters {
@@ -115,18 +132,8 @@ pub(crate) struct ConnectionCredentials {
 
 impl ConnectionCredentials {
     pub(crate) fn new(fingerprints: Vec<RTCDtlsFingerprint>, remote_role: DTLSRole) -> Self {
-        let rng = SystemRandom::new();
-
-        let mut user = [0u8; 9];
-        let _ = rng.fill(&mut user);
-        let mut password = [0u8; 18];
-        let _ = rng.fill(&mut password);
-
         Self {
-            ice_params: RTCIceParameters {
-                username_fragment: BASE64_STANDARD.encode(&user[..]),
-                password: BASE64_STANDARD.encode(&password[..]),
-            },
+            ice_params: RTCIceParameters::generate(),
             dtls_params: DTLSParameters {
                 fingerprints,
                 role: if remote_role == DTLSRole::Server {
@@ -138,6 +145,15 @@ impl ConnectionCredentials {
         }
     }
 
+    /// restart_ice creates credentials with new ICE parameters for ICE restart, while DTLS
+    /// parameters are kept since DTLS association survives it
+    pub(crate) fn restart_ice(&self) -> Self {
+        Self {
+            ice_params: RTCIceParameters::generate(),
+            dtls_params: self.dtls_params.clone(),
+        }
+    }
+
     pub(crate) fn from_sdp(sdp: &SessionDescription) -> Result<Self> {
         let username_fragment = sdp
             .media_descriptions
@@ -153,16 +169,7 @@ impl ConnectionCredentials {
             .ok_or(Error::ErrAttributeNotFound)?
             .ok_or(Error::ErrAttributeNotFound)?
             .to_string();
-        let fingerprint = if let Some(fingerprint) = sdp.attribute("fingerprint") {
-            fingerprint.try_into()?
-        } else {
-            sdp.media_descriptions
-                .iter()
-                .find_map(|m| m.attribute("fingerprint"))
-                .ok_or(Error::ErrAttributeNotFound)?
-                .ok_or(Error::ErrAttributeNotFound)?
-                .try_into()?
-        };
+        let fingerprints = extract_fingerprint(sdp)?;
         let role = DTLSRole::from(sdp);
 
         Ok(Self {
@@ -170,10 +177,7 @@ impl ConnectionCredentials {
                 username_fragment,
                 password,
             },
-            dtls_params: DTLSParameters {
-                role,
-                fingerprints: vec![fingerprint],
-            },
+            dtls_params: DTLSParameters { role, fingerprints },
         })
     }
 
@@ -0,0 +1,246 @@
+use super::Endpoint;
+use crate::configs::media_config::MIME_TYPE_VP8;
+use crate::description::rtp_codec::{
+    RTCRtpCodecCapability, RTCRtpCodecParameters, RTCRtpHeaderExtensionParameters,
+    RTCRtpParameters, RTPCodecType,
+};
+use crate::description::rtp_transceiver::RTCRtpTransceiver;
+use crate::description::rtp_transceiver_direction::RTCRtpTransceiverDirection;
+use crate::description::SDES_REPAIR_RTP_STREAM_ID_URI;
+use crate::interceptors::Registry;
+use bytes::Bytes;
+use rtcp::payload_feedbacks::full_intra_request::FullIntraRequest;
+use rtcp::payload_feedbacks::picture_loss_indication::PictureLossIndication;
+use std::time::{Duration, Instant};
+
+const MID_EXT_ID: u8 = 1;
+const RID_EXT_ID: u8 = 2;
+const RRID_EXT_ID: u8 = 3;
+
+fn simulcast_endpoint() -> Endpoint {
+    let mut endpoint = Endpoint::new(1, Registry::new().build("test"));
+    let ext = |uri: &str, id: u8| RTCRtpHeaderExtensionParameters {
+        uri: uri.to_string(),
+        id: id as isize,
+    };
+    let codec = |mime_type: &str, payload_type: u8, sdp_fmtp_line: &str| RTCRtpCodecParameters {
+        capability: RTCRtpCodecCapability {
+            mime_type: mime_type.to_string(),
+            clock_rate: 90000,
+            sdp_fmtp_line: sdp_fmtp_line.to_string(),
+            ..Default::default()
+        },
+        payload_type,
+        ..Default::default()
+    };
+    endpoint.get_mut_transceivers().insert(
+        "0".to_string(),
+        RTCRtpTransceiver {
+            mid: "0".to_string(),
+            sender: None,
+            direction: RTCRtpTransceiverDirection::Recvonly,
+            current_direction: RTCRtpTransceiverDirection::Unspecified,
+            rtp_params: RTCRtpParameters {
+                header_extensions: vec![
+                    ext(sdp::extmap::SDES_MID_URI, MID_EXT_ID),
+                    ext(sdp::extmap::SDES_RTP_STREAM_ID_URI, RID_EXT_ID),
+                    ext(SDES_REPAIR_RTP_STREAM_ID_URI, RRID_EXT_ID),
+                ],
+                codecs: vec![
+                    codec(MIME_TYPE_VP8, 96, ""),
+                    codec("video/rtx", 97, "apt=96"),
+                ],
+            },
+            rids: vec!["q".to_string(), "h".to_string()],
+            kind: RTPCodecType::Video,
+        },
+    );
+    endpoint
+}
+
+fn packet(
+    ssrc: u32,
+    payload_type: u8,
+    extensions: &[(u8, &str)],
+    payload: &[u8],
+) -> rtp::packet::Packet {
+    let mut packet = rtp::packet::Packet::default();
+    packet.header.ssrc = ssrc;
+    packet.header.payload_type = payload_type;
+    for (id, value) in extensions {
+        packet
+            .header
+            .set_extension(*id, Bytes::copy_from_slice(value.as_bytes()))
+            .unwrap();
+    }
+    packet.payload = Bytes::copy_from_slice(payload);
+    packet
+}
+
+#[test]
+fn test_bind_simulcast_ssrc() {
+    let mut endpoint = simulcast_endpoint();
+
+    assert!(endpoint.bind_simulcast_ssrc(&packet(
+        100,
+        96,
+        &[(MID_EXT_ID, "0"), (RID_EXT_ID, "q")],
+        &[]
+    )));
+    assert_eq!(endpoint.get_mid_by_ssrc(100), Some(&"0".to_string()));
+    assert_eq!(endpoint.get_rid_by_ssrc(100), Some(&"q".to_string()));
+    assert_eq!(
+        endpoint.get_ssrc_by_rid(&"0".to_string(), &"q".to_string()),
+        Some(100)
+    );
+    assert!(!endpoint.is_simulcast_rtx_ssrc(100));
+
+    // unknown rid and mid
+    assert!(!endpoint.bind_simulcast_ssrc(&packet(
+        101,
+        96,
+        &[(MID_EXT_ID, "0"), (RID_EXT_ID, "f")],
+        &[]
+    )));
+    assert!(!endpoint.bind_simulcast_ssrc(&packet(
+        102,
+        96,
+        &[(MID_EXT_ID, "1"), (RID_EXT_ID, "q")],
+        &[]
+    )));
+    assert!(!endpoint.bind_simulcast_ssrc(&packet(103, 96, &[], &[])));
+}
+
+#[test]
+fn test_bind_and_unwrap_simulcast_rtx() {
+    let mut endpoint = simulcast_endpoint();
+    let rtx_packet = packet(
+        200,
+        97,
+        &[(MID_EXT_ID, "0"), (RRID_EXT_ID, "q")],
+        &[0x12, 0x34, 0xaa, 0xbb],
+    );
+
+    assert!(endpoint.bind_simulcast_ssrc(&rtx_packet));
+    assert!(endpoint.is_simulcast_rtx_ssrc(200));
+    assert_eq!(endpoint.get_mid_by_ssrc(200), Some(&"0".to_string()));
+    assert!(endpoint.get_rid_by_ssrc(200).is_none());
+    // the layer's ssrc is not bound yet
+    assert!(endpoint.unwrap_simulcast_rtx(&rtx_packet).is_none());
+
+    assert!(endpoint.bind_simulcast_ssrc(&packet(
+        100,
+        96,
+        &[(MID_EXT_ID, "0"), (RID_EXT_ID, "q")],
+        &[]
+    )));
+    let unwrapped = endpoint.unwrap_simulcast_rtx(&rtx_packet).unwrap();
+    assert_eq!(unwrapped.header.ssrc, 100);
+    assert_eq!(unwrapped.header.payload_type, 96);
+    assert_eq!(unwrapped.header.sequence_number, 0x1234);
+    assert_eq!(&unwrapped.payload[..], &[0xaa, 0xbb]);
+
+    // padding only probes have no original sequence number
+    assert!(endpoint
+        .unwrap_simulcast_rtx(&packet(200, 97, &[], &[]))
+        .is_none());
+}
+
+#[test]
+fn test_request_keyframe_throttled() {
+    let mut endpoint = simulcast_endpoint();
+    let now = Instant::now();
+    let interval = Duration::from_millis(500);
+
+    let pli = endpoint
+        .request_keyframe(100, 1, false, now, interval)
+        .unwrap();
+    let pli = pli
+        .as_any()
+        .downcast_ref::<PictureLossIndication>()
+        .unwrap();
+    assert_eq!((pli.sender_ssrc, pli.media_ssrc), (1, 100));
+
+    assert!(endpoint
+        .request_keyframe(100, 1, false, now + Duration::from_millis(100), interval)
+        .is_none());
+    // other ssrcs are throttled separately
+    assert!(endpoint
+        .request_keyframe(101, 1, false, now + Duration::from_millis(100), interval)
+        .is_some());
+
+    let fir = endpoint
+        .request_keyframe(100, 1, true, now + interval, interval)
+        .unwrap();
+    let fir = fir.as_any().downcast_ref::<FullIntraRequest>().unwrap();
+    assert_eq!(fir.fir.len(), 1);
+    assert_eq!(fir.fir[0].ssrc, 100);
+    assert_eq!(fir.fir[0].sequence_number, 1);
+    let fir = endpoint
+        .request_keyframe(100, 1, true, now + interval * 2, interval)
+        .unwrap();
+    let fir = fir.as_any().downcast_ref::<FullIntraRequest>().unwrap();
+    assert_eq!(fir.fir[0].sequence_number, 2);
+}
+
+#[test]
+fn test_deferred_keyframe_request() {
+    let mut endpoint = simulcast_endpoint();
+    let now = Instant::now();
+    let interval = Duration::from_millis(500);
+
+    assert!(endpoint
+        .request_keyframe(100, 1, false, now, interval)
+        .is_some());
+    assert!(endpoint
+        .next_deferred_keyframe_request_timeout(interval)
+        .is_none());
+
+    // throttled requests are merged into a single trailing one, FIR wins over PLI
+    assert!(endpoint
+        .request_keyframe(100, 1, false, now + Duration::from_millis(100), interval)
+        .is_none());
+    assert!(endpoint
+        .request_keyframe(100, 2, true, now + Duration::from_millis(200), interval)
+        .is_none());
+    assert_eq!(
+        endpoint.next_deferred_keyframe_request_timeout(interval),
+        Some(now + interval)
+    );
+
+    assert!(endpoint
+        .poll_deferred_keyframe_requests(now + Duration::from_millis(400), interval)
+        .is_empty());
+    let rtcp_packets = endpoint.poll_deferred_keyframe_requests(now + interval, interval);
+    assert_eq!(rtcp_packets.len(), 1);
+    let fir = rtcp_packets[0]
+        .as_any()
+        .downcast_ref::<FullIntraRequest>()
+        .unwrap();
+    assert_eq!((fir.sender_ssrc, fir.fir[0].ssrc), (1, 100));
+
+    assert!(endpoint
+        .poll_deferred_keyframe_requests(now + interval * 2, interval)
+        .is_empty());
+    assert!(endpoint
+        .next_deferred_keyframe_request_timeout(interval)
+        .is_none());
+}
+
+#[test]
+fn test_renegotiation_waits_for_pending_offer() {
+    let mut endpoint = Endpoint::new(1, Registry::new().build("test"));
+    assert!(!endpoint.can_renegotiate());
+
+    endpoint.set_renegotiation_needed(true);
+    assert!(endpoint.can_renegotiate());
+
+    // offer sent, tracks change again before the answer arrives
+    endpoint.set_offer_pending(true);
+    assert!(endpoint.is_renegotiation_needed());
+    assert!(!endpoint.can_renegotiate());
+
+    // answer accepted
+    endpoint.set_offer_pending(false);
+    assert!(endpoint.can_renegotiate());
+}
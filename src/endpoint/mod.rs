@@ -1,17 +1,62 @@
 pub(crate) mod candidate;
+pub(crate) mod rtp_rewriter;
 pub(crate) mod transport;
 
-use crate::description::{rtp_transceiver::RTCRtpTransceiver, RTCSessionDescription};
-use crate::endpoint::transport::Transport;
-use crate::interceptors::Interceptor;
-use crate::types::{EndpointId, FourTuple, Mid};
-use std::collections::HashMap;
+use crate::description::{
+    fmtp,
+    rtp_codec::RTCRtpCodecParameters,
+    rtp_transceiver::{PayloadType, RTCRtpTransceiver, SsrcGroup, SSRC},
+    rtp_transceiver_direction::RTCRtpTransceiverDirection,
+    RTCSessionDescription, SDES_REPAIR_RTP_STREAM_ID_URI,
+};
+use crate::endpoint::{
+    candidate::{Candidate, ConnectionCredentials},
+    rtp_rewriter::RtpRewriter,
+    transport::Transport,
+};
+use crate::interceptors::{stream_info::StreamInfo, Interceptor};
+use crate::types::{EndpointId, FourTuple, Mid, Rid};
+use rtcp::payload_feedbacks::{
+    full_intra_request::{FirEntry, FullIntraRequest},
+    picture_loss_indication::PictureLossIndication,
+};
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// IceRestart tracks ICE restart of endpoint until a path with restarted credentials is nominated,
+/// together with the previous candidate whose transport migrates to that path
+pub(crate) enum IceRestart {
+    /// local credentials offered by SFU, waiting for answer with remote credentials
+    Offered(ConnectionCredentials, Rc<Candidate>),
+    /// candidate with restarted credentials, waiting for STUN binding with USE-CANDIDATE
+    Pending(Rc<Candidate>, Rc<Candidate>),
+}
+
+impl IceRestart {
+    pub(crate) fn local_connection_credentials(&self) -> &ConnectionCredentials {
+        match self {
+            IceRestart::Offered(local_conn_cred, _) => local_conn_cred,
+            IceRestart::Pending(candidate, _) => candidate.local_connection_credentials(),
+        }
+    }
+
+    pub(crate) fn previous_candidate(&self) -> &Rc<Candidate> {
+        match self {
+            IceRestart::Offered(_, previous_candidate)
+            | IceRestart::Pending(_, previous_candidate) => previous_candidate,
+        }
+    }
+}
 
 pub(crate) struct Endpoint {
     endpoint_id: EndpointId,
     interceptor: Box<dyn Interceptor>,
 
     is_renegotiation_needed: bool,
+    // whether a local offer is sent and its answer is not accepted yet
+    is_offer_pending: bool,
+    ice_restart: Option<IceRestart>,
     remote_description: Option<RTCSessionDescription>,
     local_description: Option<RTCSessionDescription>,
 
@@ -19,6 +64,19 @@ pub(crate) struct Endpoint {
 
     mids: Vec<Mid>,
     transceivers: HashMap<Mid, RTCRtpTransceiver>,
+    rtp_rewriters: HashMap<Mid, RtpRewriter>,
+    // ssrcs of simulcast encodings, bound by the rid header extension
+    simulcast_ssrcs: HashMap<SSRC, (Mid, Rid)>,
+    // ssrcs of simulcast encodings' RTX streams, bound by the repaired rid header extension
+    simulcast_rtx_ssrcs: HashMap<SSRC, (Mid, Rid)>,
+    // last time a keyframe request was sent for the ssrc published by this endpoint
+    keyframe_requests: HashMap<SSRC, Instant>,
+    // sender ssrc and whether FIR is requested, of the keyframe requests throttled for the ssrc,
+    // which are sent once when the interval ends
+    deferred_keyframe_requests: HashMap<SSRC, (SSRC, bool)>,
+    fir_sequence_numbers: HashMap<SSRC, u8>,
+    // mids of published tracks muted by this endpoint, which are not forwarded
+    muted_mids: HashSet<Mid>,
 }
 
 impl Endpoint {
@@ -28,6 +86,8 @@ impl Endpoint {
             interceptor,
 
             is_renegotiation_needed: false,
+            is_offer_pending: false,
+            ice_restart: None,
             remote_description: None,
             local_description: None,
 
@@ -35,6 +95,13 @@ impl Endpoint {
 
             mids: vec![],
             transceivers: HashMap::new(),
+            rtp_rewriters: HashMap::new(),
+            simulcast_ssrcs: HashMap::new(),
+            simulcast_rtx_ssrcs: HashMap::new(),
+            keyframe_requests: HashMap::new(),
+            deferred_keyframe_requests: HashMap::new(),
+            fir_sequence_numbers: HashMap::new(),
+            muted_mids: HashSet::new(),
         }
     }
 
@@ -54,6 +121,36 @@ impl Endpoint {
         self.transports.contains_key(four_tuple)
     }
 
+    /// find_migrated_transport returns the four_tuple of the transport which migrates to the
+    /// path nominated with candidate, i.e. the transport bound to candidate, or to the previous
+    /// candidate when candidate of ICE restart is nominated, unless it is already selected there
+    pub(crate) fn find_migrated_transport(
+        &self,
+        four_tuple: &FourTuple,
+        candidate: &Rc<Candidate>,
+    ) -> Option<FourTuple> {
+        let bound_candidate = match &self.ice_restart {
+            Some(IceRestart::Pending(pending, previous_candidate))
+                if Rc::ptr_eq(pending, candidate) =>
+            {
+                previous_candidate
+            }
+            _ => candidate,
+        };
+        let selected_four_tuple = self
+            .transports
+            .iter()
+            .find(|(_, transport)| Rc::ptr_eq(transport.candidate(), bound_candidate))
+            .map(|(four_tuple, _)| *four_tuple)?;
+        if selected_four_tuple == *four_tuple {
+            // candidate of ICE restart is nominated on the selected path
+            (!Rc::ptr_eq(bound_candidate, candidate)).then_some(selected_four_tuple)
+        } else {
+            // another transport on the nominated path is kept
+            (!self.transports.contains_key(four_tuple)).then_some(selected_four_tuple)
+        }
+    }
+
     pub(crate) fn get_transports(&self) -> &HashMap<FourTuple, Transport> {
         &self.transports
     }
@@ -82,6 +179,482 @@ impl Endpoint {
         &mut self.transceivers
     }
 
+    /// get_mid_by_ssrc finds the mid of the track published by this endpoint with ssrc
+    pub(crate) fn get_mid_by_ssrc(&self, ssrc: SSRC) -> Option<&Mid> {
+        if let Some((mid, _)) = self
+            .simulcast_ssrcs
+            .get(&ssrc)
+            .or_else(|| self.simulcast_rtx_ssrcs.get(&ssrc))
+        {
+            return Some(mid);
+        }
+        self.transceivers.iter().find_map(|(mid, transceiver)| {
+            if transceiver.direction == RTCRtpTransceiverDirection::Recvonly
+                && transceiver
+                    .sender
+                    .as_ref()
+                    .is_some_and(|sender| sender.ssrcs.contains(&ssrc))
+            {
+                Some(mid)
+            } else {
+                None
+            }
+        })
+    }
+
+    /// get_rid_by_ssrc finds the rid of the simulcast encoding published by this endpoint with ssrc
+    pub(crate) fn get_rid_by_ssrc(&self, ssrc: SSRC) -> Option<&Rid> {
+        self.simulcast_ssrcs.get(&ssrc).map(|(_, rid)| rid)
+    }
+
+    /// get_ssrc_by_rid finds the ssrc of the simulcast encoding published by this endpoint with mid and rid
+    pub(crate) fn get_ssrc_by_rid(&self, mid: &Mid, rid: &Rid) -> Option<SSRC> {
+        self.simulcast_ssrcs
+            .iter()
+            .find_map(|(ssrc, (m, r))| (m == mid && r == rid).then_some(*ssrc))
+    }
+
+    /// bind_simulcast_ssrc binds the unknown ssrc of rtp_packet to a simulcast encoding, or to
+    /// its RTX stream, by its mid and rid or repaired rid header extensions, returns false if it
+    /// can't be bound.
+    pub(crate) fn bind_simulcast_ssrc(&mut self, rtp_packet: &rtp::packet::Packet) -> bool {
+        let header = &rtp_packet.header;
+        if self.simulcast_ssrcs.contains_key(&header.ssrc)
+            || self.simulcast_rtx_ssrcs.contains_key(&header.ssrc)
+        {
+            return true;
+        }
+
+        let get_extension_value = |transceiver: &RTCRtpTransceiver, uri: &str| {
+            let ext = transceiver
+                .rtp_params
+                .header_extensions
+                .iter()
+                .find(|ext| ext.uri == uri)?;
+            let payload = header.get_extension(ext.id as u8)?;
+            String::from_utf8(payload.to_vec()).ok()
+        };
+
+        let binding = self.transceivers.iter().find_map(|(mid, transceiver)| {
+            if transceiver.direction != RTCRtpTransceiverDirection::Recvonly
+                || transceiver.rids.is_empty()
+                || get_extension_value(transceiver, sdp::extmap::SDES_MID_URI).as_ref() != Some(mid)
+            {
+                return None;
+            }
+            let (rid, is_rtx) =
+                match get_extension_value(transceiver, sdp::extmap::SDES_RTP_STREAM_ID_URI) {
+                    Some(rid) => (rid, false),
+                    None => (
+                        get_extension_value(transceiver, SDES_REPAIR_RTP_STREAM_ID_URI)?,
+                        true,
+                    ),
+                };
+            transceiver
+                .rids
+                .contains(&rid)
+                .then(|| (mid.clone(), rid, is_rtx))
+        });
+
+        match binding {
+            Some((mid, rid, false)) => {
+                self.simulcast_ssrcs.insert(header.ssrc, (mid, rid));
+                true
+            }
+            Some((mid, rid, true)) => {
+                self.simulcast_rtx_ssrcs.insert(header.ssrc, (mid, rid));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// is_simulcast_rtx_ssrc checks whether ssrc is bound to the RTX stream of a simulcast encoding
+    pub(crate) fn is_simulcast_rtx_ssrc(&self, ssrc: SSRC) -> bool {
+        self.simulcast_rtx_ssrcs.contains_key(&ssrc)
+    }
+
+    /// unwrap_simulcast_rtx restores the original packet of a simulcast encoding from rtp_packet
+    /// of its RTX stream, RFC 4588 section 4, returns None if rtp_packet isn't from such RTX stream,
+    /// the encoding's ssrc is not bound yet, or it is a padding only packet.
+    pub(crate) fn unwrap_simulcast_rtx(
+        &self,
+        rtp_packet: &rtp::packet::Packet,
+    ) -> Option<rtp::packet::Packet> {
+        let (mid, rid) = self.simulcast_rtx_ssrcs.get(&rtp_packet.header.ssrc)?;
+        let ssrc = self.get_ssrc_by_rid(mid, rid)?;
+        if rtp_packet.payload.len() < 2 {
+            return None;
+        }
+        let rtx_codec = self
+            .transceivers
+            .get(mid)?
+            .rtp_params
+            .codecs
+            .iter()
+            .find(|codec| codec.payload_type == rtp_packet.header.payload_type)?;
+        let payload_type = fmtp::parse(
+            &rtx_codec.capability.mime_type,
+            &rtx_codec.capability.sdp_fmtp_line,
+        )
+        .parameter("apt")?
+        .parse::<PayloadType>()
+        .ok()?;
+
+        let mut rtp_packet = rtp_packet.clone();
+        rtp_packet.header.ssrc = ssrc;
+        rtp_packet.header.payload_type = payload_type;
+        rtp_packet.header.sequence_number =
+            u16::from_be_bytes([rtp_packet.payload[0], rtp_packet.payload[1]]);
+        rtp_packet.payload = rtp_packet.payload.slice(2..);
+        Some(rtp_packet)
+    }
+
+    /// add_forwarding_transceiver adds a send only transceiver to forward the track published by
+    /// publisher_id, whose ssrcs are remapped to ones unique in this endpoint, and returns its mid.
+    pub(crate) fn add_forwarding_transceiver(
+        &mut self,
+        publisher_id: EndpointId,
+        publisher_transceiver: &RTCRtpTransceiver,
+        codecs: &[RTCRtpCodecParameters],
+    ) -> Mid {
+        let mid = format!("{}-{}", publisher_id, publisher_transceiver.mid);
+
+        let mut ssrcs = HashMap::new();
+        let mut transceiver = publisher_transceiver.clone();
+        transceiver.mid = mid.clone();
+        transceiver.direction = RTCRtpTransceiverDirection::Sendonly;
+        transceiver.current_direction = RTCRtpTransceiverDirection::Unspecified;
+        transceiver.rids = vec![];
+        let mut simulcast_ssrc = None;
+        if let Some(sender) = transceiver.sender.as_mut() {
+            if publisher_transceiver.rids.is_empty() {
+                for ssrc in sender.ssrcs.iter_mut() {
+                    let local_ssrc = self.generate_ssrc(&ssrcs);
+                    ssrcs.insert(*ssrc, local_ssrc);
+                    *ssrc = local_ssrc;
+                }
+                sender.ssrc_groups = sender
+                    .ssrc_groups
+                    .iter()
+                    .map(|ssrc_group| SsrcGroup {
+                        name: ssrc_group.name.clone(),
+                        ssrcs: ssrc_group
+                            .ssrcs
+                            .iter()
+                            .filter_map(|ssrc| ssrcs.get(ssrc).copied())
+                            .collect(),
+                    })
+                    .collect();
+            } else {
+                // all simulcast layers are forwarded to a single ssrc
+                let local_ssrc = self.generate_ssrc(&ssrcs);
+                sender.ssrcs = vec![local_ssrc];
+                sender.ssrc_groups = vec![];
+                simulcast_ssrc = Some(local_ssrc);
+            }
+        }
+
+        let mut rtp_rewriter = RtpRewriter::new(
+            publisher_id,
+            publisher_transceiver.mid.clone(),
+            ssrcs,
+            &publisher_transceiver.rtp_params,
+            codecs,
+        );
+        if let (Some(ssrc), Some(rid)) = (simulcast_ssrc, publisher_transceiver.rids.first()) {
+            rtp_rewriter = rtp_rewriter.with_simulcast(ssrc, rid.clone());
+        }
+        self.rtp_rewriters.insert(mid.clone(), rtp_rewriter);
+        if let Some(sender) = transceiver.sender.as_ref() {
+            self.interceptor.bind_local_stream(&StreamInfo {
+                mid: mid.clone(),
+                ssrcs: sender.ssrcs.clone(),
+                rtp_header_extensions: transceiver.rtp_params.header_extensions.clone(),
+                clock_rate: publisher_transceiver
+                    .rtp_params
+                    .codecs
+                    .first()
+                    .map(|codec| codec.capability.clock_rate)
+                    .unwrap_or_default(),
+            });
+        }
+        self.mids.push(mid.clone());
+        self.transceivers.insert(mid.clone(), transceiver);
+
+        mid
+    }
+
+    /// update_forwarding_transceiver rebinds the transceiver forwarding the track of publisher_id
+    /// to the ssrcs renegotiated by publisher, returns true if its ssrcs offered to this endpoint
+    /// are changed, so that renegotiation is needed.
+    pub(crate) fn update_forwarding_transceiver(
+        &mut self,
+        publisher_id: EndpointId,
+        publisher_transceiver: &RTCRtpTransceiver,
+    ) -> bool {
+        let mid = format!("{}-{}", publisher_id, publisher_transceiver.mid);
+        let Some(previous_ssrcs) = self.rtp_rewriters.get(&mid).map(|r| r.ssrcs().clone()) else {
+            return false;
+        };
+        let Some(transceiver) = self.transceivers.get(&mid) else {
+            return false;
+        };
+        if !publisher_transceiver.rids.is_empty() {
+            // all simulcast layers are bound by rid into a single ssrc, which doesn't change
+            return false;
+        }
+        let local_ssrcs = transceiver
+            .sender
+            .as_ref()
+            .map(|sender| sender.ssrcs.clone())
+            .unwrap_or_default();
+
+        // reuse subscriber's ssrcs in order, so that its description only changes if more ssrcs
+        // are published
+        let mut ssrcs = HashMap::new();
+        let mut sender = publisher_transceiver.sender.clone();
+        if let Some(sender) = sender.as_mut() {
+            for (i, ssrc) in sender.ssrcs.iter_mut().enumerate() {
+                let local_ssrc = match local_ssrcs.get(i) {
+                    Some(local_ssrc) => *local_ssrc,
+                    None => self.generate_ssrc(&ssrcs),
+                };
+                ssrcs.insert(*ssrc, local_ssrc);
+                *ssrc = local_ssrc;
+            }
+            sender.ssrc_groups = sender
+                .ssrc_groups
+                .iter()
+                .map(|ssrc_group| SsrcGroup {
+                    name: ssrc_group.name.clone(),
+                    ssrcs: ssrc_group
+                        .ssrcs
+                        .iter()
+                        .filter_map(|ssrc| ssrcs.get(ssrc).copied())
+                        .collect(),
+                })
+                .collect();
+        }
+        if ssrcs == previous_ssrcs {
+            return false;
+        }
+
+        if let Some(rtp_rewriter) = self.rtp_rewriters.get_mut(&mid) {
+            rtp_rewriter.rebind(ssrcs, &publisher_transceiver.rtp_params);
+        }
+        let mut is_renegotiation_needed = false;
+        if let Some(transceiver) = self.transceivers.get_mut(&mid) {
+            let new_ssrcs = sender
+                .as_ref()
+                .map(|sender| sender.ssrcs.clone())
+                .unwrap_or_default();
+            is_renegotiation_needed = new_ssrcs != local_ssrcs;
+            if is_renegotiation_needed {
+                self.interceptor.bind_local_stream(&StreamInfo {
+                    mid: mid.clone(),
+                    ssrcs: new_ssrcs,
+                    rtp_header_extensions: transceiver.rtp_params.header_extensions.clone(),
+                    clock_rate: publisher_transceiver
+                        .rtp_params
+                        .codecs
+                        .first()
+                        .map(|codec| codec.capability.clock_rate)
+                        .unwrap_or_default(),
+                });
+            }
+            transceiver.sender = sender;
+        }
+        is_renegotiation_needed
+    }
+
+    /// set_track_muted returns true if the muted state of published track with mid is changed
+    pub(crate) fn set_track_muted(&mut self, mid: &Mid, muted: bool) -> bool {
+        if muted {
+            self.muted_mids.insert(mid.clone())
+        } else {
+            self.muted_mids.remove(mid)
+        }
+    }
+
+    pub(crate) fn is_track_muted(&self, mid: &Mid) -> bool {
+        self.muted_mids.contains(mid)
+    }
+
+    pub(crate) fn get_mut_rtp_rewriter(&mut self, mid: &Mid) -> Option<&mut RtpRewriter> {
+        self.rtp_rewriters.get_mut(mid)
+    }
+
+    /// deactivate_forwarding_transceivers marks the transceivers forwarding tracks published by
+    /// publisher_id as inactive, returns false if there is none.
+    pub(crate) fn deactivate_forwarding_transceivers(&mut self, publisher_id: EndpointId) -> bool {
+        let mids: Vec<Mid> = self
+            .rtp_rewriters
+            .iter()
+            .filter(|(_, rtp_rewriter)| rtp_rewriter.publisher().0 == publisher_id)
+            .map(|(mid, _)| mid.clone())
+            .collect();
+
+        for mid in &mids {
+            // m-lines can't be removed from the session description, so keep them as inactive
+            self.rtp_rewriters.remove(mid);
+            if let Some(transceiver) = self.transceivers.get_mut(mid) {
+                transceiver.direction = RTCRtpTransceiverDirection::Inactive;
+                if let Some(sender) = transceiver.sender.as_ref() {
+                    self.interceptor.unbind_local_stream(&StreamInfo {
+                        mid: mid.clone(),
+                        ssrcs: sender.ssrcs.clone(),
+                        ..Default::default()
+                    });
+                }
+            }
+        }
+
+        !mids.is_empty()
+    }
+
+    /// get_publisher_ids returns the ids of endpoints whose tracks are forwarded to this endpoint
+    pub(crate) fn get_publisher_ids(&self) -> HashSet<EndpointId> {
+        self.rtp_rewriters
+            .values()
+            .map(|rtp_rewriter| rtp_rewriter.publisher().0)
+            .collect()
+    }
+
+    /// get_rtp_rewriter_by_ssrc finds the rewriter which forwards a track to this endpoint with ssrc
+    pub(crate) fn get_rtp_rewriter_by_ssrc(&self, ssrc: SSRC) -> Option<&RtpRewriter> {
+        self.rtp_rewriters
+            .values()
+            .find(|rtp_rewriter| rtp_rewriter.has_ssrc(ssrc))
+    }
+
+    /// should_request_keyframe checks whether a keyframe request can be sent for the ssrc published
+    /// by this endpoint, so that duplicated requests within interval are throttled into one.
+    fn should_request_keyframe(&mut self, ssrc: SSRC, now: Instant, interval: Duration) -> bool {
+        if self
+            .keyframe_requests
+            .get(&ssrc)
+            .is_some_and(|last_time| now.saturating_duration_since(*last_time) < interval)
+        {
+            false
+        } else {
+            self.keyframe_requests.insert(ssrc, now);
+            true
+        }
+    }
+
+    /// request_keyframe returns a FullIntraRequest if is_fir, or a PictureLossIndication otherwise,
+    /// for the ssrc published by this endpoint, or None if it is throttled, in which case it is
+    /// deferred until poll_deferred_keyframe_requests after the interval.
+    pub(crate) fn request_keyframe(
+        &mut self,
+        ssrc: SSRC,
+        sender_ssrc: SSRC,
+        is_fir: bool,
+        now: Instant,
+        interval: Duration,
+    ) -> Option<Box<dyn rtcp::packet::Packet>> {
+        if !self.should_request_keyframe(ssrc, now, interval) {
+            self.deferred_keyframe_requests
+                .entry(ssrc)
+                .and_modify(|(_, deferred_is_fir)| *deferred_is_fir |= is_fir)
+                .or_insert((sender_ssrc, is_fir));
+            return None;
+        }
+        self.deferred_keyframe_requests.remove(&ssrc);
+        Some(self.build_keyframe_request(ssrc, sender_ssrc, is_fir))
+    }
+
+    /// poll_deferred_keyframe_requests returns the keyframe requests throttled by
+    /// request_keyframe, whose interval ends by now
+    pub(crate) fn poll_deferred_keyframe_requests(
+        &mut self,
+        now: Instant,
+        interval: Duration,
+    ) -> Vec<Box<dyn rtcp::packet::Packet>> {
+        let ssrcs: Vec<SSRC> = self.deferred_keyframe_requests.keys().copied().collect();
+        let mut rtcp_packets = vec![];
+        for ssrc in ssrcs {
+            if !self.should_request_keyframe(ssrc, now, interval) {
+                continue;
+            }
+            if let Some((sender_ssrc, is_fir)) = self.deferred_keyframe_requests.remove(&ssrc) {
+                rtcp_packets.push(self.build_keyframe_request(ssrc, sender_ssrc, is_fir));
+            }
+        }
+        rtcp_packets
+    }
+
+    /// next_deferred_keyframe_request_timeout returns when the earliest deferred keyframe request
+    /// can be sent
+    pub(crate) fn next_deferred_keyframe_request_timeout(
+        &self,
+        interval: Duration,
+    ) -> Option<Instant> {
+        self.deferred_keyframe_requests
+            .keys()
+            .filter_map(|ssrc| self.keyframe_requests.get(ssrc))
+            .map(|last_time| *last_time + interval)
+            .min()
+    }
+
+    fn build_keyframe_request(
+        &mut self,
+        ssrc: SSRC,
+        sender_ssrc: SSRC,
+        is_fir: bool,
+    ) -> Box<dyn rtcp::packet::Packet> {
+        if is_fir {
+            Box::new(FullIntraRequest {
+                sender_ssrc,
+                media_ssrc: 0,
+                fir: vec![FirEntry {
+                    ssrc,
+                    sequence_number: self.next_fir_sequence_number(ssrc),
+                }],
+            })
+        } else {
+            Box::new(PictureLossIndication {
+                sender_ssrc,
+                media_ssrc: ssrc,
+            })
+        }
+    }
+
+    /// find_srtp_ready_four_tuple finds a transport whose local srtp context is ready, one is
+    /// enough to send RTCP feedback to this endpoint
+    pub(crate) fn find_srtp_ready_four_tuple(&self) -> Option<FourTuple> {
+        self.transports.iter().find_map(|(four_tuple, transport)| {
+            transport
+                .is_local_srtp_context_ready()
+                .then_some(*four_tuple)
+        })
+    }
+
+    /// next_fir_sequence_number returns the sequence number of the next FullIntraRequest for ssrc
+    fn next_fir_sequence_number(&mut self, ssrc: SSRC) -> u8 {
+        let sequence_number = self.fir_sequence_numbers.entry(ssrc).or_default();
+        *sequence_number = sequence_number.wrapping_add(1);
+        *sequence_number
+    }
+
+    fn generate_ssrc(&self, pending_ssrcs: &HashMap<SSRC, SSRC>) -> SSRC {
+        loop {
+            let ssrc = rand::random::<SSRC>();
+            if ssrc != 0
+                && !pending_ssrcs.values().any(|s| *s == ssrc)
+                && !self.transceivers.values().any(|transceiver| {
+                    transceiver
+                        .sender
+                        .as_ref()
+                        .is_some_and(|sender| sender.ssrcs.contains(&ssrc))
+                })
+            {
+                return ssrc;
+            }
+        }
+    }
+
     pub(crate) fn get_mut_mids_and_transceivers(
         &mut self,
     ) -> (&mut Vec<Mid>, &mut HashMap<Mid, RTCRtpTransceiver>) {
@@ -111,4 +684,33 @@ impl Endpoint {
     pub(crate) fn set_renegotiation_needed(&mut self, is_renegotiation_needed: bool) {
         self.is_renegotiation_needed = is_renegotiation_needed;
     }
+
+    pub(crate) fn is_offer_pending(&self) -> bool {
+        self.is_offer_pending
+    }
+
+    pub(crate) fn set_offer_pending(&mut self, is_offer_pending: bool) {
+        self.is_offer_pending = is_offer_pending;
+    }
+
+    /// whether a renegotiation offer can be sent now, without glare with an outstanding offer
+    pub(crate) fn can_renegotiate(&self) -> bool {
+        self.is_renegotiation_needed && !self.is_offer_pending
+    }
+
+    pub(crate) fn ice_restart(&self) -> Option<&IceRestart> {
+        self.ice_restart.as_ref()
+    }
+
+    pub(crate) fn set_ice_restart(
+        &mut self,
+        ice_restart: Option<IceRestart>,
+    ) -> Option<IceRestart> {
+        std::mem::replace(&mut self.ice_restart, ice_restart)
+    }
 }
+
+#[cfg(test)]
+mod endpoint_test;
+#[cfg(test)]
+mod rtp_rewriter_test;
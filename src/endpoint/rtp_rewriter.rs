@@ -0,0 +1,397 @@
+use crate::configs::media_config::{MIME_TYPE_AV1, MIME_TYPE_H264, MIME_TYPE_VP8, MIME_TYPE_VP9};
+use crate::description::rtp_codec::{
+    codec_parameters_fuzzy_search, CodecMatch, RTCRtpCodecParameters, RTCRtpParameters,
+};
+use crate::description::{
+    rtp_transceiver::{PayloadType, SSRC},
+    SDES_REPAIR_RTP_STREAM_ID_URI,
+};
+use crate::types::{EndpointId, Mid, Rid};
+use std::collections::HashMap;
+use std::time::Instant;
+
+const UINT16SIZE_HALF: u16 = 1 << 15;
+
+/// RtpRewriter rewrites RTP packets forwarded from a publisher's track into a subscriber's
+/// transceiver, so that they carry the ssrcs and payload types announced in subscriber's SDP,
+/// with continuous sequence numbers and timestamps even when the source ssrc switches.
+/// For a simulcast track, only one layer selected by rid is forwarded.
+pub(crate) struct RtpRewriter {
+    publisher_id: EndpointId,
+    publisher_mid: Mid,
+    // publisher's ssrc to subscriber's ssrc
+    ssrcs: HashMap<SSRC, SSRC>,
+    source_params: RTCRtpParameters,
+    // codecs offered to subscriber, replaced by the answered ones once negotiated
+    codecs: Vec<RTCRtpCodecParameters>,
+    // publisher's payload type to subscriber's payload type and its clock rate
+    payload_types: HashMap<PayloadType, (PayloadType, u32)>,
+    // publisher's payload type to its mime type
+    mime_types: HashMap<PayloadType, String>,
+    // ids of publisher's header extensions which are meaningless to subscriber
+    stripped_extension_ids: Vec<u8>,
+    simulcast: Option<SimulcastLayer>,
+    // keyed by subscriber's ssrc
+    streams: HashMap<SSRC, RtpRewriterStream>,
+}
+
+struct SimulcastLayer {
+    // subscriber's ssrc which all layers are forwarded to
+    ssrc: SSRC,
+    current_rid: Option<Rid>,
+    target_rid: Rid,
+}
+
+impl RtpRewriter {
+    pub(crate) fn new(
+        publisher_id: EndpointId,
+        publisher_mid: Mid,
+        ssrcs: HashMap<SSRC, SSRC>,
+        source_params: &RTCRtpParameters,
+        codecs: &[RTCRtpCodecParameters],
+    ) -> Self {
+        let mut rtp_rewriter = Self {
+            publisher_id,
+            publisher_mid,
+            ssrcs,
+            source_params: source_params.clone(),
+            codecs: codecs.to_vec(),
+            payload_types: HashMap::new(),
+            mime_types: HashMap::new(),
+            stripped_extension_ids: vec![],
+            simulcast: None,
+            streams: HashMap::new(),
+        };
+        rtp_rewriter.update_mappings();
+        rtp_rewriter
+    }
+
+    /// set_codecs maps publisher's payload types to the codecs answered by subscriber
+    pub(crate) fn set_codecs(&mut self, codecs: &[RTCRtpCodecParameters]) {
+        self.codecs = codecs.to_vec();
+        self.update_mappings();
+    }
+
+    /// rebind replaces publisher's ssrcs and rtp parameters after publisher renegotiates, while
+    /// subscriber's streams keep continuous sequence numbers and timestamps
+    pub(crate) fn rebind(&mut self, ssrcs: HashMap<SSRC, SSRC>, source_params: &RTCRtpParameters) {
+        self.ssrcs = ssrcs;
+        self.source_params = source_params.clone();
+        self.update_mappings();
+    }
+
+    /// ssrcs returns publisher's ssrcs mapped to subscriber's ssrcs
+    pub(crate) fn ssrcs(&self) -> &HashMap<SSRC, SSRC> {
+        &self.ssrcs
+    }
+
+    fn update_mappings(&mut self) {
+        self.payload_types.clear();
+        self.mime_types.clear();
+        for source_codec in &self.source_params.codecs {
+            let (codec, codec_match) = codec_parameters_fuzzy_search(source_codec, &self.codecs);
+            if codec_match != CodecMatch::None {
+                self.payload_types.insert(
+                    source_codec.payload_type,
+                    (codec.payload_type, codec.capability.clock_rate),
+                );
+            }
+            self.mime_types.insert(
+                source_codec.payload_type,
+                source_codec.capability.mime_type.clone(),
+            );
+        }
+
+        self.stripped_extension_ids = self
+            .source_params
+            .header_extensions
+            .iter()
+            .filter(|ext| {
+                ext.uri == sdp::extmap::SDES_MID_URI
+                    || ext.uri == sdp::extmap::SDES_RTP_STREAM_ID_URI
+                    || ext.uri == SDES_REPAIR_RTP_STREAM_ID_URI
+            })
+            .map(|ext| ext.id as u8)
+            .collect();
+    }
+
+    /// with_simulcast forwards the simulcast layer with rid to subscriber's ssrc
+    pub(crate) fn with_simulcast(mut self, ssrc: SSRC, rid: Rid) -> Self {
+        self.simulcast = Some(SimulcastLayer {
+            ssrc,
+            current_rid: None,
+            target_rid: rid,
+        });
+        self
+    }
+
+    /// set_target_rid selects the simulcast layer to switch to on its next keyframe
+    pub(crate) fn set_target_rid(&mut self, rid: Rid) -> bool {
+        if let Some(simulcast) = self.simulcast.as_mut() {
+            simulcast.target_rid = rid;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// publisher returns the endpoint id and mid of the track forwarded by this rewriter
+    pub(crate) fn publisher(&self) -> (EndpointId, &Mid) {
+        (self.publisher_id, &self.publisher_mid)
+    }
+
+    /// has_ssrc checks whether subscriber's ssrc is forwarded by this rewriter
+    pub(crate) fn has_ssrc(&self, ssrc: SSRC) -> bool {
+        self.simulcast
+            .as_ref()
+            .is_some_and(|simulcast| simulcast.ssrc == ssrc)
+            || self.ssrcs.values().any(|s| *s == ssrc)
+    }
+
+    /// source_ssrc returns publisher's ssrc which subscriber's ssrc is forwarded from
+    pub(crate) fn source_ssrc(&self, ssrc: SSRC) -> Option<SSRC> {
+        self.ssrcs
+            .iter()
+            .find_map(|(source_ssrc, s)| (*s == ssrc).then_some(*source_ssrc))
+    }
+
+    /// target_rid returns the rid of the simulcast layer forwarded, or to be switched to,
+    /// as subscriber's ssrc
+    pub(crate) fn target_rid(&self, ssrc: SSRC) -> Option<&Rid> {
+        self.simulcast
+            .as_ref()
+            .filter(|simulcast| simulcast.ssrc == ssrc)
+            .map(|simulcast| &simulcast.target_rid)
+    }
+
+    /// translate_rtp_time returns subscriber's ssrc currently forwarded from publisher's ssrc,
+    /// and the rtp time translated into it
+    pub(crate) fn translate_rtp_time(
+        &self,
+        source_ssrc: SSRC,
+        rtp_time: u32,
+    ) -> Option<(SSRC, u32)> {
+        self.streams.iter().find_map(|(ssrc, stream)| {
+            (stream.source_ssrc == Some(source_ssrc))
+                .then(|| (*ssrc, rtp_time.wrapping_add(stream.timestamp_offset)))
+        })
+    }
+
+    /// rewrite returns the packet to be sent to subscriber, or None if its ssrc or payload type is
+    /// not mapped by this rewriter or it doesn't belong to the selected simulcast layer.
+    pub(crate) fn rewrite(
+        &mut self,
+        now: Instant,
+        rtp_packet: &rtp::packet::Packet,
+        rid: Option<&Rid>,
+    ) -> Option<rtp::packet::Packet> {
+        let ssrc = if let Some(rid) = rid {
+            let simulcast = self.simulcast.as_mut()?;
+            if simulcast.current_rid.as_ref() != Some(rid) {
+                // switch layer on keyframe only, so that subscriber can decode from it
+                let mime_type = self.mime_types.get(&rtp_packet.header.payload_type)?;
+                if simulcast.target_rid != *rid || !is_keyframe(mime_type, &rtp_packet.payload) {
+                    return None;
+                }
+                simulcast.current_rid = Some(rid.clone());
+            }
+            simulcast.ssrc
+        } else {
+            *self.ssrcs.get(&rtp_packet.header.ssrc)?
+        };
+        // drop codecs which subscriber doesn't accept
+        let (payload_type, clock_rate) = self
+            .payload_types
+            .get(&rtp_packet.header.payload_type)
+            .copied()?;
+
+        let stream = self.streams.entry(ssrc).or_default();
+        let (sequence_number, timestamp) = stream.rewrite(now, rtp_packet, clock_rate);
+
+        let mut rtp_packet = rtp_packet.clone();
+        for id in &self.stripped_extension_ids {
+            let _ = rtp_packet.header.del_extension(*id);
+        }
+        rtp_packet.header.ssrc = ssrc;
+        rtp_packet.header.payload_type = payload_type;
+        rtp_packet.header.sequence_number = sequence_number;
+        rtp_packet.header.timestamp = timestamp;
+        Some(rtp_packet)
+    }
+}
+
+#[derive(Default)]
+struct RtpRewriterStream {
+    source_ssrc: Option<SSRC>,
+    sequence_number_offset: u16,
+    timestamp_offset: u32,
+    last_sequence_number: u16,
+    last_timestamp: u32,
+    last_time: Option<Instant>,
+}
+
+impl RtpRewriterStream {
+    fn rewrite(
+        &mut self,
+        now: Instant,
+        rtp_packet: &rtp::packet::Packet,
+        clock_rate: u32,
+    ) -> (u16, u32) {
+        let header = &rtp_packet.header;
+        match self.source_ssrc {
+            None => {
+                // keep the first source's sequence numbers and timestamps as they are
+                self.source_ssrc = Some(header.ssrc);
+                self.last_sequence_number = header.sequence_number;
+                self.last_timestamp = header.timestamp;
+                self.last_time = Some(now);
+            }
+            Some(source_ssrc) if source_ssrc != header.ssrc => {
+                // source switches, continue right after the last forwarded packet
+                let elapsed = self
+                    .last_time
+                    .map(|last_time| now.saturating_duration_since(last_time))
+                    .unwrap_or_default();
+                let timestamp_delta = ((elapsed.as_secs_f64() * clock_rate as f64) as u32).max(1);
+
+                self.source_ssrc = Some(header.ssrc);
+                self.sequence_number_offset = self
+                    .last_sequence_number
+                    .wrapping_add(1)
+                    .wrapping_sub(header.sequence_number);
+                self.timestamp_offset = self
+                    .last_timestamp
+                    .wrapping_add(timestamp_delta)
+                    .wrapping_sub(header.timestamp);
+            }
+            _ => {}
+        }
+
+        let sequence_number = header
+            .sequence_number
+            .wrapping_add(self.sequence_number_offset);
+        let timestamp = header.timestamp.wrapping_add(self.timestamp_offset);
+
+        if sequence_number.wrapping_sub(self.last_sequence_number) < UINT16SIZE_HALF {
+            self.last_sequence_number = sequence_number;
+            self.last_timestamp = timestamp;
+            self.last_time = Some(now);
+        }
+
+        (sequence_number, timestamp)
+    }
+}
+
+/// is_keyframe checks whether the payload starts a keyframe, which decoder can start from
+pub(crate) fn is_keyframe(mime_type: &str, payload: &[u8]) -> bool {
+    if mime_type.eq_ignore_ascii_case(MIME_TYPE_VP8) {
+        is_vp8_keyframe(payload)
+    } else if mime_type.eq_ignore_ascii_case(MIME_TYPE_VP9) {
+        is_vp9_keyframe(payload)
+    } else if mime_type.eq_ignore_ascii_case(MIME_TYPE_H264) {
+        is_h264_keyframe(payload)
+    } else if mime_type.eq_ignore_ascii_case(MIME_TYPE_AV1) {
+        is_av1_keyframe(payload)
+    } else {
+        false
+    }
+}
+
+/// <https://datatracker.ietf.org/doc/html/rfc7741#section-4.2>
+fn is_vp8_keyframe(payload: &[u8]) -> bool {
+    if payload.is_empty() {
+        return false;
+    }
+    // start of partition 0 only
+    if payload[0] & 0x10 == 0 || payload[0] & 0x0F != 0 {
+        return false;
+    }
+
+    let mut offset = 1;
+    if payload[0] & 0x80 != 0 {
+        let Some(&x) = payload.get(offset) else {
+            return false;
+        };
+        offset += 1;
+        if x & 0x80 != 0 {
+            // picture id is 7 or 15 bits
+            let Some(&m) = payload.get(offset) else {
+                return false;
+            };
+            offset += if m & 0x80 != 0 { 2 } else { 1 };
+        }
+        if x & 0x40 != 0 {
+            offset += 1;
+        }
+        if x & 0x30 != 0 {
+            offset += 1;
+        }
+    }
+
+    // P bit of VP8 payload header is 0 for keyframe
+    payload.get(offset).is_some_and(|b| b & 0x01 == 0)
+}
+
+/// <https://datatracker.ietf.org/doc/html/draft-ietf-payload-vp9-16#section-4.2>
+fn is_vp9_keyframe(payload: &[u8]) -> bool {
+    if payload.is_empty() {
+        return false;
+    }
+    let b = payload[0];
+    // not inter-picture predicted, and start of frame
+    if b & 0x40 != 0 || b & 0x08 == 0 {
+        return false;
+    }
+    if b & 0x20 == 0 {
+        return true;
+    }
+
+    let mut offset = 1;
+    if b & 0x80 != 0 {
+        let Some(&m) = payload.get(offset) else {
+            return false;
+        };
+        offset += if m & 0x80 != 0 { 2 } else { 1 };
+    }
+    // base spatial layer only
+    payload.get(offset).is_some_and(|l| (l >> 1) & 0x07 == 0)
+}
+
+/// <https://datatracker.ietf.org/doc/html/rfc6184#section-5.2>
+fn is_h264_keyframe(payload: &[u8]) -> bool {
+    const NALU_TYPE_IDR: u8 = 5;
+    const NALU_TYPE_SPS: u8 = 7;
+    const NALU_TYPE_STAP_A: u8 = 24;
+    const NALU_TYPE_FU_A: u8 = 28;
+
+    if payload.is_empty() {
+        return false;
+    }
+    match payload[0] & 0x1F {
+        NALU_TYPE_IDR | NALU_TYPE_SPS => true,
+        NALU_TYPE_STAP_A => {
+            let mut offset = 1;
+            while offset + 2 < payload.len() {
+                let size = u16::from_be_bytes([payload[offset], payload[offset + 1]]) as usize;
+                let nalu_type = payload[offset + 2] & 0x1F;
+                if nalu_type == NALU_TYPE_IDR || nalu_type == NALU_TYPE_SPS {
+                    return true;
+                }
+                offset += 2 + size;
+            }
+            false
+        }
+        NALU_TYPE_FU_A => payload
+            .get(1)
+            .is_some_and(|b| b & 0x80 != 0 && b & 0x1F == NALU_TYPE_IDR),
+        _ => false,
+    }
+}
+
+/// <https://aomediacodec.github.io/av1-rtp-spec/#44-av1-aggregation-header>
+fn is_av1_keyframe(payload: &[u8]) -> bool {
+    // N bit marks the first packet of a coded video sequence
+    payload
+        .first()
+        .is_some_and(|b| b & 0x08 != 0 && b & 0x80 == 0)
+}
@@ -0,0 +1,285 @@
+use super::rtp_rewriter::{is_keyframe, RtpRewriter};
+use crate::configs::media_config::{MIME_TYPE_AV1, MIME_TYPE_H264, MIME_TYPE_VP8, MIME_TYPE_VP9};
+use crate::description::rtp_codec::{
+    RTCRtpCodecCapability, RTCRtpCodecParameters, RTCRtpParameters,
+};
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+const VP8_KEYFRAME: &[u8] = &[0x10, 0x00];
+const VP8_DELTA_FRAME: &[u8] = &[0x10, 0x01];
+
+fn codec(mime_type: &str, payload_type: u8) -> RTCRtpCodecParameters {
+    RTCRtpCodecParameters {
+        capability: RTCRtpCodecCapability {
+            mime_type: mime_type.to_string(),
+            clock_rate: 90000,
+            ..Default::default()
+        },
+        payload_type,
+        ..Default::default()
+    }
+}
+
+fn source_params() -> RTCRtpParameters {
+    RTCRtpParameters {
+        header_extensions: vec![],
+        codecs: vec![codec(MIME_TYPE_VP8, 96), codec(MIME_TYPE_VP9, 98)],
+    }
+}
+
+fn packet(
+    ssrc: u32,
+    payload_type: u8,
+    seq: u16,
+    timestamp: u32,
+    payload: &[u8],
+) -> rtp::packet::Packet {
+    let mut packet = rtp::packet::Packet::default();
+    packet.header.ssrc = ssrc;
+    packet.header.payload_type = payload_type;
+    packet.header.sequence_number = seq;
+    packet.header.timestamp = timestamp;
+    packet.payload = Bytes::copy_from_slice(payload);
+    packet
+}
+
+fn rewriter(ssrcs: &[(u32, u32)]) -> RtpRewriter {
+    RtpRewriter::new(
+        1,
+        "0".to_string(),
+        ssrcs.iter().copied().collect::<HashMap<_, _>>(),
+        &source_params(),
+        &[codec(MIME_TYPE_VP8, 100), codec(MIME_TYPE_VP9, 101)],
+    )
+}
+
+#[test]
+fn test_rewrite_ssrc_and_payload_type() {
+    let now = Instant::now();
+    let mut rtp_rewriter = rewriter(&[(1111, 2222)]);
+
+    let rewritten = rtp_rewriter
+        .rewrite(now, &packet(1111, 96, 10, 1000, VP8_DELTA_FRAME), None)
+        .unwrap();
+    assert_eq!(rewritten.header.ssrc, 2222);
+    assert_eq!(rewritten.header.payload_type, 100);
+    assert_eq!(rewritten.header.sequence_number, 10);
+    assert_eq!(rewritten.header.timestamp, 1000);
+
+    // unknown ssrc and payload type
+    assert!(rtp_rewriter
+        .rewrite(now, &packet(3333, 96, 11, 1000, VP8_DELTA_FRAME), None)
+        .is_none());
+    assert!(rtp_rewriter
+        .rewrite(now, &packet(1111, 111, 11, 1000, VP8_DELTA_FRAME), None)
+        .is_none());
+}
+
+#[test]
+fn test_rewrite_with_answered_codecs() {
+    let now = Instant::now();
+    let mut rtp_rewriter = rewriter(&[(1111, 2222)]);
+
+    // subscriber only accepts VP9, with another payload type than offered
+    rtp_rewriter.set_codecs(&[codec(MIME_TYPE_VP9, 120)]);
+    assert!(rtp_rewriter
+        .rewrite(now, &packet(1111, 96, 10, 1000, VP8_DELTA_FRAME), None)
+        .is_none());
+    let rewritten = rtp_rewriter
+        .rewrite(now, &packet(1111, 98, 11, 1000, &[0x08]), None)
+        .unwrap();
+    assert_eq!(rewritten.header.payload_type, 120);
+}
+
+#[test]
+fn test_rewrite_sequence_number_wraparound() {
+    let now = Instant::now();
+    let mut rtp_rewriter = rewriter(&[(1111, 2222)]);
+
+    let seqs: Vec<u16> = [65534, 65535, 0, 1]
+        .iter()
+        .map(|seq| {
+            rtp_rewriter
+                .rewrite(now, &packet(1111, 96, *seq, 1000, VP8_DELTA_FRAME), None)
+                .unwrap()
+                .header
+                .sequence_number
+        })
+        .collect();
+    assert_eq!(seqs, vec![65534, 65535, 0, 1]);
+}
+
+#[test]
+fn test_rewrite_continuity_on_rebind() {
+    let now = Instant::now();
+    let mut rtp_rewriter = rewriter(&[(1111, 2222)]);
+    rtp_rewriter
+        .rewrite(now, &packet(1111, 96, 500, 90000, VP8_DELTA_FRAME), None)
+        .unwrap();
+    // reordered packet doesn't move the continuation point
+    rtp_rewriter
+        .rewrite(now, &packet(1111, 96, 499, 87000, VP8_DELTA_FRAME), None)
+        .unwrap();
+
+    // publisher renegotiates with a new ssrc, which is forwarded to the same subscriber's ssrc
+    rtp_rewriter.rebind(
+        [(5555, 2222)].into_iter().collect::<HashMap<_, _>>(),
+        &source_params(),
+    );
+    assert!(rtp_rewriter
+        .rewrite(now, &packet(1111, 96, 501, 93000, VP8_DELTA_FRAME), None)
+        .is_none());
+
+    let later = now + Duration::from_millis(100);
+    let rewritten = rtp_rewriter
+        .rewrite(later, &packet(5555, 96, 30000, 7777, VP8_KEYFRAME), None)
+        .unwrap();
+    assert_eq!(rewritten.header.ssrc, 2222);
+    assert_eq!(rewritten.header.sequence_number, 501);
+    assert_eq!(rewritten.header.timestamp, 90000 + 9000);
+
+    let rewritten = rtp_rewriter
+        .rewrite(
+            later,
+            &packet(5555, 96, 30001, 7777 + 3000, VP8_DELTA_FRAME),
+            None,
+        )
+        .unwrap();
+    assert_eq!(rewritten.header.sequence_number, 502);
+    assert_eq!(rewritten.header.timestamp, 90000 + 9000 + 3000);
+    assert_eq!(
+        rtp_rewriter.translate_rtp_time(5555, 7777),
+        Some((2222, 99000))
+    );
+}
+
+#[test]
+fn test_rewrite_simulcast_layer_switch() {
+    let now = Instant::now();
+    let mut rtp_rewriter = RtpRewriter::new(
+        1,
+        "0".to_string(),
+        HashMap::new(),
+        &source_params(),
+        &[codec(MIME_TYPE_VP8, 100)],
+    )
+    .with_simulcast(2222, "q".to_string());
+    let q = "q".to_string();
+    let h = "h".to_string();
+
+    // waits for a keyframe of the target layer
+    assert!(rtp_rewriter
+        .rewrite(now, &packet(1, 96, 10, 1000, VP8_DELTA_FRAME), Some(&q))
+        .is_none());
+    assert!(rtp_rewriter
+        .rewrite(now, &packet(2, 96, 20, 5000, VP8_KEYFRAME), Some(&h))
+        .is_none());
+    let rewritten = rtp_rewriter
+        .rewrite(now, &packet(1, 96, 11, 1000, VP8_KEYFRAME), Some(&q))
+        .unwrap();
+    assert_eq!(rewritten.header.ssrc, 2222);
+    assert_eq!(rewritten.header.sequence_number, 11);
+
+    // keeps forwarding the current layer until the target layer sends a keyframe
+    assert!(rtp_rewriter.set_target_rid(h.clone()));
+    assert!(rtp_rewriter
+        .rewrite(now, &packet(2, 96, 21, 5000, VP8_DELTA_FRAME), Some(&h))
+        .is_none());
+    assert_eq!(
+        rtp_rewriter
+            .rewrite(now, &packet(1, 96, 12, 1000, VP8_DELTA_FRAME), Some(&q))
+            .unwrap()
+            .header
+            .sequence_number,
+        12
+    );
+
+    let rewritten = rtp_rewriter
+        .rewrite(now, &packet(2, 96, 22, 5000, VP8_KEYFRAME), Some(&h))
+        .unwrap();
+    assert_eq!(rewritten.header.ssrc, 2222);
+    assert_eq!(rewritten.header.sequence_number, 13);
+    // at least one tick after the last forwarded timestamp
+    assert_eq!(rewritten.header.timestamp, 1001);
+    assert!(rtp_rewriter
+        .rewrite(now, &packet(1, 96, 13, 1000, VP8_DELTA_FRAME), Some(&q))
+        .is_none());
+}
+
+#[test]
+fn test_is_vp8_keyframe() {
+    assert!(is_keyframe(MIME_TYPE_VP8, &[0x10, 0x00]));
+    assert!(!is_keyframe(MIME_TYPE_VP8, &[0x10, 0x01]));
+    // not the start of partition 0
+    assert!(!is_keyframe(MIME_TYPE_VP8, &[0x00, 0x00]));
+    assert!(!is_keyframe(MIME_TYPE_VP8, &[0x11, 0x00]));
+    // extended control bits with 15 bits picture id, tl0picidx and tid
+    assert!(is_keyframe(
+        MIME_TYPE_VP8,
+        &[0x90, 0xe0, 0x81, 0x23, 0x01, 0x40, 0x00]
+    ));
+    assert!(!is_keyframe(
+        MIME_TYPE_VP8,
+        &[0x90, 0xe0, 0x81, 0x23, 0x01, 0x40, 0x01]
+    ));
+    // 7 bits picture id
+    assert!(is_keyframe(MIME_TYPE_VP8, &[0x90, 0x80, 0x05, 0x00]));
+    // truncated
+    assert!(!is_keyframe(MIME_TYPE_VP8, &[0x90, 0x80]));
+    assert!(!is_keyframe(MIME_TYPE_VP8, &[]));
+}
+
+#[test]
+fn test_is_vp9_keyframe() {
+    assert!(is_keyframe(MIME_TYPE_VP9, &[0x08]));
+    // inter-picture predicted
+    assert!(!is_keyframe(MIME_TYPE_VP9, &[0x48]));
+    // not the start of a frame
+    assert!(!is_keyframe(MIME_TYPE_VP9, &[0x00]));
+    // layer indices with spatial layer 0 and 1
+    assert!(is_keyframe(MIME_TYPE_VP9, &[0x28, 0x00]));
+    assert!(!is_keyframe(MIME_TYPE_VP9, &[0x28, 0x02]));
+    // 15 bits picture id before layer indices
+    assert!(is_keyframe(MIME_TYPE_VP9, &[0xa8, 0x80, 0x01, 0x00]));
+    assert!(!is_keyframe(MIME_TYPE_VP9, &[0xa8, 0x80, 0x01]));
+    assert!(!is_keyframe(MIME_TYPE_VP9, &[]));
+}
+
+#[test]
+fn test_is_h264_keyframe() {
+    // IDR and SPS
+    assert!(is_keyframe(MIME_TYPE_H264, &[0x65, 0x88]));
+    assert!(is_keyframe(MIME_TYPE_H264, &[0x67, 0x42]));
+    // non-IDR slice
+    assert!(!is_keyframe(MIME_TYPE_H264, &[0x41, 0x9a]));
+    // STAP-A with SPS, and with a non-IDR slice only
+    assert!(is_keyframe(
+        MIME_TYPE_H264,
+        &[0x78, 0x00, 0x02, 0x09, 0xf0, 0x00, 0x02, 0x67, 0x42]
+    ));
+    assert!(!is_keyframe(
+        MIME_TYPE_H264,
+        &[0x78, 0x00, 0x02, 0x41, 0x9a]
+    ));
+    // FU-A start and continuation of IDR
+    assert!(is_keyframe(MIME_TYPE_H264, &[0x7c, 0x85]));
+    assert!(!is_keyframe(MIME_TYPE_H264, &[0x7c, 0x05]));
+    assert!(!is_keyframe(MIME_TYPE_H264, &[0x7c, 0x81]));
+    assert!(!is_keyframe(MIME_TYPE_H264, &[]));
+}
+
+#[test]
+fn test_is_av1_keyframe() {
+    assert!(is_keyframe(MIME_TYPE_AV1, &[0x08]));
+    // continuation of an OBU from the previous packet
+    assert!(!is_keyframe(MIME_TYPE_AV1, &[0x88]));
+    assert!(!is_keyframe(MIME_TYPE_AV1, &[0x00]));
+    assert!(!is_keyframe(MIME_TYPE_AV1, &[]));
+}
+
+#[test]
+fn test_is_keyframe_unknown_codec() {
+    assert!(!is_keyframe("audio/opus", &[0x10, 0x00]));
+}
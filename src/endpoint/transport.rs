@@ -1,18 +1,67 @@
 use crate::endpoint::candidate::Candidate;
+use crate::messages::DataChannelMessageParams;
 use crate::types::FourTuple;
-use sctp::{Association, AssociationHandle};
+use bytes::BytesMut;
+use log::debug;
+use sctp::{Association, AssociationHandle, Payload};
 use srtp::context::Context;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::net::SocketAddr;
 use std::rc::Rc;
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
+
+/// RTCIceConnectionState indicates the state of ICE connection of a transport, which is
+/// driven by consent freshness of its remote peer, RFC 7675
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RTCIceConnectionState {
+    /// transport is nominated, but no consent is granted yet
+    #[default]
+    Checking,
+    /// consent is granted by a recent STUN binding request
+    Connected,
+    /// no STUN binding request is received within disconnected timeout
+    Disconnected,
+    /// consent is expired after failed timeout, so the transport is removed
+    Failed,
+}
+
+impl fmt::Display for RTCIceConnectionState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            RTCIceConnectionState::Checking => write!(f, "checking"),
+            RTCIceConnectionState::Connected => write!(f, "connected"),
+            RTCIceConnectionState::Disconnected => write!(f, "disconnected"),
+            RTCIceConnectionState::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+/// SrtpDecryptErrorCounts counts incoming SRTP and SRTCP packets of a transport failed to decrypt
+/// by their cause, so that tampering can be told from packet loss
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SrtpDecryptErrorCounts {
+    /// packets rejected by replay protection
+    pub replayed: u64,
+    /// packets failed to verify authentication tag
+    pub auth_failed: u64,
+    /// packets failed to decrypt by other causes
+    pub decrypt_failed: u64,
+}
 
 pub(crate) struct Transport {
     four_tuple: FourTuple,
+    // peer address when transport was created, which keeps identifying DTLS connection and SCTP
+    // association after the transport is migrated to another four_tuple
+    dtls_remote_addr: SocketAddr,
     last_activity: Instant,
 
     // ICE
     candidate: Rc<Candidate>,
+    // last time a valid STUN binding request granted consent, RFC 7675
+    last_consent: Instant,
+    ice_connection_state: RTCIceConnectionState,
 
     // DTLS
     dtls_endpoint: dtls::endpoint::Endpoint,
@@ -24,10 +73,21 @@ pub(crate) struct Transport {
     // DataChannel
     association_handle: Option<usize>,
     stream_id: Option<u16>,
+    // whether signaling messages are wrapped in versioned SignalingEnvelope
+    is_signaling_envelope: bool,
+    // labels and ordering/reliability params of application data channels by
+    // (association_handle, stream_id)
+    data_channels: HashMap<(usize, u16), (String, DataChannelMessageParams)>,
+    // application data channels opened by server, which are waiting for DataChannelAck
+    pending_data_channel_acks: HashSet<(usize, u16)>,
+    // application data channels opened by server, whose messages are surfaced to application
+    // instead of being relayed to other endpoints
+    server_data_channels: HashSet<(usize, u16)>,
 
     // SRTP
     local_srtp_context: Option<Context>,
     remote_srtp_context: Option<Context>,
+    srtp_decrypt_error_counts: SrtpDecryptErrorCounts,
 }
 
 impl Transport {
@@ -40,9 +100,12 @@ impl Transport {
     ) -> Self {
         Self {
             four_tuple,
+            dtls_remote_addr: four_tuple.peer_addr,
             last_activity: Instant::now(),
 
             candidate,
+            last_consent: Instant::now(),
+            ice_connection_state: RTCIceConnectionState::Checking,
 
             dtls_endpoint: dtls::endpoint::Endpoint::new(Some(dtls_handshake_config)),
 
@@ -51,9 +114,14 @@ impl Transport {
 
             association_handle: None,
             stream_id: None,
+            is_signaling_envelope: false,
+            data_channels: HashMap::new(),
+            pending_data_channel_acks: HashSet::new(),
+            server_data_channels: HashSet::new(),
 
             local_srtp_context: None,
             remote_srtp_context: None,
+            srtp_decrypt_error_counts: SrtpDecryptErrorCounts::default(),
         }
     }
 
@@ -61,10 +129,50 @@ impl Transport {
         &self.four_tuple
     }
 
+    pub(crate) fn dtls_remote_addr(&self) -> SocketAddr {
+        self.dtls_remote_addr
+    }
+
     pub(crate) fn candidate(&self) -> &Rc<Candidate> {
         &self.candidate
     }
 
+    /// migrate moves transport to four_tuple nominated with candidate, keeping its DTLS, SCTP
+    /// and SRTP states
+    pub(crate) fn migrate(&mut self, four_tuple: FourTuple, candidate: Rc<Candidate>) {
+        self.four_tuple = four_tuple;
+        self.candidate = candidate;
+        self.keep_alive();
+    }
+
+    /// close shuts down the SCTP associations and the DTLS connection of transport, and returns
+    /// the DTLS records carrying SCTP SHUTDOWN chunks and close_notify alert to be sent to peer
+    pub(crate) fn close(&mut self, now: Instant) -> Vec<BytesMut> {
+        let remote = self.dtls_remote_addr;
+        for association in self.sctp_associations.values_mut() {
+            if let Err(err) = association.shutdown() {
+                debug!("skip SCTP shutdown of {:?}: {}", self.four_tuple, err);
+                continue;
+            }
+            while let Some(transmit) = association.poll_transmit(now) {
+                if let Payload::RawEncode(contents) = transmit.payload {
+                    for content in contents {
+                        if let Err(err) = self.dtls_endpoint.write(remote, &content) {
+                            debug!("skip SCTP shutdown of {:?}: {}", self.four_tuple, err);
+                        }
+                    }
+                }
+            }
+        }
+        self.dtls_endpoint.close(remote);
+
+        let mut records = vec![];
+        while let Some(transmit) = self.dtls_endpoint.poll_transmit() {
+            records.push(transmit.payload);
+        }
+        records
+    }
+
     pub(crate) fn get_mut_dtls_endpoint(&mut self) -> &mut dtls::endpoint::Endpoint {
         &mut self.dtls_endpoint
     }
@@ -116,6 +224,14 @@ impl Transport {
         self.remote_srtp_context = Some(remote_srtp_context);
     }
 
+    pub(crate) fn srtp_decrypt_error_counts(&self) -> SrtpDecryptErrorCounts {
+        self.srtp_decrypt_error_counts
+    }
+
+    pub(crate) fn get_mut_srtp_decrypt_error_counts(&mut self) -> &mut SrtpDecryptErrorCounts {
+        &mut self.srtp_decrypt_error_counts
+    }
+
     pub(crate) fn set_association_handle_and_stream_id(
         &mut self,
         association_handle: usize,
@@ -125,10 +241,95 @@ impl Transport {
         self.stream_id = Some(stream_id)
     }
 
+    pub(crate) fn clear_association_handle_and_stream_id(&mut self) {
+        self.association_handle = None;
+        self.stream_id = None;
+    }
+
     pub(crate) fn association_handle_and_stream_id(&self) -> (Option<usize>, Option<u16>) {
         (self.association_handle, self.stream_id)
     }
 
+    pub(crate) fn is_signaling_envelope(&self) -> bool {
+        self.is_signaling_envelope
+    }
+
+    pub(crate) fn set_signaling_envelope(&mut self, is_signaling_envelope: bool) {
+        self.is_signaling_envelope = is_signaling_envelope;
+    }
+
+    pub(crate) fn add_data_channel(
+        &mut self,
+        association_handle: usize,
+        stream_id: u16,
+        label: String,
+        params: DataChannelMessageParams,
+    ) {
+        self.data_channels
+            .insert((association_handle, stream_id), (label, params));
+    }
+
+    /// add_pending_data_channel_ack marks the data channel opened by server as waiting for
+    /// DataChannelAck
+    pub(crate) fn add_pending_data_channel_ack(
+        &mut self,
+        association_handle: usize,
+        stream_id: u16,
+    ) {
+        self.pending_data_channel_acks
+            .insert((association_handle, stream_id));
+    }
+
+    /// add_server_data_channel marks the data channel as opened by server
+    pub(crate) fn add_server_data_channel(&mut self, association_handle: usize, stream_id: u16) {
+        self.server_data_channels
+            .insert((association_handle, stream_id));
+    }
+
+    pub(crate) fn is_server_data_channel(&self, association_handle: usize, stream_id: u16) -> bool {
+        self.server_data_channels
+            .contains(&(association_handle, stream_id))
+    }
+
+    /// remove_pending_data_channel_ack returns whether the data channel was waiting for
+    /// DataChannelAck
+    pub(crate) fn remove_pending_data_channel_ack(
+        &mut self,
+        association_handle: usize,
+        stream_id: u16,
+    ) -> bool {
+        self.pending_data_channel_acks
+            .remove(&(association_handle, stream_id))
+    }
+
+    pub(crate) fn remove_data_channel(
+        &mut self,
+        association_handle: usize,
+        stream_id: u16,
+    ) -> Option<String> {
+        self.pending_data_channel_acks
+            .remove(&(association_handle, stream_id));
+        self.server_data_channels
+            .remove(&(association_handle, stream_id));
+        self.data_channels
+            .remove(&(association_handle, stream_id))
+            .map(|(label, _)| label)
+    }
+
+    pub(crate) fn get_data_channels(
+        &self,
+    ) -> &HashMap<(usize, u16), (String, DataChannelMessageParams)> {
+        &self.data_channels
+    }
+
+    /// find_data_channel returns (association_handle, stream_id) of application data channel
+    /// with label
+    pub(crate) fn find_data_channel(&self, label: &str) -> Option<(usize, u16)> {
+        self.data_channels
+            .iter()
+            .find_map(|(&id, (data_channel_label, _))| (data_channel_label == label).then_some(id))
+    }
+
     pub(crate) fn is_local_srtp_context_ready(&self) -> bool {
         self.local_srtp_context.is_some()
     }
@@ -140,4 +341,66 @@ impl Transport {
     pub(crate) fn last_activity(&self) -> Instant {
         self.last_activity
     }
+
+    pub(crate) fn ice_connection_state(&self) -> RTCIceConnectionState {
+        self.ice_connection_state
+    }
+
+    pub(crate) fn last_consent(&self) -> Instant {
+        self.last_consent
+    }
+
+    /// refresh_consent records consent granted by a valid STUN binding request, and returns
+    /// the new ICE connection state if it is changed
+    pub(crate) fn refresh_consent(&mut self, now: Instant) -> Option<RTCIceConnectionState> {
+        self.last_consent = now;
+        self.set_ice_connection_state(RTCIceConnectionState::Connected)
+    }
+
+    /// update_ice_connection_state checks consent freshness, and returns the new ICE
+    /// connection state if it is changed
+    pub(crate) fn update_ice_connection_state(
+        &mut self,
+        now: Instant,
+        disconnected_timeout: Duration,
+        failed_timeout: Duration,
+    ) -> Option<RTCIceConnectionState> {
+        let elapsed = now.saturating_duration_since(self.last_consent);
+        if elapsed >= failed_timeout {
+            self.set_ice_connection_state(RTCIceConnectionState::Failed)
+        } else if elapsed >= disconnected_timeout
+            && self.ice_connection_state != RTCIceConnectionState::Failed
+        {
+            self.set_ice_connection_state(RTCIceConnectionState::Disconnected)
+        } else {
+            None
+        }
+    }
+
+    /// next_consent_timeout returns when ICE connection state changes without new consent
+    pub(crate) fn next_consent_timeout(
+        &self,
+        disconnected_timeout: Duration,
+        failed_timeout: Duration,
+    ) -> Option<Instant> {
+        match self.ice_connection_state {
+            RTCIceConnectionState::Checking | RTCIceConnectionState::Connected => {
+                Some(self.last_consent + disconnected_timeout.min(failed_timeout))
+            }
+            RTCIceConnectionState::Disconnected => Some(self.last_consent + failed_timeout),
+            RTCIceConnectionState::Failed => None,
+        }
+    }
+
+    fn set_ice_connection_state(
+        &mut self,
+        ice_connection_state: RTCIceConnectionState,
+    ) -> Option<RTCIceConnectionState> {
+        if self.ice_connection_state == ice_connection_state {
+            None
+        } else {
+            self.ice_connection_state = ice_connection_state;
+            Some(ice_connection_state)
+        }
+    }
 }
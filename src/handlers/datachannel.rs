@@ -2,6 +2,7 @@ use crate::messages::{
     ApplicationMessage, DTLSMessageEvent, DataChannelEvent, DataChannelMessage,
     DataChannelMessageParams, DataChannelMessageType, MessageEvent, TaggedMessageEvent,
 };
+use bytes::BytesMut;
 use datachannel::message::{message_channel_ack::*, message_channel_open::*, message_type::*, *};
 use log::{debug, error, warn};
 use retty::channel::{Context, Handler};
@@ -48,7 +49,8 @@ impl Handler for DataChannelHandler {
                 || -> Result<(Option<ApplicationMessage>, Option<DataChannelMessage>)> {
                     if message.data_message_type == DataChannelMessageType::Control {
                         let mut buf = &message.payload[..];
-                        if MessageType::unmarshal(&mut buf)? == MessageType::DataChannelOpen {
+                        let message_type = MessageType::unmarshal(&mut buf)?;
+                        if message_type == MessageType::DataChannelOpen {
                             debug!("DataChannelOpen for association_handle {} and stream_id {} and data_message_type {:?}",
                             message.association_handle,
                             message.stream_id,
@@ -58,26 +60,46 @@ impl Handler for DataChannelHandler {
                             let (unordered, reliability_type) =
                                 get_reliability_params(data_channel_open.channel_type);
 
+                            let params = DataChannelMessageParams {
+                                unordered,
+                                reliability_type,
+                                reliability_parameter: data_channel_open.reliability_parameter,
+                            };
+
                             let payload = Message::DataChannelAck(DataChannelAck {}).marshal()?;
                             Ok((
                                 Some(ApplicationMessage {
                                     association_handle: message.association_handle,
                                     stream_id: message.stream_id,
-                                    data_channel_event: DataChannelEvent::Open,
+                                    data_channel_event: DataChannelEvent::Open {
+                                        label: String::from_utf8(data_channel_open.label)?,
+                                        protocol: String::from_utf8(data_channel_open.protocol)?,
+                                        params,
+                                    },
                                 }),
                                 Some(DataChannelMessage {
                                     association_handle: message.association_handle,
                                     stream_id: message.stream_id,
                                     data_message_type: DataChannelMessageType::Control,
-                                    params: Some(DataChannelMessageParams {
-                                        unordered,
-                                        reliability_type,
-                                        reliability_parameter: data_channel_open
-                                            .reliability_parameter,
-                                    }),
+                                    params: Some(params),
                                     payload,
                                 }),
                             ))
+                        } else if message_type == MessageType::DataChannelAck {
+                            // ack is only accepted for data channels opened by server, which is
+                            // checked by GatewayHandler against transport states
+                            debug!(
+                                "DataChannelAck for association_handle {} and stream_id {}",
+                                message.association_handle, message.stream_id
+                            );
+                            Ok((
+                                Some(ApplicationMessage {
+                                    association_handle: message.association_handle,
+                                    stream_id: message.stream_id,
+                                    data_channel_event: DataChannelEvent::Ack,
+                                }),
+                                None,
+                            ))
                         } else {
                             Ok((None, None))
                         }
@@ -86,7 +108,10 @@ impl Handler for DataChannelHandler {
                             Some(ApplicationMessage {
                                 association_handle: message.association_handle,
                                 stream_id: message.stream_id,
-                                data_channel_event: DataChannelEvent::Message(message.payload),
+                                data_channel_event: DataChannelEvent::Message(
+                                    message.data_message_type,
+                                    message.payload,
+                                ),
                             }),
                             None,
                         ))
@@ -139,18 +164,58 @@ impl Handler for DataChannelHandler {
             if let MessageEvent::Dtls(DTLSMessageEvent::DataChannel(message)) = msg.message {
                 debug!("send application message {:?}", msg.transport.peer_addr);
 
-                if let DataChannelEvent::Message(payload) = message.data_channel_event {
+                if let DataChannelEvent::Message(data_message_type, payload) =
+                    message.data_channel_event
+                {
                     self.transmits.push_back(TaggedMessageEvent {
                         now: msg.now,
                         transport: msg.transport,
                         message: MessageEvent::Dtls(DTLSMessageEvent::Sctp(DataChannelMessage {
                             association_handle: message.association_handle,
                             stream_id: message.stream_id,
-                            data_message_type: DataChannelMessageType::Text,
+                            data_message_type,
                             params: None,
                             payload,
                         })),
                     });
+                } else if let DataChannelEvent::Open {
+                    label,
+                    protocol,
+                    params,
+                } = message.data_channel_event
+                {
+                    let try_write = || -> Result<BytesMut> {
+                        Message::DataChannelOpen(DataChannelOpen {
+                            channel_type: get_channel_type(&params),
+                            priority: CHANNEL_PRIORITY_NORMAL,
+                            reliability_parameter: params.reliability_parameter,
+                            label: label.into_bytes(),
+                            protocol: protocol.into_bytes(),
+                        })
+                        .marshal()
+                    };
+                    match try_write() {
+                        Ok(payload) => {
+                            debug!("send DataChannelOpen message {:?}", msg.transport.peer_addr);
+                            self.transmits.push_back(TaggedMessageEvent {
+                                now: msg.now,
+                                transport: msg.transport,
+                                message: MessageEvent::Dtls(DTLSMessageEvent::Sctp(
+                                    DataChannelMessage {
+                                        association_handle: message.association_handle,
+                                        stream_id: message.stream_id,
+                                        data_message_type: DataChannelMessageType::Control,
+                                        params: Some(params),
+                                        payload,
+                                    },
+                                )),
+                            });
+                        }
+                        Err(err) => {
+                            error!("try_write with error {}", err);
+                            ctx.fire_exception(Box::new(err))
+                        }
+                    }
                 } else {
                     warn!(
                         "drop unsupported DATACHANNEL message to {}",
@@ -180,3 +245,14 @@ fn get_reliability_params(channel_type: ChannelType) -> (bool, ReliabilityType)
 
     (unordered, reliability_type)
 }
+
+fn get_channel_type(params: &DataChannelMessageParams) -> ChannelType {
+    match (params.reliability_type, params.unordered) {
+        (ReliabilityType::Reliable, false) => ChannelType::Reliable,
+        (ReliabilityType::Reliable, true) => ChannelType::ReliableUnordered,
+        (ReliabilityType::Rexmit, false) => ChannelType::PartialReliableRexmit,
+        (ReliabilityType::Rexmit, true) => ChannelType::PartialReliableRexmitUnordered,
+        (ReliabilityType::Timed, false) => ChannelType::PartialReliableTimed,
+        (ReliabilityType::Timed, true) => ChannelType::PartialReliableTimedUnordered,
+    }
+}
@@ -4,30 +4,34 @@ use std::cell::RefCell;
 use std::collections::VecDeque;
 use std::net::SocketAddr;
 use std::rc::Rc;
+use std::sync::Arc;
 use std::time::Instant;
 
+use crate::configs::server_config::ServerConfig;
 use crate::messages::{DTLSMessageEvent, MessageEvent, TaggedMessageEvent};
-use crate::server::states::ServerStates;
+use crate::server::certificate::RTCDtlsFingerprint;
+use crate::server::{events::ServerEvent, states::ServerStates};
 use dtls::endpoint::EndpointEvent;
 use dtls::extension::extension_use_srtp::SrtpProtectionProfile;
 use dtls::state::State;
 use log::{debug, error, warn};
 use retty::transport::TransportContext;
 use shared::error::{Error, Result};
-use srtp::option::{srtcp_replay_protection, srtp_replay_protection};
+use srtp::option::{
+    srtcp_no_replay_protection, srtcp_replay_protection, srtp_no_replay_protection,
+    srtp_replay_protection,
+};
 use srtp::protection_profile::ProtectionProfile;
 
 /// DtlsHandler implements DTLS Protocol handling
 pub struct DtlsHandler {
-    local_addr: SocketAddr,
     server_states: Rc<RefCell<ServerStates>>,
     transmits: VecDeque<TaggedMessageEvent>,
 }
 
 impl DtlsHandler {
-    pub fn new(local_addr: SocketAddr, server_states: Rc<RefCell<ServerStates>>) -> Self {
+    pub fn new(server_states: Rc<RefCell<ServerStates>>) -> Self {
         DtlsHandler {
-            local_addr,
             server_states: Rc::clone(&server_states),
             transmits: VecDeque::new(),
         }
@@ -55,6 +59,7 @@ impl Handler for DtlsHandler {
 
             let try_read = || -> Result<Vec<BytesMut>> {
                 let mut server_states = self.server_states.borrow_mut();
+                let server_config = Arc::clone(server_states.server_config());
                 let transport = match server_states.get_mut_transport(&four_tuple) {
                     Ok(transport) => transport,
                     Err(err) => {
@@ -64,13 +69,21 @@ impl Handler for DtlsHandler {
                 };
                 let mut messages = vec![];
                 let mut contexts = vec![];
+                let mut rejection = None;
 
                 {
+                    let remote_fingerprints = transport
+                        .candidate()
+                        .remote_connection_credentials()
+                        .dtls_params
+                        .fingerprints
+                        .clone();
+                    let dtls_remote_addr = transport.dtls_remote_addr();
                     let dtls_endpoint = transport.get_mut_dtls_endpoint();
 
                     for message in dtls_endpoint.read(
                         msg.now,
-                        msg.transport.peer_addr,
+                        dtls_remote_addr,
                         Some(msg.transport.local_addr.ip()),
                         msg.transport.ecn,
                         dtls_message,
@@ -78,11 +91,22 @@ impl Handler for DtlsHandler {
                         match message {
                             EndpointEvent::HandshakeComplete => {
                                 if let Some(state) =
-                                    dtls_endpoint.get_connection_state(msg.transport.peer_addr)
+                                    dtls_endpoint.get_connection_state(dtls_remote_addr)
                                 {
                                     debug!("recv dtls handshake complete");
+                                    if let Err(err) = DtlsHandler::verify_remote_certificate(
+                                        state,
+                                        &remote_fingerprints,
+                                    ) {
+                                        warn!(
+                                            "reject DTLS transport of {}: {}",
+                                            msg.transport.peer_addr, err
+                                        );
+                                        rejection = Some(err);
+                                        break;
+                                    }
                                     let (local_context, remote_context) =
-                                        DtlsHandler::update_srtp_contexts(state)?;
+                                        DtlsHandler::update_srtp_contexts(state, &server_config)?;
                                     contexts.push((local_context, remote_context));
                                 } else {
                                     warn!(
@@ -98,12 +122,17 @@ impl Handler for DtlsHandler {
                         }
                     }
 
+                    if rejection.is_some() {
+                        // close_notify alert is sent to peer
+                        dtls_endpoint.close(dtls_remote_addr);
+                    }
+
                     while let Some(transmit) = dtls_endpoint.poll_transmit() {
                         self.transmits.push_back(TaggedMessageEvent {
                             now: transmit.now,
                             transport: TransportContext {
-                                local_addr: self.local_addr,
-                                peer_addr: transmit.remote,
+                                local_addr: msg.transport.local_addr,
+                                peer_addr: msg.transport.peer_addr,
                                 ecn: transmit.ecn,
                             },
                             message: MessageEvent::Dtls(DTLSMessageEvent::Raw(transmit.payload)),
@@ -111,11 +140,29 @@ impl Handler for DtlsHandler {
                     }
                 }
 
+                if let Some(err) = rejection {
+                    server_states.remove_transport(four_tuple);
+                    return Err(err);
+                }
+
+                let is_handshake_complete = !contexts.is_empty();
                 for (local_context, remote_context) in contexts {
                     transport.set_local_srtp_context(local_context);
                     transport.set_remote_srtp_context(remote_context);
                 }
 
+                if is_handshake_complete {
+                    if let Some((session_id, endpoint_id)) =
+                        server_states.find_endpoint(&four_tuple)
+                    {
+                        server_states.emit_event(ServerEvent::DtlsHandshakeCompleted {
+                            session_id,
+                            endpoint_id,
+                            peer_addr: msg.transport.peer_addr,
+                        });
+                    }
+                }
+
                 Ok(messages)
             };
 
@@ -157,6 +204,7 @@ impl Handler for DtlsHandler {
             for session in server_states.get_mut_sessions().values_mut() {
                 for endpoint in session.get_mut_endpoints().values_mut() {
                     for transport in endpoint.get_mut_transports().values_mut() {
+                        let four_tuple = *transport.four_tuple();
                         let dtls_endpoint = transport.get_mut_dtls_endpoint();
                         let remotes: Vec<SocketAddr> =
                             dtls_endpoint.get_connections_keys().copied().collect();
@@ -167,8 +215,8 @@ impl Handler for DtlsHandler {
                             self.transmits.push_back(TaggedMessageEvent {
                                 now: transmit.now,
                                 transport: TransportContext {
-                                    local_addr: self.local_addr,
-                                    peer_addr: transmit.remote,
+                                    local_addr: four_tuple.local_addr,
+                                    peer_addr: four_tuple.peer_addr,
                                     ecn: transmit.ecn,
                                 },
                                 message: MessageEvent::Dtls(DTLSMessageEvent::Raw(
@@ -227,15 +275,16 @@ impl Handler for DtlsHandler {
                 let mut try_write = || -> Result<()> {
                     let mut server_states = self.server_states.borrow_mut();
                     let transport = server_states.get_mut_transport(&four_tuple)?;
+                    let dtls_remote_addr = transport.dtls_remote_addr();
                     let dtls_endpoint = transport.get_mut_dtls_endpoint();
 
-                    dtls_endpoint.write(msg.transport.peer_addr, &dtls_message)?;
+                    dtls_endpoint.write(dtls_remote_addr, &dtls_message)?;
                     while let Some(transmit) = dtls_endpoint.poll_transmit() {
                         self.transmits.push_back(TaggedMessageEvent {
                             now: transmit.now,
                             transport: TransportContext {
-                                local_addr: self.local_addr,
-                                peer_addr: transmit.remote,
+                                local_addr: msg.transport.local_addr,
+                                peer_addr: msg.transport.peer_addr,
                                 ecn: transmit.ecn,
                             },
                             message: MessageEvent::Dtls(DTLSMessageEvent::Raw(transmit.payload)),
@@ -259,35 +308,94 @@ impl Handler for DtlsHandler {
             }
         }
 
+        // records of transports closed by ServerStates::close_endpoint are already encrypted
+        {
+            let mut server_states = self.server_states.borrow_mut();
+            while let Some(msg) = server_states.poll_dtls_transmit() {
+                self.transmits.push_back(msg);
+            }
+        }
+
         self.transmits.pop_front()
     }
 }
 
 impl DtlsHandler {
-    const DEFAULT_SESSION_SRTP_REPLAY_PROTECTION_WINDOW: usize = 64;
-    const DEFAULT_SESSION_SRTCP_REPLAY_PROTECTION_WINDOW: usize = 64;
-    pub(crate) fn update_srtp_contexts(
+    /// verify_remote_certificate checks the certificate presented by peer in DTLS handshake
+    /// against the fingerprints advertised in its SDP, RFC 8122 S5
+    pub(crate) fn verify_remote_certificate(
         state: &State,
-    ) -> Result<(srtp::context::Context, srtp::context::Context)> {
-        let profile = match state.srtp_protection_profile() {
+        remote_fingerprints: &[RTCDtlsFingerprint],
+    ) -> Result<()> {
+        let Some(certificate) = state.peer_certificates.first() else {
+            return Err(Error::Other(
+                "remote DTLS certificate is not presented".to_string(),
+            ));
+        };
+
+        for remote_fingerprint in remote_fingerprints {
+            let fingerprint = RTCDtlsFingerprint::new(&remote_fingerprint.algorithm, certificate)?;
+            if fingerprint
+                .value
+                .eq_ignore_ascii_case(&remote_fingerprint.value)
+            {
+                return Ok(());
+            }
+        }
+
+        Err(Error::Other(
+            "remote DTLS certificate does not match any fingerprint in SDP".to_string(),
+        ))
+    }
+
+    /// srtp_protection_profile maps DTLS-SRTP protection profile to the one supported by SRTP
+    /// contexts, whose key and salt lengths are used to extract keying material, RFC 5764 S4.2
+    pub(crate) fn srtp_protection_profile(
+        srtp_protection_profile: SrtpProtectionProfile,
+    ) -> Result<ProtectionProfile> {
+        match srtp_protection_profile {
             SrtpProtectionProfile::Srtp_Aes128_Cm_Hmac_Sha1_80 => {
-                ProtectionProfile::Aes128CmHmacSha1_80
+                Ok(ProtectionProfile::Aes128CmHmacSha1_80)
             }
-            SrtpProtectionProfile::Srtp_Aead_Aes_128_Gcm => ProtectionProfile::AeadAes128Gcm,
-            _ => return Err(Error::ErrNoSuchSrtpProfile),
-        };
+            SrtpProtectionProfile::Srtp_Aead_Aes_128_Gcm => Ok(ProtectionProfile::AeadAes128Gcm),
+            _ => Err(Error::ErrNoSuchSrtpProfile),
+        }
+    }
+
+    /// update_srtp_contexts creates local and remote SRTP contexts with the negotiated protection
+    /// profile, which must be one of srtp_protection_profiles allowed by ServerConfig, and the
+    /// replay protection windows of ServerConfig
+    pub(crate) fn update_srtp_contexts(
+        state: &State,
+        server_config: &ServerConfig,
+    ) -> Result<(srtp::context::Context, srtp::context::Context)> {
+        let srtp_protection_profile = state.srtp_protection_profile();
+        if !server_config
+            .srtp_protection_profiles()
+            .contains(&srtp_protection_profile)
+        {
+            return Err(Error::Other(format!(
+                "SRTP protection profile {:?} is not allowed by ServerConfig",
+                srtp_protection_profile
+            )));
+        }
+        let profile = DtlsHandler::srtp_protection_profile(srtp_protection_profile)?;
 
         let mut srtp_config = srtp::config::Config {
             profile,
             ..Default::default()
         };
-        /*if self.setting_engine.replay_protection.srtp != 0 {
-            srtp_config.remote_rtp_options = Some(srtp::option::srtp_replay_protection(
-                self.setting_engine.replay_protection.srtp,
-            ));
-        } else if self.setting_engine.disable_srtp_replay_protection {
-            srtp_config.remote_rtp_options = Some(srtp::option::srtp_no_replay_protection());
-        }*/
+        srtp_config.remote_rtp_options = Some(if server_config.srtp_replay_protection_window > 0 {
+            srtp_replay_protection(server_config.srtp_replay_protection_window)
+        } else {
+            srtp_no_replay_protection()
+        });
+        srtp_config.remote_rtcp_options =
+            Some(if server_config.srtcp_replay_protection_window > 0 {
+                srtcp_replay_protection(server_config.srtcp_replay_protection_window)
+            } else {
+                srtcp_no_replay_protection()
+            });
 
         srtp_config.extract_session_keys_from_dtls(state, false)?;
 
@@ -303,20 +411,8 @@ impl DtlsHandler {
             &srtp_config.keys.remote_master_key,
             &srtp_config.keys.remote_master_salt,
             srtp_config.profile,
-            if srtp_config.remote_rtp_options.is_none() {
-                Some(srtp_replay_protection(
-                    Self::DEFAULT_SESSION_SRTP_REPLAY_PROTECTION_WINDOW,
-                ))
-            } else {
-                srtp_config.remote_rtp_options
-            },
-            if srtp_config.remote_rtcp_options.is_none() {
-                Some(srtcp_replay_protection(
-                    Self::DEFAULT_SESSION_SRTCP_REPLAY_PROTECTION_WINDOW,
-                ))
-            } else {
-                srtp_config.remote_rtcp_options
-            },
+            srtp_config.remote_rtp_options,
+            srtp_config.remote_rtcp_options,
         )?;
 
         Ok((local_context, remote_context))
@@ -3,20 +3,31 @@ use crate::description::{
     RTCSessionDescription,
 };
 use crate::endpoint::candidate::Candidate;
+use crate::endpoint::Endpoint;
 use crate::messages::{
-    ApplicationMessage, DTLSMessageEvent, DataChannelEvent, MessageEvent, RTPMessageEvent,
-    STUNMessageEvent, TaggedMessageEvent,
+    ApplicationMessage, DTLSMessageEvent, DataChannelEvent, DataChannelMessageParams,
+    DataChannelMessageType, MessageEvent, RTPMessageEvent, STUNMessageEvent, TaggedMessageEvent,
 };
-use crate::server::states::ServerStates;
+use crate::server::{
+    events::ServerEvent,
+    signaling::{SignalingMessage, SignalingRequest},
+    states::ServerStates,
+};
+use crate::types::{EndpointId, Mid, Rid};
 use bytes::BytesMut;
 use log::{debug, info, trace, warn};
 use retty::channel::{Context, Handler};
 use retty::transport::TransportContext;
+use rtcp::header::PacketType;
+use rtcp::payload_feedbacks::full_intra_request::FullIntraRequest;
+use rtcp::payload_feedbacks::picture_loss_indication::PictureLossIndication;
+use rtcp::payload_feedbacks::receiver_estimated_maximum_bitrate::ReceiverEstimatedMaximumBitrate;
 use shared::error::{Error, Result};
 use std::cell::RefCell;
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 use std::ops::{Add, Sub};
 use std::rc::Rc;
+use std::sync::Arc;
 use std::time::Duration;
 use std::time::Instant;
 use stun::attributes::{
@@ -143,9 +154,9 @@ impl Handler for GatewayHandler {
         now: Instant,
     ) {
         // terminate timeout here, no more ctx.fire_handle_timeout(now);
+        let mut server_states = self.server_states.borrow_mut();
         if self.next_timeout <= now {
             let mut four_tuples = vec![];
-            let mut server_states = self.server_states.borrow_mut();
             for session in server_states.get_mut_sessions().values_mut() {
                 for endpoint in session.get_mut_endpoints().values_mut() {
                     for transport in endpoint.get_mut_transports().values_mut() {
@@ -158,9 +169,24 @@ impl Handler for GatewayHandler {
             for four_tuple in four_tuples {
                 server_states.remove_transport(four_tuple);
             }
+            server_states.remove_expired_candidates(now);
 
             self.next_timeout = self.next_timeout.add(self.idle_timeout);
         }
+
+        server_states.update_ice_connection_states(now);
+
+        // keyframe requests throttled within the last interval are sent once it ends
+        for message in server_states.poll_deferred_keyframe_requests(now) {
+            self.transmits.push_back(message);
+        }
+
+        // endpoints may need renegotiation after other endpoints left
+        let messages =
+            GatewayHandler::create_renegotiation_offer_message_events(&mut server_states, now);
+        for message in messages {
+            self.transmits.push_back(message);
+        }
     }
 
     fn poll_timeout(
@@ -171,6 +197,21 @@ impl Handler for GatewayHandler {
         if self.next_timeout < *eto {
             *eto = self.next_timeout;
         }
+        {
+            let server_states = self.server_states.borrow();
+            if let Some(next_consent_timeout) = server_states.next_consent_timeout() {
+                if next_consent_timeout < *eto {
+                    *eto = next_consent_timeout;
+                }
+            }
+            if let Some(next_keyframe_request_timeout) =
+                server_states.next_deferred_keyframe_request_timeout()
+            {
+                if next_keyframe_request_timeout < *eto {
+                    *eto = next_keyframe_request_timeout;
+                }
+            }
+        }
         ctx.fire_poll_timeout(eto);
     }
 
@@ -181,10 +222,18 @@ impl Handler for GatewayHandler {
         if let Some(msg) = ctx.fire_poll_write() {
             self.transmits.push_back(msg);
         }
+        // messages pushed by application through ServerStates
+        let mut server_states = self.server_states.borrow_mut();
+        while let Some(msg) = server_states.poll_transmit() {
+            self.transmits.push_back(msg);
+        }
         self.transmits.pop_front()
     }
 }
 
+// publisher's mid, and its ssrc or simulcast rid, which a subscriber's ssrc is forwarded from
+type TrackSource = (Mid, Option<u32>, Option<Rid>);
+
 impl GatewayHandler {
     fn handle_stun_message(
         server_states: &mut ServerStates,
@@ -204,6 +253,8 @@ impl GatewayHandler {
         };
 
         GatewayHandler::add_endpoint(server_states, &request, &candidate, &transport_context)?;
+        // every valid STUN binding request grants consent to the transport, RFC 7675 S5.1
+        server_states.refresh_consent(&(&transport_context).into(), now);
 
         let mut response = stun::message::Message::new();
         response.build(&[
@@ -241,20 +292,33 @@ impl GatewayHandler {
         message: ApplicationMessage,
     ) -> Result<Vec<TaggedMessageEvent>> {
         match message.data_channel_event {
-            DataChannelEvent::Open => GatewayHandler::handle_datachannel_open(
-                server_states,
-                now,
-                transport_context,
-                message.association_handle,
-                message.stream_id,
-            ),
-            DataChannelEvent::Message(payload) => GatewayHandler::handle_datachannel_message(
+            DataChannelEvent::Open { label, params, .. } => {
+                GatewayHandler::handle_datachannel_open(
+                    server_states,
+                    now,
+                    transport_context,
+                    message.association_handle,
+                    message.stream_id,
+                    label,
+                    params,
+                )
+            }
+            DataChannelEvent::Message(data_message_type, payload) => {
+                GatewayHandler::handle_datachannel_message(
+                    server_states,
+                    now,
+                    transport_context,
+                    message.association_handle,
+                    message.stream_id,
+                    data_message_type,
+                    payload,
+                )
+            }
+            DataChannelEvent::Ack => GatewayHandler::handle_datachannel_ack(
                 server_states,
-                now,
                 transport_context,
                 message.association_handle,
                 message.stream_id,
-                payload,
             ),
             DataChannelEvent::Close => GatewayHandler::handle_datachannel_close(
                 server_states,
@@ -272,12 +336,25 @@ impl GatewayHandler {
         transport_context: TransportContext,
         association_handle: usize,
         stream_id: u16,
+        label: String,
+        params: DataChannelMessageParams,
     ) -> Result<Vec<TaggedMessageEvent>> {
         let four_tuple = (&transport_context).into();
         let (session_id, endpoint_id) = server_states
             .find_endpoint(&four_tuple)
             .ok_or(Error::ErrClientTransportNotSet)?;
 
+        // the first data channel is used for signaling, the others are relayed between endpoints
+        let transport = server_states.get_mut_transport(&four_tuple)?;
+        if let (Some(_), Some(_)) = transport.association_handle_and_stream_id() {
+            info!(
+                "{}/{}: data channel {} is ready for {:?}",
+                session_id, endpoint_id, label, four_tuple
+            );
+            transport.add_data_channel(association_handle, stream_id, label, params);
+            return Ok(vec![]);
+        }
+
         let session = server_states
             .get_mut_session(&session_id)
             .ok_or(Error::Other(format!(
@@ -290,16 +367,14 @@ impl GatewayHandler {
         for (&other_endpoint_id, other_endpoint) in endpoints.iter() {
             if other_endpoint_id != endpoint_id {
                 let other_transceivers = other_endpoint.get_transceivers();
-                for (other_mid_value, other_transceiver) in other_transceivers.iter() {
+                for other_transceiver in other_transceivers.values() {
                     if other_transceiver.direction == RTCRtpTransceiverDirection::Recvonly {
-                        let mut transceiver = other_transceiver.clone();
-                        transceiver.mid = format!("{}-{}", other_endpoint_id, other_mid_value);
-                        transceiver.direction = RTCRtpTransceiverDirection::Sendonly;
-                        new_transceivers.push(transceiver);
+                        new_transceivers.push((other_endpoint_id, other_transceiver.clone()));
                     }
                 }
             }
         }
+        let server_config = Arc::clone(&session.session_config().server_config);
 
         let endpoint = session
             .get_mut_endpoint(&endpoint_id)
@@ -322,13 +397,30 @@ impl GatewayHandler {
         );
         endpoint.set_renegotiation_needed(!new_transceivers.is_empty());
 
-        let (mids, transceivers) = endpoint.get_mut_mids_and_transceivers();
-        for transceiver in new_transceivers {
-            mids.push(transceiver.mid.clone());
-            transceivers.insert(transceiver.mid.clone(), transceiver);
+        let is_renegotiation_needed = endpoint.is_renegotiation_needed();
+        for (other_endpoint_id, other_transceiver) in new_transceivers.iter() {
+            endpoint.add_forwarding_transceiver(
+                *other_endpoint_id,
+                other_transceiver,
+                server_config
+                    .media_config
+                    .get_codecs_by_kind(other_transceiver.kind),
+            );
+        }
+
+        if server_config.auto_subscribe {
+            for (other_endpoint_id, other_transceiver) in new_transceivers {
+                session.add_subscription(other_endpoint_id, &other_transceiver.mid, endpoint_id);
+            }
         }
 
-        if endpoint.is_renegotiation_needed() {
+        server_states.emit_event(ServerEvent::DataChannelReady {
+            session_id,
+            endpoint_id,
+            peer_addr: transport_context.peer_addr,
+        });
+
+        if is_renegotiation_needed && server_config.datachannel_signaling {
             Ok(vec![GatewayHandler::create_offer_message_event(
                 server_states,
                 now,
@@ -341,30 +433,439 @@ impl GatewayHandler {
         }
     }
 
-    fn handle_datachannel_close(
-        _server_states: &mut ServerStates,
-        _now: Instant,
-        _transport_context: TransportContext,
-        _association_handle: usize,
-        _stream_id: u16,
+    fn handle_datachannel_ack(
+        server_states: &mut ServerStates,
+        transport_context: TransportContext,
+        association_handle: usize,
+        stream_id: u16,
     ) -> Result<Vec<TaggedMessageEvent>> {
-        //TODO: handle datachannel close event!
-        // clean up resources, like sctp_association, endpoint, etc.
+        let four_tuple = (&transport_context).into();
+        let (session_id, endpoint_id) = server_states
+            .find_endpoint(&four_tuple)
+            .ok_or(Error::ErrClientTransportNotSet)?;
+        let transport = server_states.get_mut_transport(&four_tuple)?;
+        if !transport.remove_pending_data_channel_ack(association_handle, stream_id) {
+            debug!(
+                "{}/{}: ignore unexpected DataChannelAck with association_handle {} and stream_id {}",
+                session_id, endpoint_id, association_handle, stream_id
+            );
+            return Ok(vec![]);
+        }
+        let (label, _) = transport
+            .get_data_channels()
+            .get(&(association_handle, stream_id))
+            .cloned()
+            .ok_or(Error::Other(format!(
+                "can't find data channel with association_handle {} and stream_id {}",
+                association_handle, stream_id
+            )))?;
+        info!(
+            "{}/{}: data channel {} is opened for {:?}",
+            session_id, endpoint_id, label, four_tuple
+        );
+
+        server_states.emit_event(ServerEvent::DataChannelOpened {
+            session_id,
+            endpoint_id,
+            label,
+        });
         Ok(vec![])
     }
 
+    fn handle_datachannel_close(
+        server_states: &mut ServerStates,
+        now: Instant,
+        transport_context: TransportContext,
+        association_handle: usize,
+        stream_id: u16,
+    ) -> Result<Vec<TaggedMessageEvent>> {
+        let four_tuple = (&transport_context).into();
+        let (session_id, endpoint_id) = server_states
+            .find_endpoint(&four_tuple)
+            .ok_or(Error::ErrClientTransportNotSet)?;
+
+        let session = server_states
+            .get_mut_session(&session_id)
+            .ok_or(Error::Other(format!(
+                "can't find session id {}",
+                session_id
+            )))?;
+        let endpoint = session
+            .get_mut_endpoint(&endpoint_id)
+            .ok_or(Error::Other(format!(
+                "can't find endpoint id {}",
+                endpoint_id
+            )))?;
+        let transports = endpoint.get_mut_transports();
+        let transport = transports.get_mut(&four_tuple).ok_or(Error::Other(format!(
+            "can't find transport for endpoint id {} with {:?}",
+            endpoint_id, four_tuple
+        )))?;
+        if transport.association_handle_and_stream_id()
+            != (Some(association_handle), Some(stream_id))
+        {
+            // not the data channel used for signaling
+            if let Some(label) = transport.remove_data_channel(association_handle, stream_id) {
+                info!(
+                    "{}/{}: data channel {} is closed for {:?}",
+                    session_id,
+                    endpoint_id,
+                    label,
+                    transport.four_tuple()
+                );
+            }
+            return Ok(vec![]);
+        }
+        transport.clear_association_handle_and_stream_id();
+        info!(
+            "{}/{}: data channel is closed for {:?}",
+            session_id,
+            endpoint_id,
+            transport.four_tuple()
+        );
+
+        // peers drop the departed endpoint's tracks by renegotiation
+        for mid in session.remove_published_tracks(endpoint_id) {
+            server_states.emit_event(ServerEvent::TrackUnpublished {
+                session_id,
+                endpoint_id,
+                mid,
+            });
+        }
+
+        if !server_states.server_config().datachannel_signaling {
+            return Ok(vec![]);
+        }
+        let peers = GatewayHandler::get_other_datachannel_transport_contexts(
+            server_states,
+            &transport_context,
+        )?;
+        let mut messages = vec![];
+        for (other_transport_context, association_handle, stream_id, is_renegotiation_needed) in
+            peers
+        {
+            if is_renegotiation_needed {
+                messages.push(GatewayHandler::create_offer_message_event(
+                    server_states,
+                    now,
+                    other_transport_context,
+                    association_handle,
+                    stream_id,
+                )?);
+            }
+        }
+
+        Ok(messages)
+    }
+
     fn handle_datachannel_message(
         server_states: &mut ServerStates,
         now: Instant,
         transport_context: TransportContext,
         association_handle: usize,
         stream_id: u16,
+        data_message_type: DataChannelMessageType,
         payload: BytesMut,
     ) -> Result<Vec<TaggedMessageEvent>> {
-        let request_sdp_str = String::from_utf8(payload.to_vec())?;
-        let request_sdp = serde_json::from_str::<RTCSessionDescription>(&request_sdp_str)
-            .map_err(|err| Error::Other(err.to_string()))?;
+        let four_tuple = (&transport_context).into();
+        let (session_id, endpoint_id) = server_states
+            .find_endpoint(&four_tuple)
+            .ok_or(Error::ErrClientTransportNotSet)?;
+
+        let transport = server_states.get_mut_transport(&four_tuple)?;
+        if transport.association_handle_and_stream_id()
+            != (Some(association_handle), Some(stream_id))
+        {
+            // data channel opened by server talks to application, not to other endpoints
+            if transport.is_server_data_channel(association_handle, stream_id) {
+                let label = transport
+                    .get_data_channels()
+                    .get(&(association_handle, stream_id))
+                    .map(|(label, _)| label.clone())
+                    .ok_or(Error::Other(format!(
+                        "can't find data channel with association_handle {} and stream_id {}",
+                        association_handle, stream_id
+                    )))?;
+                server_states.emit_event(ServerEvent::DataChannelMessage {
+                    session_id,
+                    endpoint_id,
+                    label,
+                    payload,
+                    is_binary: data_message_type == DataChannelMessageType::Binary,
+                });
+                return Ok(vec![]);
+            }
+            return GatewayHandler::relay_datachannel_message(
+                server_states,
+                now,
+                transport_context,
+                association_handle,
+                stream_id,
+                data_message_type,
+                payload,
+            );
+        }
+
+        let request = SignalingRequest::decode(&payload)?;
+
+        match request {
+            SignalingRequest::SessionDescription(request_sdp) => {
+                GatewayHandler::handle_session_description(
+                    server_states,
+                    now,
+                    transport_context,
+                    association_handle,
+                    stream_id,
+                    request_sdp,
+                )
+            }
+            SignalingRequest::Envelope(message) => {
+                server_states
+                    .get_mut_transport(&four_tuple)?
+                    .set_signaling_envelope(true);
+
+                match message.and_then(|message| {
+                    GatewayHandler::handle_signaling_message(
+                        server_states,
+                        now,
+                        transport_context,
+                        association_handle,
+                        stream_id,
+                        message,
+                    )
+                }) {
+                    Ok(messages) => Ok(messages),
+                    Err(err) => {
+                        // report failure back to endpoint instead of firing exception
+                        warn!(
+                            "{}/{}: failed to handle signaling message with error {}",
+                            session_id, endpoint_id, err
+                        );
+                        Ok(vec![GatewayHandler::create_signaling_message_event(
+                            server_states,
+                            now,
+                            transport_context,
+                            association_handle,
+                            stream_id,
+                            SignalingMessage::Error {
+                                message: err.to_string(),
+                            },
+                        )?])
+                    }
+                }
+            }
+        }
+    }
+
+    fn relay_datachannel_message(
+        server_states: &mut ServerStates,
+        now: Instant,
+        transport_context: TransportContext,
+        association_handle: usize,
+        stream_id: u16,
+        data_message_type: DataChannelMessageType,
+        payload: BytesMut,
+    ) -> Result<Vec<TaggedMessageEvent>> {
+        let four_tuple = (&transport_context).into();
+        let (session_id, endpoint_id) = server_states
+            .find_endpoint(&four_tuple)
+            .ok_or(Error::ErrClientTransportNotSet)?;
+        let (label, params) = server_states
+            .get_mut_transport(&four_tuple)?
+            .get_data_channels()
+            .get(&(association_handle, stream_id))
+            .cloned()
+            .ok_or(Error::Other(format!(
+                "can't find data channel with association_handle {} and stream_id {}",
+                association_handle, stream_id
+            )))?;
+        let data_channel_relay_filter = server_states
+            .server_config()
+            .data_channel_relay_filter
+            .clone();
+
+        let session = server_states
+            .get_session(&session_id)
+            .ok_or(Error::Other(format!(
+                "can't find session id {}",
+                session_id
+            )))?;
+        let mut messages = vec![];
+        for (&other_endpoint_id, other_endpoint) in session.get_endpoints() {
+            if other_endpoint_id == endpoint_id {
+                continue;
+            }
+            // one data channel with the same label is enough for each endpoint, and it must have
+            // the same ordering and reliability, otherwise the relayed messages would be
+            // delivered with different guarantees than the sender asked for
+            let Some((other_four_tuple, (other_association_handle, other_stream_id))) =
+                other_endpoint.get_transports().iter().find_map(
+                    |(other_four_tuple, other_transport)| {
+                        other_transport.get_data_channels().iter().find_map(
+                            |(&id, (other_label, other_params))| {
+                                (*other_label == label
+                                    && *other_params == params
+                                    && !other_transport.is_server_data_channel(id.0, id.1))
+                                .then_some((*other_four_tuple, id))
+                            },
+                        )
+                    },
+                )
+            else {
+                if other_endpoint
+                    .get_transports()
+                    .values()
+                    .any(|other_transport| {
+                        other_transport
+                            .get_data_channels()
+                            .iter()
+                            .any(|(&id, (other_label, _))| {
+                                *other_label == label
+                                    && !other_transport.is_server_data_channel(id.0, id.1)
+                            })
+                    })
+                {
+                    debug!(
+                        "{}/{}: skip relaying data channel {} to {} with different params",
+                        session_id, endpoint_id, label, other_endpoint_id
+                    );
+                }
+                continue;
+            };
+
+            let payload = if let Some(data_channel_relay_filter) = &data_channel_relay_filter {
+                let Some(payload) = data_channel_relay_filter(
+                    session_id,
+                    endpoint_id,
+                    other_endpoint_id,
+                    &label,
+                    &payload,
+                ) else {
+                    continue;
+                };
+                payload
+            } else {
+                payload.clone()
+            };
+
+            messages.push(TaggedMessageEvent {
+                now,
+                transport: TransportContext {
+                    local_addr: other_four_tuple.local_addr,
+                    peer_addr: other_four_tuple.peer_addr,
+                    ecn: None,
+                },
+                message: MessageEvent::Dtls(DTLSMessageEvent::DataChannel(ApplicationMessage {
+                    association_handle: other_association_handle,
+                    stream_id: other_stream_id,
+                    data_channel_event: DataChannelEvent::Message(data_message_type, payload),
+                })),
+            });
+        }
+
+        Ok(messages)
+    }
+
+    fn handle_signaling_message(
+        server_states: &mut ServerStates,
+        now: Instant,
+        transport_context: TransportContext,
+        association_handle: usize,
+        stream_id: u16,
+        message: SignalingMessage,
+    ) -> Result<Vec<TaggedMessageEvent>> {
+        let four_tuple = (&transport_context).into();
+        let (session_id, endpoint_id) = server_states
+            .find_endpoint(&four_tuple)
+            .ok_or(Error::ErrClientTransportNotSet)?;
+
+        match message {
+            SignalingMessage::Offer(request_sdp) | SignalingMessage::Answer(request_sdp) => {
+                GatewayHandler::handle_session_description(
+                    server_states,
+                    now,
+                    transport_context,
+                    association_handle,
+                    stream_id,
+                    request_sdp,
+                )
+            }
+            SignalingMessage::IceRestart => {
+                let offer = server_states.restart_ice(session_id, endpoint_id, Some(four_tuple))?;
+                Ok(vec![GatewayHandler::create_signaling_message_event(
+                    server_states,
+                    now,
+                    transport_context,
+                    association_handle,
+                    stream_id,
+                    SignalingMessage::Offer(offer),
+                )?])
+            }
+            SignalingMessage::Mute { mid, muted } => {
+                server_states.set_track_muted(session_id, endpoint_id, mid, muted)?;
+                Ok(vec![])
+            }
+            SignalingMessage::Subscribe { publisher_id, mid } => {
+                server_states.subscribe(session_id, endpoint_id, publisher_id, mid)?;
+                Ok(vec![])
+            }
+            SignalingMessage::Unsubscribe { publisher_id, mid } => {
+                server_states.unsubscribe(session_id, endpoint_id, publisher_id, mid)?;
+                Ok(vec![])
+            }
+            SignalingMessage::LayerRequest {
+                publisher_id,
+                mid,
+                rid,
+            } => {
+                server_states.select_layer(session_id, endpoint_id, publisher_id, mid, rid)?;
+                Ok(vec![])
+            }
+            SignalingMessage::Error { message } => {
+                warn!(
+                    "{}/{}: signaling error reported by endpoint: {}",
+                    session_id, endpoint_id, message
+                );
+                Ok(vec![])
+            }
+            SignalingMessage::AppData { data, .. } => {
+                let peers = GatewayHandler::get_other_datachannel_transport_contexts(
+                    server_states,
+                    &transport_context,
+                )?;
+                let mut messages = Vec::with_capacity(peers.len());
+                for (other_transport_context, association_handle, stream_id, _) in peers {
+                    match GatewayHandler::create_signaling_message_event(
+                        server_states,
+                        now,
+                        other_transport_context,
+                        association_handle,
+                        stream_id,
+                        SignalingMessage::AppData {
+                            endpoint_id: Some(endpoint_id),
+                            data: data.clone(),
+                        },
+                    ) {
+                        Ok(message) => messages.push(message),
+                        Err(err) => debug!(
+                            "skip relaying app data to {:?} with error {}",
+                            other_transport_context.peer_addr, err
+                        ),
+                    }
+                }
+                Ok(messages)
+            }
+        }
+    }
 
+    fn handle_session_description(
+        server_states: &mut ServerStates,
+        now: Instant,
+        transport_context: TransportContext,
+        association_handle: usize,
+        stream_id: u16,
+        request_sdp: RTCSessionDescription,
+    ) -> Result<Vec<TaggedMessageEvent>> {
         let four_tuple = (&transport_context).into();
         let (session_id, endpoint_id) = server_states
             .find_endpoint(&four_tuple)
@@ -378,28 +879,24 @@ impl GatewayHandler {
                     Some(four_tuple),
                     request_sdp,
                 )?;
-                let answer_str =
-                    serde_json::to_string(&answer).map_err(|err| Error::Other(err.to_string()))?;
-
-                let peers = GatewayHandler::get_other_datachannel_transport_contexts(
-                    server_states,
-                    &transport_context,
-                )?;
+                let peers = if server_states.server_config().datachannel_signaling {
+                    GatewayHandler::get_other_datachannel_transport_contexts(
+                        server_states,
+                        &transport_context,
+                    )?
+                } else {
+                    vec![]
+                };
                 let mut messages = Vec::with_capacity(peers.len() + 1);
 
-                messages.push(TaggedMessageEvent {
+                messages.push(GatewayHandler::create_signaling_message_event(
+                    server_states,
                     now,
-                    transport: transport_context,
-                    message: MessageEvent::Dtls(DTLSMessageEvent::DataChannel(
-                        ApplicationMessage {
-                            association_handle,
-                            stream_id,
-                            data_channel_event: DataChannelEvent::Message(BytesMut::from(
-                                answer_str.as_str(),
-                            )),
-                        },
-                    )),
-                });
+                    transport_context,
+                    association_handle,
+                    stream_id,
+                    SignalingMessage::Answer(answer),
+                )?);
 
                 // trigger other endpoints' create_offer()
                 for (
@@ -423,7 +920,7 @@ impl GatewayHandler {
                 Ok(messages)
             }
             RTCSdpType::Answer => {
-                server_states.accept_answer(session_id, endpoint_id, four_tuple, request_sdp)?;
+                server_states.accept_answer(session_id, endpoint_id, request_sdp)?;
                 Ok(vec![])
             }
             _ => Err(Error::Other(format!(
@@ -444,16 +941,19 @@ impl GatewayHandler {
             .get_mut_transport(&(&transport_context).into())?
             .keep_alive();
 
-        //TODO: Selective Forwarding RTP Packets
-        let peers =
-            GatewayHandler::get_other_media_transport_contexts(server_states, &transport_context)?;
+        let peers = GatewayHandler::get_subscriber_rtp_packets(
+            server_states,
+            now,
+            &transport_context,
+            &rtp_packet,
+        )?;
 
         let mut outgoing_messages = Vec::with_capacity(peers.len());
-        for transport in peers {
+        for (transport, rtp_packet) in peers {
             outgoing_messages.push(TaggedMessageEvent {
                 now,
                 transport,
-                message: MessageEvent::Rtp(RTPMessageEvent::Rtp(rtp_packet.clone())),
+                message: MessageEvent::Rtp(RTPMessageEvent::Rtp(rtp_packet)),
             });
         }
 
@@ -471,20 +971,19 @@ impl GatewayHandler {
             .get_mut_transport(&(&transport_context).into())?
             .keep_alive();
 
-        //TODO: Selective Forwarding RTCP Packets
-        let peers =
-            GatewayHandler::get_other_media_transport_contexts(server_states, &transport_context)?;
-
-        let mut outgoing_messages = Vec::with_capacity(peers.len());
-        for transport in peers {
-            outgoing_messages.push(TaggedMessageEvent {
-                now,
-                transport,
-                message: MessageEvent::Rtp(RTPMessageEvent::Rtcp(rtcp_packets.clone())),
-            });
-        }
+        GatewayHandler::forward_sender_reports(
+            server_states,
+            now,
+            &transport_context,
+            &rtcp_packets,
+        )?;
 
-        Ok(outgoing_messages)
+        GatewayHandler::get_publisher_rtcp_messages(
+            server_states,
+            now,
+            &transport_context,
+            &rtcp_packets,
+        )
     }
 
     fn check_stun_message(
@@ -570,7 +1069,7 @@ impl GatewayHandler {
                             },
                             association_handle,
                             stream_id,
-                            other_endpoint.is_renegotiation_needed(),
+                            other_endpoint.can_renegotiate(),
                         ));
                     } else {
                         // data channel is not ready yet for other_endpoint_id's other_four_tuple.
@@ -588,46 +1087,360 @@ impl GatewayHandler {
         Ok(peers)
     }
 
-    fn get_other_media_transport_contexts(
+    /// forward_sender_reports passes publisher's NTP/RTP timestamp mapping to subscribers'
+    /// interceptors, which generate sender reports for the forwarded streams
+    fn forward_sender_reports(
         server_states: &mut ServerStates,
+        now: Instant,
         transport_context: &TransportContext,
-    ) -> Result<Vec<TransportContext>> {
+        rtcp_packets: &[Box<dyn rtcp::packet::Packet>],
+    ) -> Result<()> {
         let four_tuple = transport_context.into();
         let (session_id, endpoint_id) = server_states
             .find_endpoint(&four_tuple)
             .ok_or(Error::ErrClientTransportNotSet)?;
         let session = server_states
-            .get_session(&session_id)
+            .get_mut_session(&session_id)
             .ok_or(Error::Other(format!(
                 "can't find session id {}",
                 session_id
             )))?;
+        let endpoint = session
+            .get_endpoint(&endpoint_id)
+            .ok_or(Error::Other(format!(
+                "can't find endpoint id {}",
+                endpoint_id
+            )))?;
 
-        let mut peers = vec![];
-        let endpoints = session.get_endpoints();
-        for (&other_endpoint_id, other_endpoint) in endpoints.iter() {
-            if other_endpoint_id != endpoint_id {
-                let transports = other_endpoint.get_transports();
-                for (other_four_tuple, other_transport) in transports.iter() {
-                    if other_transport.is_local_srtp_context_ready() {
-                        peers.push(TransportContext {
-                            local_addr: other_four_tuple.local_addr,
-                            peer_addr: other_four_tuple.peer_addr,
-                            ecn: transport_context.ecn,
-                        });
-                    } else {
-                        // local_srtp_context is not ready yet for other_endpoint_id's other_four_tuple.
-                        // this transport just joins, but local_srtp_context is still setup
-                        trace!(
-                            "{}/{}'s local_srtp_context is not ready yet for {:?} since it is still setup",
-                            session_id,
-                            other_endpoint_id,
-                            other_four_tuple,
-                        );
+        let mut sender_reports = vec![];
+        for rtcp_packet in rtcp_packets {
+            if let Some(sr) = rtcp_packet
+                .as_any()
+                .downcast_ref::<rtcp::sender_report::SenderReport>()
+            {
+                if let Some(mid) = endpoint.get_mid_by_ssrc(sr.ssrc) {
+                    sender_reports.push((mid.clone(), sr.ssrc, sr.ntp_time, sr.rtp_time));
+                }
+            }
+        }
+
+        for (mid, ssrc, ntp_time, rtp_time) in sender_reports {
+            let subscriber_ids: Vec<EndpointId> = session
+                .get_subscribers(endpoint_id, &mid)
+                .map(|subscribers| subscribers.iter().copied().collect())
+                .unwrap_or_default();
+            let subscriber_mid = format!("{}-{}", endpoint_id, mid);
+            for subscriber_id in subscriber_ids {
+                let Some(subscriber) = session.get_mut_endpoint(&subscriber_id) else {
+                    continue;
+                };
+                let Some((subscriber_ssrc, subscriber_rtp_time)) = subscriber
+                    .get_mut_rtp_rewriter(&subscriber_mid)
+                    .and_then(|rtp_rewriter| rtp_rewriter.translate_rtp_time(ssrc, rtp_time))
+                else {
+                    continue;
+                };
+                subscriber.get_mut_interceptor().forward_sender_report(
+                    now,
+                    subscriber_ssrc,
+                    ntp_time,
+                    subscriber_rtp_time,
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    fn get_publisher_rtcp_messages(
+        server_states: &mut ServerStates,
+        now: Instant,
+        transport_context: &TransportContext,
+        rtcp_packets: &[Box<dyn rtcp::packet::Packet>],
+    ) -> Result<Vec<TaggedMessageEvent>> {
+        let four_tuple = transport_context.into();
+        let (session_id, endpoint_id) = server_states
+            .find_endpoint(&four_tuple)
+            .ok_or(Error::ErrClientTransportNotSet)?;
+        let keyframe_request_interval = server_states.server_config().keyframe_request_interval;
+        let session = server_states
+            .get_mut_session(&session_id)
+            .ok_or(Error::Other(format!(
+                "can't find session id {}",
+                session_id
+            )))?;
+        let endpoint = session
+            .get_endpoint(&endpoint_id)
+            .ok_or(Error::Other(format!(
+                "can't find endpoint id {}",
+                endpoint_id
+            )))?;
+
+        // map media ssrcs of keyframe requests and bitrate estimations back to the tracks of
+        // publishers
+        let mut keyframe_requests = vec![];
+        let mut bitrate_estimations = vec![];
+        let mut publisher_rtcp_packets: HashMap<EndpointId, Vec<Box<dyn rtcp::packet::Packet>>> =
+            HashMap::new();
+        for rtcp_packet in rtcp_packets {
+            let (sender_ssrc, is_fir, media_ssrcs) =
+                if let Some(pli) = rtcp_packet.as_any().downcast_ref::<PictureLossIndication>() {
+                    (pli.sender_ssrc, Some(false), vec![pli.media_ssrc])
+                } else if let Some(fir) = rtcp_packet.as_any().downcast_ref::<FullIntraRequest>() {
+                    (
+                        fir.sender_ssrc,
+                        Some(true),
+                        fir.fir.iter().map(|entry| entry.ssrc).collect(),
+                    )
+                } else if let Some(remb) = rtcp_packet
+                    .as_any()
+                    .downcast_ref::<ReceiverEstimatedMaximumBitrate>()
+                {
+                    (remb.sender_ssrc, None, remb.ssrcs.clone())
+                } else {
+                    if rtcp_packet.header().packet_type == PacketType::ApplicationDefined {
+                        // application defined packets are end to end, pass them to publishers
+                        for publisher_id in endpoint.get_publisher_ids() {
+                            publisher_rtcp_packets
+                                .entry(publisher_id)
+                                .or_default()
+                                .push(rtcp_packet.clone());
+                        }
                     }
+                    continue;
+                };
+
+            let mut publisher_ssrcs: HashMap<EndpointId, Vec<TrackSource>> = HashMap::new();
+            for media_ssrc in media_ssrcs {
+                let Some(rtp_rewriter) = endpoint.get_rtp_rewriter_by_ssrc(media_ssrc) else {
+                    trace!(
+                        "{}/{}'s ssrc {} is not forwarded from any published track",
+                        session_id,
+                        endpoint_id,
+                        media_ssrc,
+                    );
+                    continue;
+                };
+                let (publisher_id, publisher_mid) = rtp_rewriter.publisher();
+                let source = (
+                    publisher_mid.clone(),
+                    rtp_rewriter.source_ssrc(media_ssrc),
+                    rtp_rewriter.target_rid(media_ssrc).cloned(),
+                );
+                if let Some(is_fir) = is_fir {
+                    keyframe_requests.push((publisher_id, source, sender_ssrc, is_fir));
+                } else {
+                    publisher_ssrcs
+                        .entry(publisher_id)
+                        .or_default()
+                        .push(source);
+                }
+            }
+            if let Some(remb) = rtcp_packet
+                .as_any()
+                .downcast_ref::<ReceiverEstimatedMaximumBitrate>()
+            {
+                for (publisher_id, sources) in publisher_ssrcs {
+                    bitrate_estimations.push((publisher_id, sources, remb.clone()));
                 }
             }
         }
+
+        let resolve_ssrc =
+            |publisher: &Endpoint,
+             (mid, source_ssrc, target_rid): (Mid, Option<u32>, Option<Rid>)| {
+                source_ssrc
+                    .or_else(|| target_rid.and_then(|rid| publisher.get_ssrc_by_rid(&mid, &rid)))
+            };
+
+        for (publisher_id, source, sender_ssrc, is_fir) in keyframe_requests {
+            let Some(publisher) = session.get_mut_endpoint(&publisher_id) else {
+                continue;
+            };
+            let publisher_mid = source.0.clone();
+            let Some(ssrc) = resolve_ssrc(publisher, source) else {
+                continue;
+            };
+            let Some(rtcp_packet) = publisher.request_keyframe(
+                ssrc,
+                sender_ssrc,
+                is_fir,
+                now,
+                keyframe_request_interval,
+            ) else {
+                trace!(
+                    "{}/{}'s keyframe request for {}/{}'s ssrc {} is throttled",
+                    session_id,
+                    endpoint_id,
+                    publisher_id,
+                    publisher_mid,
+                    ssrc,
+                );
+                continue;
+            };
+            publisher_rtcp_packets
+                .entry(publisher_id)
+                .or_default()
+                .push(rtcp_packet);
+        }
+
+        for (publisher_id, sources, remb) in bitrate_estimations {
+            let Some(publisher) = session.get_endpoint(&publisher_id) else {
+                continue;
+            };
+            let ssrcs: Vec<u32> = sources
+                .into_iter()
+                .filter_map(|source| resolve_ssrc(publisher, source))
+                .collect();
+            if ssrcs.is_empty() {
+                continue;
+            }
+            publisher_rtcp_packets
+                .entry(publisher_id)
+                .or_default()
+                .push(Box::new(ReceiverEstimatedMaximumBitrate { ssrcs, ..remb }));
+        }
+
+        let mut outgoing_messages = vec![];
+        for (publisher_id, rtcp_packets) in publisher_rtcp_packets {
+            let Some(publisher) = session.get_endpoint(&publisher_id) else {
+                continue;
+            };
+            // one transport is enough for the publisher to receive the feedback
+            let Some(publisher_four_tuple) = publisher.find_srtp_ready_four_tuple() else {
+                trace!(
+                    "{}/{}'s local_srtp_context is not ready yet for any transport",
+                    session_id,
+                    publisher_id,
+                );
+                continue;
+            };
+            outgoing_messages.push(TaggedMessageEvent {
+                now,
+                transport: TransportContext {
+                    local_addr: publisher_four_tuple.local_addr,
+                    peer_addr: publisher_four_tuple.peer_addr,
+                    ecn: transport_context.ecn,
+                },
+                message: MessageEvent::Rtp(RTPMessageEvent::Rtcp(rtcp_packets)),
+            });
+        }
+        Ok(outgoing_messages)
+    }
+
+    fn get_subscriber_rtp_packets(
+        server_states: &mut ServerStates,
+        now: Instant,
+        transport_context: &TransportContext,
+        rtp_packet: &rtp::packet::Packet,
+    ) -> Result<Vec<(TransportContext, rtp::packet::Packet)>> {
+        let four_tuple = transport_context.into();
+        let (session_id, endpoint_id) = server_states
+            .find_endpoint(&four_tuple)
+            .ok_or(Error::ErrClientTransportNotSet)?;
+        let session = server_states
+            .get_mut_session(&session_id)
+            .ok_or(Error::Other(format!(
+                "can't find session id {}",
+                session_id
+            )))?;
+        let endpoint = session
+            .get_mut_endpoint(&endpoint_id)
+            .ok_or(Error::Other(format!(
+                "can't find endpoint id {}",
+                endpoint_id
+            )))?;
+
+        if endpoint.get_mid_by_ssrc(rtp_packet.header.ssrc).is_none()
+            && endpoint.bind_simulcast_ssrc(rtp_packet)
+        {
+            debug!(
+                "{}/{}'s ssrc {} is bound to simulcast encoding {:?}",
+                session_id,
+                endpoint_id,
+                rtp_packet.header.ssrc,
+                endpoint.get_rid_by_ssrc(rtp_packet.header.ssrc),
+            );
+        }
+        // retransmissions of a simulcast encoding are forwarded as the encoding's packets
+        let rtx_packet;
+        let rtp_packet = if endpoint.is_simulcast_rtx_ssrc(rtp_packet.header.ssrc) {
+            let Some(unwrapped) = endpoint.unwrap_simulcast_rtx(rtp_packet) else {
+                return Ok(vec![]);
+            };
+            rtx_packet = unwrapped;
+            &rtx_packet
+        } else {
+            rtp_packet
+        };
+        let rid = endpoint.get_rid_by_ssrc(rtp_packet.header.ssrc).cloned();
+        let Some(mid) = endpoint.get_mid_by_ssrc(rtp_packet.header.ssrc).cloned() else {
+            trace!(
+                "{}/{}'s ssrc {} is not bound to any published track yet",
+                session_id,
+                endpoint_id,
+                rtp_packet.header.ssrc,
+            );
+            return Ok(vec![]);
+        };
+        if endpoint.is_track_muted(&mid) {
+            return Ok(vec![]);
+        }
+        let subscriber_ids: Vec<EndpointId> = session
+            .get_subscribers(endpoint_id, &mid)
+            .map(|subscribers| subscribers.iter().copied().collect())
+            .unwrap_or_default();
+        let subscriber_mid = format!("{}-{}", endpoint_id, mid);
+
+        let mut peers = vec![];
+        for subscriber_id in subscriber_ids {
+            let Some(subscriber) = session.get_mut_endpoint(&subscriber_id) else {
+                continue;
+            };
+
+            let mut subscriber_transport_contexts = vec![];
+            let transports = subscriber.get_transports();
+            for (subscriber_four_tuple, subscriber_transport) in transports.iter() {
+                if subscriber_transport.is_local_srtp_context_ready() {
+                    subscriber_transport_contexts.push(TransportContext {
+                        local_addr: subscriber_four_tuple.local_addr,
+                        peer_addr: subscriber_four_tuple.peer_addr,
+                        ecn: transport_context.ecn,
+                    });
+                } else {
+                    // local_srtp_context is not ready yet for subscriber_id's subscriber_four_tuple.
+                    // this transport just joins, but local_srtp_context is still setup
+                    trace!(
+                        "{}/{}'s local_srtp_context is not ready yet for {:?} since it is still setup",
+                        session_id,
+                        subscriber_id,
+                        subscriber_four_tuple,
+                    );
+                }
+            }
+            if subscriber_transport_contexts.is_empty() {
+                continue;
+            }
+
+            let Some(subscriber_rtp_packet) = subscriber
+                .get_mut_rtp_rewriter(&subscriber_mid)
+                .and_then(|rtp_rewriter| rtp_rewriter.rewrite(now, rtp_packet, rid.as_ref()))
+            else {
+                trace!(
+                    "{}/{} has no ssrc mapped for {}/{}'s ssrc {}",
+                    session_id,
+                    subscriber_id,
+                    endpoint_id,
+                    mid,
+                    rtp_packet.header.ssrc,
+                );
+                continue;
+            };
+
+            for subscriber_transport_context in subscriber_transport_contexts {
+                peers.push((subscriber_transport_context, subscriber_rtp_packet.clone()));
+            }
+        }
         Ok(peers)
     }
 
@@ -674,24 +1487,107 @@ impl GatewayHandler {
         let endpoint_id = candidate.endpoint_id();
         let endpoint = session.get_endpoint(&endpoint_id);
         let four_tuple = transport_context.into();
-        let has_transport = if let Some(endpoint) = &endpoint {
-            endpoint.has_transport(&four_tuple)
+        let (has_transport, migrated_four_tuple) = if let Some(endpoint) = &endpoint {
+            // established transport moves to the newly nominated path, e.g. after network
+            // change or ICE restart, instead of redoing DTLS handshake on a new transport
+            (
+                endpoint.has_transport(&four_tuple),
+                endpoint.find_migrated_transport(&four_tuple, candidate),
+            )
         } else {
             is_new_endpoint = true;
-            false
+            (false, None)
         };
 
-        if !request.contains(ATTR_USE_CANDIDATE) || has_transport {
+        if !request.contains(ATTR_USE_CANDIDATE) {
+            return Ok(is_new_endpoint);
+        }
+        if let Some(migrated_four_tuple) = migrated_four_tuple {
+            server_states.migrate_transport(
+                migrated_four_tuple,
+                four_tuple,
+                Rc::clone(candidate),
+            )?;
+            return Ok(is_new_endpoint);
+        }
+        if has_transport {
             return Ok(is_new_endpoint);
         }
 
-        let is_new_endpoint = session.add_endpoint(candidate, transport_context)?;
+        // new transport presents the certificate whose fingerprints are advertised to candidate
+        let dtls_handshake_config = server_states.find_dtls_handshake_config(candidate)?;
+        let session = server_states
+            .get_mut_session(&session_id)
+            .ok_or(Error::Other(format!("session {} not found", session_id)))?;
+        let is_new_endpoint =
+            session.add_endpoint(candidate, transport_context, dtls_handshake_config)?;
 
         server_states.add_endpoint(four_tuple, session_id, endpoint_id);
 
         Ok(is_new_endpoint)
     }
 
+    fn create_renegotiation_offer_message_events(
+        server_states: &mut ServerStates,
+        now: Instant,
+    ) -> Vec<TaggedMessageEvent> {
+        if !server_states.server_config().datachannel_signaling {
+            return vec![];
+        }
+        let mut peers = vec![];
+        for session in server_states.get_sessions().values() {
+            for endpoint in session.get_endpoints().values() {
+                // skip endpoints whose previous offer is not answered yet to avoid glare,
+                // they are renegotiated once the answer is accepted
+                if !endpoint.can_renegotiate() {
+                    continue;
+                }
+                // one data channel is enough to send the offer
+                if let Some((four_tuple, association_handle, stream_id)) = endpoint
+                    .get_transports()
+                    .iter()
+                    .find_map(|(four_tuple, transport)| {
+                        if let (Some(association_handle), Some(stream_id)) =
+                            transport.association_handle_and_stream_id()
+                        {
+                            Some((*four_tuple, association_handle, stream_id))
+                        } else {
+                            None
+                        }
+                    })
+                {
+                    peers.push((
+                        TransportContext {
+                            local_addr: four_tuple.local_addr,
+                            peer_addr: four_tuple.peer_addr,
+                            ecn: None,
+                        },
+                        association_handle,
+                        stream_id,
+                    ));
+                }
+            }
+        }
+
+        let mut messages = Vec::with_capacity(peers.len());
+        for (transport_context, association_handle, stream_id) in peers {
+            match GatewayHandler::create_offer_message_event(
+                server_states,
+                now,
+                transport_context,
+                association_handle,
+                stream_id,
+            ) {
+                Ok(message) => messages.push(message),
+                Err(err) => warn!(
+                    "failed to create renegotiation offer for {:?} with error {}",
+                    transport_context.peer_addr, err
+                ),
+            }
+        }
+        messages
+    }
+
     fn create_offer_message_event(
         server_states: &mut ServerStates,
         now: Instant,
@@ -703,44 +1599,30 @@ impl GatewayHandler {
         let (session_id, endpoint_id) = server_states
             .find_endpoint(&four_tuple)
             .ok_or(Error::ErrClientTransportNotSet)?;
-        let session = server_states
-            .get_mut_session(&session_id)
-            .ok_or(Error::Other(format!(
-                "can't find session id {}",
-                session_id
-            )))?;
-
-        let endpoint = session
-            .get_mut_endpoint(&endpoint_id)
-            .ok_or(Error::Other(format!(
-                "can't find endpoint id {}",
-                endpoint_id
-            )))?;
-        endpoint.set_renegotiation_needed(false); //clean renegotiation_needed flag
+        let offer = server_states.create_offer(session_id, endpoint_id, Some(four_tuple))?;
 
-        let remote_description = endpoint
-            .remote_description()
-            .ok_or(Error::Other("remote_description is not set".to_string()))?
-            .clone();
-
-        let local_conn_cred = {
-            let transports = endpoint.get_mut_transports();
-            let transport = transports.get_mut(&four_tuple).ok_or(Error::Other(format!(
-                "can't find transport for endpoint id {} with {:?}",
-                endpoint_id, four_tuple
-            )))?;
-            transport.candidate().local_connection_credentials().clone()
-        };
-
-        let offer = session.create_offer(
-            endpoint_id,
-            &remote_description,
-            &local_conn_cred.ice_params,
-        )?;
-        session.set_local_description(endpoint_id, &offer)?;
+        GatewayHandler::create_signaling_message_event(
+            server_states,
+            now,
+            transport_context,
+            association_handle,
+            stream_id,
+            SignalingMessage::Offer(offer),
+        )
+    }
 
-        let offer_str =
-            serde_json::to_string(&offer).map_err(|err| Error::Other(err.to_string()))?;
+    fn create_signaling_message_event(
+        server_states: &mut ServerStates,
+        now: Instant,
+        transport_context: TransportContext,
+        association_handle: usize,
+        stream_id: u16,
+        message: SignalingMessage,
+    ) -> Result<TaggedMessageEvent> {
+        let is_envelope = server_states
+            .get_mut_transport(&(&transport_context).into())?
+            .is_signaling_envelope();
+        let message_str = message.encode(is_envelope)?;
 
         Ok(TaggedMessageEvent {
             now,
@@ -748,7 +1630,10 @@ impl GatewayHandler {
             message: MessageEvent::Dtls(DTLSMessageEvent::DataChannel(ApplicationMessage {
                 association_handle,
                 stream_id,
-                data_channel_event: DataChannelEvent::Message(BytesMut::from(offer_str.as_str())),
+                data_channel_event: DataChannelEvent::Message(
+                    DataChannelMessageType::Text,
+                    BytesMut::from(message_str.as_str()),
+                ),
             })),
         })
     }
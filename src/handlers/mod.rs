@@ -7,3 +7,7 @@ pub(crate) mod interceptor;
 pub(crate) mod sctp;
 pub(crate) mod srtp;
 pub(crate) mod stun;
+pub(crate) mod tcp_framing;
+
+#[cfg(test)]
+mod tcp_framing_test;
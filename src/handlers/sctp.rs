@@ -1,8 +1,9 @@
 use crate::messages::{
-    DTLSMessageEvent, DataChannelMessage, DataChannelMessageParams, DataChannelMessageType,
-    MessageEvent, TaggedMessageEvent,
+    ApplicationMessage, DTLSMessageEvent, DataChannelEvent, DataChannelMessage,
+    DataChannelMessageParams, DataChannelMessageType, MessageEvent, TaggedMessageEvent,
 };
 use crate::server::states::ServerStates;
+use crate::types::FourTuple;
 use bytes::BytesMut;
 use log::{debug, error};
 use retty::channel::{Context, Handler};
@@ -15,13 +16,11 @@ use shared::error::{Error, Result};
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::collections::VecDeque;
-use std::net::SocketAddr;
 use std::rc::Rc;
 use std::time::Instant;
 
 /// SctpHandler implements SCTP Protocol handling
 pub struct SctpHandler {
-    local_addr: SocketAddr,
     server_states: Rc<RefCell<ServerStates>>,
     internal_buffer: Vec<u8>,
     transmits: VecDeque<TaggedMessageEvent>,
@@ -30,10 +29,12 @@ pub struct SctpHandler {
 enum SctpMessage {
     Inbound(DataChannelMessage),
     Outbound(Transmit),
+    // the data channel used for signaling is closed
+    Closed(usize, u16),
 }
 
 impl SctpHandler {
-    pub fn new(local_addr: SocketAddr, server_states: Rc<RefCell<ServerStates>>) -> Self {
+    pub fn new(server_states: Rc<RefCell<ServerStates>>) -> Self {
         let max_message_size = {
             let server_states = server_states.borrow();
             server_states
@@ -44,7 +45,6 @@ impl SctpHandler {
         };
 
         SctpHandler {
-            local_addr,
             server_states: Rc::clone(&server_states),
             internal_buffer: vec![0u8; max_message_size],
             transmits: VecDeque::new(),
@@ -74,6 +74,15 @@ impl Handler for SctpHandler {
             let try_read = || -> Result<Vec<SctpMessage>> {
                 let mut server_states = self.server_states.borrow_mut();
                 let transport = server_states.get_mut_transport(&four_tuple)?;
+                // signaling data channel first, then application data channels
+                let mut data_channels = vec![];
+                if let (Some(association_handle), Some(stream_id)) =
+                    transport.association_handle_and_stream_id()
+                {
+                    data_channels.push((association_handle, stream_id));
+                }
+                data_channels.extend(transport.get_data_channels().keys());
+                let dtls_remote_addr = transport.dtls_remote_addr();
                 let (sctp_endpoint, sctp_associations) =
                     transport.get_mut_sctp_endpoint_associations();
 
@@ -81,7 +90,7 @@ impl Handler for SctpHandler {
                     HashMap::new();
                 if let Some((ch, event)) = sctp_endpoint.handle(
                     msg.now,
-                    msg.transport.peer_addr,
+                    dtls_remote_addr,
                     Some(msg.transport.local_addr.ip()),
                     msg.transport.ecn,
                     dtls_message.freeze(), //TODO: switch API Bytes to BytesMut
@@ -126,6 +135,14 @@ impl Handler for SctpHandler {
                             }
                         }
 
+                        // data channel is closed by resetting its stream, which is unregistered
+                        // from the association without any event
+                        for &(association_handle, stream_id) in &data_channels {
+                            if ch.0 == association_handle && conn.stream(stream_id).is_err() {
+                                messages.push(SctpMessage::Closed(association_handle, stream_id));
+                            }
+                        }
+
                         while let Some(event) = conn.poll_endpoint_event() {
                             endpoint_events.push((*ch, event));
                         }
@@ -139,7 +156,19 @@ impl Handler for SctpHandler {
 
                     for (ch, event) in endpoint_events {
                         sctp_endpoint.handle_event(ch, event); // handle drain event
-                        sctp_associations.remove(&ch);
+                        if sctp_associations.remove(&ch).is_some() {
+                            for &(association_handle, stream_id) in &data_channels {
+                                if ch.0 == association_handle
+                                    && !messages.iter().any(|message| {
+                                        matches!(message, SctpMessage::Closed(a, s)
+                                            if *a == association_handle && *s == stream_id)
+                                    })
+                                {
+                                    messages
+                                        .push(SctpMessage::Closed(association_handle, stream_id));
+                                }
+                            }
+                        }
                     }
                 }
 
@@ -160,14 +189,28 @@ impl Handler for SctpHandler {
                                     message: MessageEvent::Dtls(DTLSMessageEvent::Sctp(message)),
                                 })
                             }
+                            SctpMessage::Closed(association_handle, stream_id) => {
+                                debug!("sctp data channel closed {:?}", msg.transport.peer_addr);
+                                ctx.fire_read(TaggedMessageEvent {
+                                    now: msg.now,
+                                    transport: msg.transport,
+                                    message: MessageEvent::Dtls(DTLSMessageEvent::DataChannel(
+                                        ApplicationMessage {
+                                            association_handle,
+                                            stream_id,
+                                            data_channel_event: DataChannelEvent::Close,
+                                        },
+                                    )),
+                                })
+                            }
                             SctpMessage::Outbound(transmit) => {
                                 if let Payload::RawEncode(raw_data) = transmit.payload {
                                     for raw in raw_data {
                                         self.transmits.push_back(TaggedMessageEvent {
                                             now: transmit.now,
                                             transport: TransportContext {
-                                                local_addr: self.local_addr,
-                                                peer_addr: transmit.remote,
+                                                local_addr: msg.transport.local_addr,
+                                                peer_addr: msg.transport.peer_addr,
                                                 ecn: transmit.ecn,
                                             },
                                             message: MessageEvent::Dtls(DTLSMessageEvent::Raw(
@@ -197,13 +240,14 @@ impl Handler for SctpHandler {
         ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
         now: Instant,
     ) {
-        let try_timeout = || -> Result<Vec<Transmit>> {
+        let try_timeout = || -> Result<Vec<(FourTuple, Transmit)>> {
             let mut transmits = vec![];
             let mut server_states = self.server_states.borrow_mut();
 
             for session in server_states.get_mut_sessions().values_mut() {
                 for endpoint in session.get_mut_endpoints().values_mut() {
                     for transport in endpoint.get_mut_transports().values_mut() {
+                        let four_tuple = *transport.four_tuple();
                         let (sctp_endpoint, sctp_associations) =
                             transport.get_mut_sctp_endpoint_associations();
 
@@ -216,7 +260,11 @@ impl Handler for SctpHandler {
                             }
 
                             while let Some(x) = conn.poll_transmit(now) {
-                                transmits.extend(split_transmit(x));
+                                transmits.extend(
+                                    split_transmit(x)
+                                        .into_iter()
+                                        .map(|transmit| (four_tuple, transmit)),
+                                );
                             }
                         }
 
@@ -232,14 +280,14 @@ impl Handler for SctpHandler {
         };
         match try_timeout() {
             Ok(transmits) => {
-                for transmit in transmits {
+                for (four_tuple, transmit) in transmits {
                     if let Payload::RawEncode(raw_data) = transmit.payload {
                         for raw in raw_data {
                             self.transmits.push_back(TaggedMessageEvent {
                                 now: transmit.now,
                                 transport: TransportContext {
-                                    local_addr: self.local_addr,
-                                    peer_addr: transmit.remote,
+                                    local_addr: four_tuple.local_addr,
+                                    peer_addr: four_tuple.peer_addr,
                                     ecn: transmit.ecn,
                                 },
                                 message: MessageEvent::Dtls(DTLSMessageEvent::Raw(BytesMut::from(
@@ -349,8 +397,8 @@ impl Handler for SctpHandler {
                                     self.transmits.push_back(TaggedMessageEvent {
                                         now: transmit.now,
                                         transport: TransportContext {
-                                            local_addr: self.local_addr,
-                                            peer_addr: transmit.remote,
+                                            local_addr: msg.transport.local_addr,
+                                            peer_addr: msg.transport.peer_addr,
                                             ecn: transmit.ecn,
                                         },
                                         message: MessageEvent::Dtls(DTLSMessageEvent::Raw(
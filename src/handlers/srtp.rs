@@ -1,7 +1,9 @@
 use crate::messages::{MessageEvent, RTPMessageEvent, TaggedMessageEvent};
 use crate::server::states::ServerStates;
+use crate::types::FourTuple;
 use bytes::BytesMut;
 use log::{debug, error};
+use opentelemetry::KeyValue;
 use retty::channel::{Context, Handler};
 use shared::{
     error::{Error, Result},
@@ -21,6 +23,51 @@ impl SrtpHandler {
     pub fn new(server_states: Rc<RefCell<ServerStates>>) -> Self {
         SrtpHandler { server_states }
     }
+
+    /// record_decrypt_error counts packets failed to decrypt by their cause, so that tampering can
+    /// be told from packet loss. Metrics are labelled by session and endpoint id instead of peer
+    /// address, while counts of each transport are kept on it.
+    fn record_decrypt_error(
+        server_states: &mut ServerStates,
+        four_tuple: &FourTuple,
+        protocol: &'static str,
+        err: &Error,
+    ) {
+        let Some((session_id, endpoint_id)) = server_states.find_endpoint(four_tuple) else {
+            return;
+        };
+        let attributes = [
+            KeyValue::new("protocol", protocol),
+            KeyValue::new("session_id", session_id as i64),
+            KeyValue::new("endpoint_id", endpoint_id as i64),
+        ];
+        let Ok(transport) = server_states.get_mut_transport(four_tuple) else {
+            return;
+        };
+        let counts = transport.get_mut_srtp_decrypt_error_counts();
+        match err {
+            Error::SrtpSsrcDuplicated(..) | Error::SrtcpSsrcDuplicated(..) => {
+                counts.replayed += 1;
+                server_states
+                    .metrics()
+                    .record_srtp_replayed_count(1, &attributes);
+            }
+            Error::RtpFailedToVerifyAuthTag
+            | Error::RtcpFailedToVerifyAuthTag
+            | Error::ErrFailedToVerifyAuthTag => {
+                counts.auth_failed += 1;
+                server_states
+                    .metrics()
+                    .record_srtp_auth_failed_count(1, &attributes);
+            }
+            _ => {
+                counts.decrypt_failed += 1;
+                server_states
+                    .metrics()
+                    .record_srtp_decrypt_failed_count(1, &attributes);
+            }
+        }
+    }
 }
 
 impl Handler for SrtpHandler {
@@ -48,7 +95,18 @@ impl Handler for SrtpHandler {
                 if is_rtcp(&message) {
                     let mut remote_context = transport.remote_srtp_context();
                     if let Some(context) = remote_context.as_mut() {
-                        let mut decrypted = context.decrypt_rtcp(&message)?;
+                        let mut decrypted = match context.decrypt_rtcp(&message) {
+                            Ok(decrypted) => decrypted,
+                            Err(err) => {
+                                SrtpHandler::record_decrypt_error(
+                                    &mut server_states,
+                                    &four_tuple,
+                                    "srtcp",
+                                    &err,
+                                );
+                                return Err(err);
+                            }
+                        };
                         let rtcp_packets = rtcp::packet::unmarshal(&mut decrypted)?;
                         if rtcp_packets.is_empty() {
                             return Err(Error::Other("empty rtcp_packets".to_string()));
@@ -68,7 +126,18 @@ impl Handler for SrtpHandler {
                 } else {
                     let mut remote_context = transport.remote_srtp_context();
                     if let Some(context) = remote_context.as_mut() {
-                        let mut decrypted = context.decrypt_rtp(&message)?;
+                        let mut decrypted = match context.decrypt_rtp(&message) {
+                            Ok(decrypted) => decrypted,
+                            Err(err) => {
+                                SrtpHandler::record_decrypt_error(
+                                    &mut server_states,
+                                    &four_tuple,
+                                    "srtp",
+                                    &err,
+                                );
+                                return Err(err);
+                            }
+                        };
                         let rtp_packet = rtp::Packet::unmarshal(&mut decrypted)?;
 
                         server_states.metrics().record_rtp_packet_in_count(1, &[]);
@@ -0,0 +1,144 @@
+use bytes::{Buf, BufMut, BytesMut};
+use log::{debug, error};
+use retty::channel::{Context, Handler};
+use retty::transport::TaggedBytesMut;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::rc::Rc;
+use std::time::Instant;
+
+use crate::server::states::ServerStates;
+use crate::types::FourTuple;
+
+/// length of RFC 4571 frame header
+const FRAME_HEADER_LEN: usize = 2;
+
+/// TcpFramingHandler implements RFC 4571 framing of STUN/DTLS/RTP/RTCP packets over ICE-TCP
+/// connections accepted on ServerConfig::tcp_addrs, and bypasses packets of UDP sockets
+pub struct TcpFramingHandler {
+    server_states: Rc<RefCell<ServerStates>>,
+    buffers: HashMap<FourTuple, BytesMut>,
+}
+
+impl TcpFramingHandler {
+    pub fn new(server_states: Rc<RefCell<ServerStates>>) -> Self {
+        TcpFramingHandler {
+            server_states,
+            buffers: HashMap::new(),
+        }
+    }
+
+    fn is_tcp(&self, local_addr: &SocketAddr) -> bool {
+        self.server_states
+            .borrow()
+            .server_config()
+            .tcp_addrs
+            .contains(local_addr)
+    }
+}
+
+impl Handler for TcpFramingHandler {
+    type Rin = TaggedBytesMut;
+    type Rout = Self::Rin;
+    type Win = TaggedBytesMut;
+    type Wout = Self::Win;
+
+    fn name(&self) -> &str {
+        "TcpFramingHandler"
+    }
+
+    fn handle_read(
+        &mut self,
+        ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+        msg: Self::Rin,
+    ) {
+        if !self.is_tcp(&msg.transport.local_addr) {
+            // Bypass
+            ctx.fire_read(msg);
+            return;
+        }
+
+        // TCP stream may split or coalesce frames, so keep partial frame until the rest arrives
+        let four_tuple: FourTuple = (&msg.transport).into();
+        let buffer = self.buffers.entry(four_tuple).or_default();
+        buffer.extend_from_slice(&msg.message);
+
+        let mut frames = vec![];
+        while buffer.len() >= FRAME_HEADER_LEN {
+            let length = u16::from_be_bytes([buffer[0], buffer[1]]) as usize;
+            if buffer.len() < FRAME_HEADER_LEN + length {
+                break;
+            }
+            buffer.advance(FRAME_HEADER_LEN);
+            let frame = buffer.split_to(length);
+            if frame.is_empty() {
+                debug!("skip zero length frame from {}", msg.transport.peer_addr);
+            } else {
+                frames.push(frame);
+            }
+        }
+        if buffer.is_empty() {
+            self.buffers.remove(&four_tuple);
+        }
+
+        for frame in frames {
+            ctx.fire_read(TaggedBytesMut {
+                now: msg.now,
+                transport: msg.transport,
+                message: frame,
+            });
+        }
+    }
+
+    fn handle_timeout(
+        &mut self,
+        ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+        now: Instant,
+    ) {
+        // partial frames of removed transports are never completed
+        {
+            let mut server_states = self.server_states.borrow_mut();
+            while let Some(four_tuple) = server_states.poll_removed_tcp_four_tuple() {
+                self.buffers.remove(&four_tuple);
+            }
+        }
+
+        ctx.fire_timeout(now);
+    }
+
+    fn poll_write(
+        &mut self,
+        ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+    ) -> Option<Self::Wout> {
+        loop {
+            let msg = ctx.fire_poll_write()?;
+            if !self.is_tcp(&msg.transport.local_addr) {
+                // Bypass
+                return Some(msg);
+            }
+
+            let Ok(length) = u16::try_from(msg.message.len()) else {
+                error!(
+                    "drop packet of {} bytes to {} exceeding RFC 4571 frame size",
+                    msg.message.len(),
+                    msg.transport.peer_addr
+                );
+                continue;
+            };
+            let mut message = BytesMut::with_capacity(FRAME_HEADER_LEN + msg.message.len());
+            message.put_u16(length);
+            message.extend_from_slice(&msg.message);
+            return Some(TaggedBytesMut {
+                now: msg.now,
+                transport: msg.transport,
+                message,
+            });
+        }
+    }
+
+    fn handle_close(&mut self, ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>) {
+        self.buffers.clear();
+        ctx.fire_close();
+    }
+}
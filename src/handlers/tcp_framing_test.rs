@@ -0,0 +1,166 @@
+use super::tcp_framing::TcpFramingHandler;
+use crate::server::states::ServerStates;
+use crate::{RTCCertificate, ServerConfig};
+use bytes::BytesMut;
+use retty::channel::{Context, Handler, InboundPipeline, OutboundPipeline, Pipeline};
+use retty::transport::{TaggedBytesMut, TransportContext};
+use std::cell::RefCell;
+use std::net::SocketAddr;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Instant;
+
+const UDP_ADDR: &str = "127.0.0.1:3478";
+const TCP_ADDR: &str = "127.0.0.1:3479";
+const PEER_ADDR: &str = "127.0.0.1:50000";
+
+type Reads = Rc<RefCell<Vec<BytesMut>>>;
+
+/// collects the frames read out of TcpFramingHandler
+struct ReadCollector {
+    reads: Reads,
+}
+
+impl Handler for ReadCollector {
+    type Rin = TaggedBytesMut;
+    type Rout = Self::Rin;
+    type Win = TaggedBytesMut;
+    type Wout = Self::Win;
+
+    fn name(&self) -> &str {
+        "ReadCollector"
+    }
+
+    fn handle_read(
+        &mut self,
+        _ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+        msg: Self::Rin,
+    ) {
+        self.reads.borrow_mut().push(msg.message);
+    }
+
+    fn poll_write(
+        &mut self,
+        ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+    ) -> Option<Self::Wout> {
+        ctx.fire_poll_write()
+    }
+}
+
+fn pipeline() -> (Rc<Pipeline<TaggedBytesMut, TaggedBytesMut>>, Reads) {
+    let key_pair = rcgen::KeyPair::generate(&rcgen::PKCS_ECDSA_P256_SHA256).unwrap();
+    let certificates = vec![RTCCertificate::from_key_pair(key_pair).unwrap()];
+    let server_config =
+        ServerConfig::new(certificates).with_tcp_addrs(vec![TCP_ADDR.parse().unwrap()]);
+    let server_states = Rc::new(RefCell::new(
+        ServerStates::new(
+            Arc::new(server_config),
+            UDP_ADDR.parse().unwrap(),
+            opentelemetry::global::meter("test"),
+        )
+        .unwrap(),
+    ));
+
+    let reads = Rc::new(RefCell::new(vec![]));
+    let pipeline: Pipeline<TaggedBytesMut, TaggedBytesMut> = Pipeline::new();
+    pipeline.add_back(TcpFramingHandler::new(server_states));
+    pipeline.add_back(ReadCollector {
+        reads: Rc::clone(&reads),
+    });
+    (pipeline.finalize(), reads)
+}
+
+fn message(local_addr: &str, message: &[u8]) -> TaggedBytesMut {
+    TaggedBytesMut {
+        now: Instant::now(),
+        transport: TransportContext {
+            local_addr: local_addr.parse::<SocketAddr>().unwrap(),
+            peer_addr: PEER_ADDR.parse().unwrap(),
+            ecn: None,
+        },
+        message: BytesMut::from(message),
+    }
+}
+
+#[test]
+fn test_read_split_frames() {
+    let (pipeline, reads) = pipeline();
+
+    pipeline.read(message(TCP_ADDR, &[0]));
+    pipeline.read(message(TCP_ADDR, &[3, 1]));
+    assert!(reads.borrow().is_empty());
+
+    pipeline.read(message(TCP_ADDR, &[2, 3]));
+    assert_eq!(*reads.borrow(), vec![BytesMut::from(&[1u8, 2, 3][..])]);
+}
+
+#[test]
+fn test_read_coalesced_frames() {
+    let (pipeline, reads) = pipeline();
+
+    pipeline.read(message(TCP_ADDR, &[0, 1, 1, 0, 2, 2, 2, 0, 3, 3]));
+    assert_eq!(
+        *reads.borrow(),
+        vec![BytesMut::from(&[1u8][..]), BytesMut::from(&[2u8, 2][..])]
+    );
+
+    pipeline.read(message(TCP_ADDR, &[3, 3]));
+    assert_eq!(reads.borrow().len(), 3);
+    assert_eq!(reads.borrow()[2], BytesMut::from(&[3u8, 3, 3][..]));
+}
+
+#[test]
+fn test_read_zero_length_frame() {
+    let (pipeline, reads) = pipeline();
+
+    pipeline.read(message(TCP_ADDR, &[0, 0, 0, 1, 1, 0, 0]));
+    assert_eq!(*reads.borrow(), vec![BytesMut::from(&[1u8][..])]);
+}
+
+#[test]
+fn test_read_udp_bypass() {
+    let (pipeline, reads) = pipeline();
+
+    pipeline.read(message(UDP_ADDR, &[0, 3, 1]));
+    assert_eq!(*reads.borrow(), vec![BytesMut::from(&[0u8, 3, 1][..])]);
+}
+
+#[test]
+fn test_close_drops_partial_frame() {
+    let (pipeline, reads) = pipeline();
+
+    pipeline.read(message(TCP_ADDR, &[0, 4, 1, 2]));
+    pipeline.close();
+    pipeline.read(message(TCP_ADDR, &[0, 2, 9, 9]));
+    assert_eq!(*reads.borrow(), vec![BytesMut::from(&[9u8, 9][..])]);
+}
+
+#[test]
+fn test_write_frames() {
+    let (pipeline, _) = pipeline();
+
+    pipeline.write(message(TCP_ADDR, &[1, 2, 3]));
+    pipeline.write(message(UDP_ADDR, &[4, 5]));
+    assert_eq!(
+        pipeline.poll_transmit().map(|msg| msg.message),
+        Some(BytesMut::from(&[0u8, 3, 1, 2, 3][..]))
+    );
+    assert_eq!(
+        pipeline.poll_transmit().map(|msg| msg.message),
+        Some(BytesMut::from(&[4u8, 5][..]))
+    );
+    assert!(pipeline.poll_transmit().is_none());
+}
+
+#[test]
+fn test_write_oversized_packet() {
+    let (pipeline, _) = pipeline();
+
+    pipeline.write(message(TCP_ADDR, &vec![0u8; u16::MAX as usize + 1]));
+    pipeline.write(message(TCP_ADDR, &[1]));
+    assert_eq!(
+        pipeline.poll_transmit().map(|msg| msg.message),
+        Some(BytesMut::from(&[0u8, 1, 1][..]))
+    );
+    assert!(pipeline.poll_transmit().is_none());
+}
@@ -4,8 +4,11 @@ use std::time::Instant;
 
 pub(crate) mod nack;
 pub(crate) mod report;
+pub(crate) mod stream_info;
 pub(crate) mod twcc;
 
+use stream_info::StreamInfo;
+
 pub enum InterceptorEvent {
     Inbound(TaggedMessageEvent),
     Outbound(TaggedMessageEvent),
@@ -44,6 +47,38 @@ pub trait Interceptor {
             next.poll_timeout(eto);
         }
     }
+
+    /// bind_remote_stream lets you modify any incoming RTP packets of a new remote stream
+    /// announced by the remote description.
+    fn bind_remote_stream(&mut self, info: &StreamInfo) {
+        if let Some(next) = self.next() {
+            next.bind_remote_stream(info);
+        }
+    }
+
+    /// bind_local_stream lets you modify any outgoing RTP packets of a new local stream
+    /// added for forwarding.
+    fn bind_local_stream(&mut self, info: &StreamInfo) {
+        if let Some(next) = self.next() {
+            next.bind_local_stream(info);
+        }
+    }
+
+    /// unbind_local_stream lets you remove any state of a local stream which is no longer
+    /// forwarded.
+    fn unbind_local_stream(&mut self, info: &StreamInfo) {
+        if let Some(next) = self.next() {
+            next.unbind_local_stream(info);
+        }
+    }
+
+    /// forward_sender_report passes the NTP/RTP timestamp mapping reported by a remote sender,
+    /// translated into the local stream with ssrc which forwards the remote stream.
+    fn forward_sender_report(&mut self, now: Instant, ssrc: u32, ntp_time: u64, rtp_time: u32) {
+        if let Some(next) = self.next() {
+            next.forward_sender_report(now, ssrc, ntp_time, rtp_time);
+        }
+    }
 }
 
 /// InterceptorBuilder provides an interface for constructing interceptors
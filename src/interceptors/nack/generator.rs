@@ -0,0 +1,110 @@
+use crate::interceptors::nack::generator_stream::GeneratorStream;
+use crate::interceptors::nack::NackBuilder;
+use crate::interceptors::{Interceptor, InterceptorEvent};
+use crate::messages::{MessageEvent, RTPMessageEvent, TaggedMessageEvent};
+use crate::types::FourTuple;
+use retty::transport::TransportContext;
+use rtcp::transport_feedbacks::transport_layer_nack::{
+    nack_pairs_from_sequence_numbers, TransportLayerNack,
+};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Generator interceptor tracks the received sequence numbers of remote streams
+/// and periodically sends nack requests for the missing packets.
+pub(crate) struct Generator {
+    pub(super) log2_size_minus_6: u8,
+    pub(super) skip_last_n: u16,
+    pub(super) interval: Duration,
+    pub(super) eto: Instant,
+    pub(super) sender_ssrc: u32,
+    pub(crate) streams: HashMap<u32, GeneratorStream>,
+    pub(super) next: Option<Box<dyn Interceptor>>,
+}
+
+impl Generator {
+    pub(crate) fn builder() -> NackBuilder {
+        NackBuilder {
+            is_generator: true,
+            ..Default::default()
+        }
+    }
+}
+
+impl Interceptor for Generator {
+    fn chain(mut self: Box<Self>, next: Box<dyn Interceptor>) -> Box<dyn Interceptor> {
+        self.next = Some(next);
+        self
+    }
+
+    fn next(&mut self) -> Option<&mut Box<dyn Interceptor>> {
+        self.next.as_mut()
+    }
+
+    fn read(&mut self, msg: &mut TaggedMessageEvent) -> Vec<InterceptorEvent> {
+        if let MessageEvent::Rtp(RTPMessageEvent::Rtp(rtp_packet)) = &msg.message {
+            let log2_size_minus_6 = self.log2_size_minus_6;
+            let stream = self
+                .streams
+                .entry(rtp_packet.header.ssrc)
+                .or_insert_with(|| GeneratorStream::new(log2_size_minus_6));
+            stream.add(rtp_packet.header.sequence_number);
+        }
+
+        if let Some(next) = self.next() {
+            next.read(msg)
+        } else {
+            vec![]
+        }
+    }
+
+    fn handle_timeout(&mut self, now: Instant, four_tuples: &[FourTuple]) -> Vec<InterceptorEvent> {
+        let mut interceptor_events = vec![];
+
+        if self.eto <= now {
+            self.eto = now + self.interval;
+
+            for (ssrc, stream) in self.streams.iter() {
+                let missing = stream.missing_seq_numbers(self.skip_last_n);
+                if missing.is_empty() {
+                    continue;
+                }
+
+                let nack = TransportLayerNack {
+                    sender_ssrc: self.sender_ssrc,
+                    media_ssrc: *ssrc,
+                    nacks: nack_pairs_from_sequence_numbers(&missing),
+                };
+                for four_tuple in four_tuples {
+                    interceptor_events.push(InterceptorEvent::Outbound(TaggedMessageEvent {
+                        now,
+                        transport: TransportContext {
+                            local_addr: four_tuple.local_addr,
+                            peer_addr: four_tuple.peer_addr,
+                            ecn: None,
+                        },
+                        message: MessageEvent::Rtp(RTPMessageEvent::Rtcp(vec![Box::new(
+                            nack.clone(),
+                        )])),
+                    }));
+                }
+            }
+        }
+
+        if let Some(next) = self.next() {
+            let mut events = next.handle_timeout(now, four_tuples);
+            interceptor_events.append(&mut events);
+        }
+        interceptor_events
+    }
+
+    fn poll_timeout(&mut self, eto: &mut Instant) {
+        if self.eto < *eto {
+            *eto = self.eto
+        }
+
+        if let Some(next) = self.next() {
+            next.poll_timeout(eto);
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use crate::interceptors::nack::UINT16SIZE_HALF;
+
+/// GeneratorStream keeps a bitmap of the received sequence numbers of a remote stream
+/// and reports the ones missing after the last consecutive packet.
+pub(crate) struct GeneratorStream {
+    packets: Vec<u64>,
+    size: u16,
+    end: u16,
+    started: bool,
+    last_consecutive: u16,
+}
+
+impl GeneratorStream {
+    pub(crate) fn new(log2_size_minus_6: u8) -> Self {
+        Self {
+            packets: vec![0u64; 1 << log2_size_minus_6],
+            size: 1 << (log2_size_minus_6 + 6),
+            end: 0,
+            started: false,
+            last_consecutive: 0,
+        }
+    }
+
+    pub(crate) fn add(&mut self, seq: u16) {
+        if !self.started {
+            self.set_received(seq);
+            self.end = seq;
+            self.started = true;
+            self.last_consecutive = seq;
+            return;
+        }
+
+        let last_consecutive_plus1 = self.last_consecutive.wrapping_add(1);
+        let diff = seq.wrapping_sub(self.end);
+        if diff == 0 {
+            return;
+        } else if diff < UINT16SIZE_HALF {
+            // this means a positive diff, in other words seq > end (with counting for rollovers)
+            let mut i = self.end.wrapping_add(1);
+            while i != seq {
+                // clear packets between end and seq (these may contain packets from a "size" ago)
+                self.del_received(i);
+                i = i.wrapping_add(1);
+            }
+            self.end = seq;
+
+            let seq_sub_last_consecutive = seq.wrapping_sub(self.last_consecutive);
+            if last_consecutive_plus1 == seq {
+                self.last_consecutive = seq;
+            } else if seq_sub_last_consecutive > self.size {
+                self.last_consecutive = seq.wrapping_sub(self.size);
+                // there might be valid packets at the beginning of the buffer now
+                self.fix_last_consecutive();
+            }
+        } else if last_consecutive_plus1 == seq {
+            // negative diff, seq < end (with counting for rollovers)
+            self.last_consecutive = seq;
+            // there might be other valid packets after seq
+            self.fix_last_consecutive();
+        }
+
+        self.set_received(seq);
+    }
+
+    pub(crate) fn missing_seq_numbers(&self, skip_last_n: u16) -> Vec<u16> {
+        let until = self.end.wrapping_sub(skip_last_n);
+        let diff = until.wrapping_sub(self.last_consecutive);
+        if diff >= UINT16SIZE_HALF {
+            // until < s.last_consecutive (counting for rollover)
+            return vec![];
+        }
+
+        let mut missing_packet_seq_nums = vec![];
+        let mut i = self.last_consecutive.wrapping_add(1);
+        let until_plus1 = until.wrapping_add(1);
+        while i != until_plus1 {
+            if !self.get_received(i) {
+                missing_packet_seq_nums.push(i);
+            }
+            i = i.wrapping_add(1);
+        }
+
+        missing_packet_seq_nums
+    }
+
+    fn set_received(&mut self, seq: u16) {
+        let pos = (seq % self.size) as usize;
+        self.packets[pos / 64] |= 1u64 << (pos % 64);
+    }
+
+    fn del_received(&mut self, seq: u16) {
+        let pos = (seq % self.size) as usize;
+        self.packets[pos / 64] &= u64::MAX ^ (1u64 << (pos % 64));
+    }
+
+    fn get_received(&self, seq: u16) -> bool {
+        let pos = (seq % self.size) as usize;
+        (self.packets[pos / 64] & (1u64 << (pos % 64))) != 0
+    }
+
+    fn fix_last_consecutive(&mut self) {
+        let mut i = self.last_consecutive.wrapping_add(1);
+        while i != self.end.wrapping_add(1) && self.get_received(i) {
+            // find all consecutive packets
+            i = i.wrapping_add(1);
+        }
+        self.last_consecutive = i.wrapping_sub(1);
+    }
+}
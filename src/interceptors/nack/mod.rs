@@ -1 +1,90 @@
+use crate::interceptors::{Interceptor, InterceptorBuilder};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
 
+pub(crate) mod generator;
+pub(crate) mod generator_stream;
+pub(crate) mod responder;
+pub(crate) mod responder_stream;
+
+use generator::Generator;
+use responder::Responder;
+
+const UINT16SIZE_HALF: u16 = 1 << 15;
+
+/// NackBuilder can be used to configure Generator and Responder Interceptor.
+#[derive(Default)]
+pub struct NackBuilder {
+    is_generator: bool,
+    log2_size: Option<u8>,
+    skip_last_n: Option<u16>,
+    interval: Option<Duration>,
+}
+
+impl NackBuilder {
+    /// with_log2_size sets the size of the interceptor.
+    /// For Generator, size must be one of: 64, 128, 256, 512, 1024, 2048, 4096, 8192, 16384, 32768
+    /// For Responder, size must be one of: 1, 2, 4, 8, 16, 32, 64, 128, 256, 512, 1024, 2048, 4096, 8192, 16384, 32768
+    pub fn with_log2_size(mut self, log2_size: u8) -> NackBuilder {
+        self.log2_size = Some(log2_size);
+        self
+    }
+
+    /// with_skip_last_n sets the number of packets (n-1 packets before the last received packets)
+    /// to ignore when generating nack requests.
+    pub fn with_skip_last_n(mut self, skip_last_n: u16) -> NackBuilder {
+        self.skip_last_n = Some(skip_last_n);
+        self
+    }
+
+    /// with_interval sets send interval for the Generator.
+    pub fn with_interval(mut self, interval: Duration) -> NackBuilder {
+        self.interval = Some(interval);
+        self
+    }
+
+    fn build_generator(&self) -> Generator {
+        Generator {
+            log2_size_minus_6: if let Some(log2_size) = &self.log2_size {
+                (*log2_size).clamp(6, 15) - 6
+            } else {
+                13 - 6 // 8192 = 1 << 13
+            },
+            skip_last_n: self.skip_last_n.unwrap_or_default(),
+            interval: if let Some(interval) = &self.interval {
+                *interval
+            } else {
+                Duration::from_millis(100)
+            },
+            eto: Instant::now(),
+            sender_ssrc: rand::random::<u32>(),
+            streams: HashMap::new(),
+            next: None,
+        }
+    }
+
+    fn build_responder(&self) -> Responder {
+        Responder {
+            log2_size: if let Some(log2_size) = &self.log2_size {
+                (*log2_size).min(15)
+            } else {
+                13 // 8192 = 1 << 13
+            },
+            streams: HashMap::new(),
+            next: None,
+        }
+    }
+}
+
+impl InterceptorBuilder for NackBuilder {
+    fn build(&self, _id: &str) -> Box<dyn Interceptor> {
+        if self.is_generator {
+            Box::new(self.build_generator())
+        } else {
+            Box::new(self.build_responder())
+        }
+    }
+}
+
+#[cfg(test)]
+mod nack_test;
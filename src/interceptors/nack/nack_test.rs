@@ -0,0 +1,209 @@
+use super::generator::Generator;
+use super::generator_stream::GeneratorStream;
+use super::responder::Responder;
+use super::responder_stream::ResponderStream;
+use crate::interceptors::{Interceptor, InterceptorEvent};
+use crate::messages::{MessageEvent, RTPMessageEvent, TaggedMessageEvent};
+use crate::types::FourTuple;
+use retty::transport::TransportContext;
+use rtcp::transport_feedbacks::transport_layer_nack::{
+    nack_pairs_from_sequence_numbers, TransportLayerNack,
+};
+use std::time::{Duration, Instant};
+
+fn transport() -> TransportContext {
+    TransportContext {
+        local_addr: "127.0.0.1:3478".parse().unwrap(),
+        peer_addr: "127.0.0.1:5000".parse().unwrap(),
+        ecn: None,
+    }
+}
+
+fn rtp_packet(ssrc: u32, seq: u16) -> rtp::packet::Packet {
+    let mut packet = rtp::packet::Packet::default();
+    packet.header.ssrc = ssrc;
+    packet.header.sequence_number = seq;
+    packet
+}
+
+fn rtp_message(now: Instant, ssrc: u32, seq: u16) -> TaggedMessageEvent {
+    TaggedMessageEvent {
+        now,
+        transport: transport(),
+        message: MessageEvent::Rtp(RTPMessageEvent::Rtp(rtp_packet(ssrc, seq))),
+    }
+}
+
+fn outbound_seqs(events: &[InterceptorEvent]) -> Vec<u16> {
+    events
+        .iter()
+        .filter_map(|event| match event {
+            InterceptorEvent::Outbound(TaggedMessageEvent {
+                message: MessageEvent::Rtp(RTPMessageEvent::Rtp(packet)),
+                ..
+            }) => Some(packet.header.sequence_number),
+            _ => None,
+        })
+        .collect()
+}
+
+fn outbound_nacks(events: &[InterceptorEvent]) -> Vec<TransportLayerNack> {
+    let mut nacks = vec![];
+    for event in events {
+        if let InterceptorEvent::Outbound(TaggedMessageEvent {
+            message: MessageEvent::Rtp(RTPMessageEvent::Rtcp(rtcp_packets)),
+            ..
+        }) = event
+        {
+            for rtcp_packet in rtcp_packets {
+                if let Some(nack) = rtcp_packet.as_any().downcast_ref::<TransportLayerNack>() {
+                    nacks.push(nack.clone());
+                }
+            }
+        }
+    }
+    nacks
+}
+
+#[test]
+fn test_generator_stream_gap_detection() {
+    let mut stream = GeneratorStream::new(0);
+    for seq in [0, 1, 3, 5, 6, 9] {
+        stream.add(seq);
+    }
+    assert_eq!(stream.missing_seq_numbers(0), vec![2, 4, 7, 8]);
+    assert_eq!(stream.missing_seq_numbers(2), vec![2, 4, 7]);
+
+    // late arrivals fill the gaps
+    stream.add(2);
+    stream.add(4);
+    assert_eq!(stream.missing_seq_numbers(0), vec![7, 8]);
+    stream.add(7);
+    stream.add(8);
+    assert!(stream.missing_seq_numbers(0).is_empty());
+}
+
+#[test]
+fn test_generator_stream_wraparound() {
+    let mut stream = GeneratorStream::new(0);
+    for seq in [65533, 65535, 1, 2] {
+        stream.add(seq);
+    }
+    assert_eq!(stream.missing_seq_numbers(0), vec![65534, 0]);
+
+    stream.add(65534);
+    stream.add(0);
+    assert!(stream.missing_seq_numbers(0).is_empty());
+}
+
+#[test]
+fn test_generator_stream_gap_larger_than_size() {
+    // 64 entries, so only the last 64 sequence numbers can be reported
+    let mut stream = GeneratorStream::new(0);
+    stream.add(0);
+    stream.add(100);
+    let missing = stream.missing_seq_numbers(0);
+    assert_eq!(missing.len(), 63);
+    assert_eq!(missing.first(), Some(&37));
+    assert_eq!(missing.last(), Some(&99));
+}
+
+#[test]
+fn test_responder_stream_lookup() {
+    let mut stream = ResponderStream::new(3);
+    for seq in [10, 11, 13] {
+        stream.add(&rtp_packet(1, seq));
+    }
+    assert_eq!(stream.get(10).map(|p| p.header.sequence_number), Some(10));
+    assert_eq!(stream.get(11).map(|p| p.header.sequence_number), Some(11));
+    assert!(stream.get(12).is_none());
+    assert_eq!(stream.get(13).map(|p| p.header.sequence_number), Some(13));
+    // not sent yet
+    assert!(stream.get(14).is_none());
+
+    // 8 entries, so sequence number 10 is overwritten once 18 is sent
+    for seq in 14..=18 {
+        stream.add(&rtp_packet(1, seq));
+    }
+    assert!(stream.get(10).is_none());
+    assert_eq!(stream.get(11).map(|p| p.header.sequence_number), Some(11));
+}
+
+#[test]
+fn test_responder_stream_wraparound() {
+    let mut stream = ResponderStream::new(3);
+    for seq in [65534, 65535, 1] {
+        stream.add(&rtp_packet(1, seq));
+    }
+    assert_eq!(
+        stream.get(65534).map(|p| p.header.sequence_number),
+        Some(65534)
+    );
+    assert_eq!(
+        stream.get(65535).map(|p| p.header.sequence_number),
+        Some(65535)
+    );
+    assert!(stream.get(0).is_none());
+    assert_eq!(stream.get(1).map(|p| p.header.sequence_number), Some(1));
+}
+
+#[test]
+fn test_generator_sends_nack() {
+    let now = Instant::now();
+    let mut generator = Box::new(
+        Generator::builder()
+            .with_log2_size(6)
+            .with_interval(Duration::from_millis(100))
+            .build_generator(),
+    );
+    for seq in [100, 101, 104] {
+        generator.read(&mut rtp_message(now, 1234, seq));
+    }
+
+    let four_tuple = FourTuple {
+        local_addr: transport().local_addr,
+        peer_addr: transport().peer_addr,
+    };
+    let later = now + Duration::from_millis(100);
+    let nacks = outbound_nacks(&generator.handle_timeout(later, &[four_tuple]));
+    assert_eq!(nacks.len(), 1);
+    assert_eq!(nacks[0].media_ssrc, 1234);
+    let missing: Vec<u16> = nacks[0]
+        .nacks
+        .iter()
+        .flat_map(|pair| pair.packet_list())
+        .collect();
+    assert_eq!(missing, vec![102, 103]);
+
+    // throttled until the next interval
+    let events = generator.handle_timeout(later + Duration::from_millis(10), &[four_tuple]);
+    assert!(outbound_nacks(&events).is_empty());
+}
+
+#[test]
+fn test_responder_retransmits_on_nack() {
+    let now = Instant::now();
+    let mut responder = Box::new(Responder::builder().with_log2_size(3).build_responder());
+    for seq in 1..=5 {
+        responder.write(&mut rtp_message(now, 1234, seq));
+    }
+
+    let mut nack = TaggedMessageEvent {
+        now,
+        transport: transport(),
+        message: MessageEvent::Rtp(RTPMessageEvent::Rtcp(vec![
+            Box::new(TransportLayerNack {
+                sender_ssrc: 1,
+                media_ssrc: 1234,
+                nacks: nack_pairs_from_sequence_numbers(&[2, 4, 42]),
+            }),
+            Box::new(TransportLayerNack {
+                sender_ssrc: 1,
+                media_ssrc: 5678,
+                nacks: nack_pairs_from_sequence_numbers(&[3]),
+            }),
+        ])),
+    };
+    let events = responder.read(&mut nack);
+    assert_eq!(outbound_seqs(&events), vec![2, 4]);
+}
@@ -0,0 +1,98 @@
+use crate::interceptors::nack::responder_stream::ResponderStream;
+use crate::interceptors::nack::NackBuilder;
+use crate::interceptors::stream_info::StreamInfo;
+use crate::interceptors::{Interceptor, InterceptorEvent};
+use crate::messages::{MessageEvent, RTPMessageEvent, TaggedMessageEvent};
+use rtcp::transport_feedbacks::transport_layer_nack::TransportLayerNack;
+use std::collections::HashMap;
+
+/// Responder interceptor keeps the recently sent packets of local streams
+/// and resends them when nack requests are received.
+pub(crate) struct Responder {
+    pub(super) log2_size: u8,
+    pub(crate) streams: HashMap<u32, ResponderStream>,
+    pub(super) next: Option<Box<dyn Interceptor>>,
+}
+
+impl Responder {
+    pub(crate) fn builder() -> NackBuilder {
+        NackBuilder {
+            is_generator: false,
+            ..Default::default()
+        }
+    }
+}
+
+impl Interceptor for Responder {
+    fn chain(mut self: Box<Self>, next: Box<dyn Interceptor>) -> Box<dyn Interceptor> {
+        self.next = Some(next);
+        self
+    }
+
+    fn next(&mut self) -> Option<&mut Box<dyn Interceptor>> {
+        self.next.as_mut()
+    }
+
+    fn read(&mut self, msg: &mut TaggedMessageEvent) -> Vec<InterceptorEvent> {
+        let mut interceptor_events = vec![];
+
+        if let MessageEvent::Rtp(RTPMessageEvent::Rtcp(rtcp_packets)) = &msg.message {
+            for rtcp_packet in rtcp_packets {
+                if let Some(nack) = rtcp_packet.as_any().downcast_ref::<TransportLayerNack>() {
+                    let Some(stream) = self.streams.get(&nack.media_ssrc) else {
+                        continue;
+                    };
+
+                    for nack_pair in &nack.nacks {
+                        for seq in nack_pair.packet_list() {
+                            if let Some(rtp_packet) = stream.get(seq) {
+                                interceptor_events.push(InterceptorEvent::Outbound(
+                                    TaggedMessageEvent {
+                                        now: msg.now,
+                                        transport: msg.transport,
+                                        message: MessageEvent::Rtp(RTPMessageEvent::Rtp(
+                                            rtp_packet.clone(),
+                                        )),
+                                    },
+                                ));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        if let Some(next) = self.next() {
+            let mut events = next.read(msg);
+            interceptor_events.append(&mut events);
+        }
+        interceptor_events
+    }
+
+    fn write(&mut self, msg: &mut TaggedMessageEvent) -> Vec<InterceptorEvent> {
+        if let MessageEvent::Rtp(RTPMessageEvent::Rtp(rtp_packet)) = &msg.message {
+            let log2_size = self.log2_size;
+            let stream = self
+                .streams
+                .entry(rtp_packet.header.ssrc)
+                .or_insert_with(|| ResponderStream::new(log2_size));
+            stream.add(rtp_packet);
+        }
+
+        if let Some(next) = self.next() {
+            next.write(msg)
+        } else {
+            vec![]
+        }
+    }
+
+    fn unbind_local_stream(&mut self, info: &StreamInfo) {
+        for ssrc in &info.ssrcs {
+            self.streams.remove(ssrc);
+        }
+
+        if let Some(next) = self.next() {
+            next.unbind_local_stream(info);
+        }
+    }
+}
@@ -0,0 +1,58 @@
+use crate::interceptors::nack::UINT16SIZE_HALF;
+
+/// ResponderStream keeps a ring buffer of the recently sent packets of a local stream,
+/// so that they can be resent upon nack requests.
+pub(crate) struct ResponderStream {
+    packets: Vec<Option<rtp::packet::Packet>>,
+    size: u16,
+    last_added: u16,
+    started: bool,
+}
+
+impl ResponderStream {
+    pub(crate) fn new(log2_size: u8) -> Self {
+        Self {
+            packets: vec![None; 1 << log2_size],
+            size: 1 << log2_size,
+            last_added: 0,
+            started: false,
+        }
+    }
+
+    pub(crate) fn add(&mut self, packet: &rtp::packet::Packet) {
+        let seq = packet.header.sequence_number;
+        if !self.started {
+            self.packets[(seq % self.size) as usize] = Some(packet.clone());
+            self.last_added = seq;
+            self.started = true;
+            return;
+        }
+
+        let diff = seq.wrapping_sub(self.last_added);
+        if diff == 0 {
+            return;
+        } else if diff < UINT16SIZE_HALF {
+            let mut i = self.last_added.wrapping_add(1);
+            while i != seq {
+                self.packets[(i % self.size) as usize] = None;
+                i = i.wrapping_add(1);
+            }
+        }
+
+        self.packets[(seq % self.size) as usize] = Some(packet.clone());
+        self.last_added = seq;
+    }
+
+    pub(crate) fn get(&self, seq: u16) -> Option<&rtp::packet::Packet> {
+        let diff = self.last_added.wrapping_sub(seq);
+        if diff >= UINT16SIZE_HALF {
+            return None;
+        }
+
+        if diff >= self.size {
+            return None;
+        }
+
+        self.packets[(seq % self.size) as usize].as_ref()
+    }
+}
@@ -5,6 +5,7 @@ use std::time::{Duration, Instant};
 pub(crate) mod receiver_report;
 pub(crate) mod receiver_stream;
 pub(crate) mod sender_report;
+pub(crate) mod sender_stream;
 
 use receiver_report::ReceiverReport;
 use sender_report::SenderReport;
@@ -37,7 +38,16 @@ impl ReportBuilder {
     }
 
     fn build_sr(&self) -> SenderReport {
-        SenderReport { next: None }
+        SenderReport {
+            interval: if let Some(interval) = &self.interval {
+                *interval
+            } else {
+                Duration::from_secs(1)
+            },
+            eto: Instant::now(),
+            streams: HashMap::new(),
+            next: None,
+        }
     }
 }
 
@@ -50,3 +60,6 @@ impl InterceptorBuilder for ReportBuilder {
         }
     }
 }
+
+#[cfg(test)]
+mod report_test;
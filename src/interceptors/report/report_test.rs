@@ -0,0 +1,128 @@
+use super::sender_report::SenderReport;
+use super::sender_stream::SenderStream;
+use crate::interceptors::stream_info::StreamInfo;
+use crate::interceptors::{Interceptor, InterceptorEvent};
+use crate::messages::{MessageEvent, RTPMessageEvent, TaggedMessageEvent};
+use crate::types::FourTuple;
+use bytes::Bytes;
+use std::time::{Duration, Instant};
+
+const NTP_ONE_SECOND: u64 = 1 << 32;
+
+fn rtp_packet(ssrc: u32, payload_len: usize) -> rtp::packet::Packet {
+    let mut packet = rtp::packet::Packet::default();
+    packet.header.ssrc = ssrc;
+    packet.payload = Bytes::from(vec![0u8; payload_len]);
+    packet
+}
+
+fn sender_reports(events: &[InterceptorEvent]) -> Vec<rtcp::sender_report::SenderReport> {
+    let mut reports = vec![];
+    for event in events {
+        if let InterceptorEvent::Outbound(TaggedMessageEvent {
+            message: MessageEvent::Rtp(RTPMessageEvent::Rtcp(rtcp_packets)),
+            ..
+        }) = event
+        {
+            for rtcp_packet in rtcp_packets {
+                if let Some(sr) = rtcp_packet
+                    .as_any()
+                    .downcast_ref::<rtcp::sender_report::SenderReport>()
+                {
+                    reports.push(sr.clone());
+                }
+            }
+        }
+    }
+    reports
+}
+
+#[test]
+fn test_sender_stream_without_remote_report() {
+    let stream = SenderStream::new(1234, 90000);
+    assert!(stream.generate_report(Instant::now()).is_none());
+}
+
+#[test]
+fn test_sender_stream_ntp_rtp_mapping() {
+    let now = Instant::now();
+    let mut stream = SenderStream::new(1234, 90000);
+    stream.process_rtp(&rtp_packet(1234, 100));
+    stream.process_rtp(&rtp_packet(1234, 50));
+    let ntp_time = 0xe000_0000_8000_0000; // some seconds and a half
+    stream.process_sender_report(now, ntp_time, 1_000_000);
+
+    // reported as is, right after the remote sender report
+    let sr = stream.generate_report(now).unwrap();
+    assert_eq!(sr.ssrc, 1234);
+    assert_eq!(sr.ntp_time, ntp_time);
+    assert_eq!(sr.rtp_time, 1_000_000);
+    assert_eq!(sr.packet_count, 2);
+    assert_eq!(sr.octet_count, 150);
+
+    // both clocks advance by the time elapsed since the remote sender report
+    let sr = stream
+        .generate_report(now + Duration::from_millis(1500))
+        .unwrap();
+    assert_eq!(sr.ntp_time, ntp_time + NTP_ONE_SECOND + NTP_ONE_SECOND / 2);
+    assert_eq!(sr.rtp_time, 1_000_000 + 135_000);
+
+    // a newer remote sender report replaces the mapping
+    stream.process_sender_report(
+        now + Duration::from_secs(2),
+        ntp_time + 2 * NTP_ONE_SECOND,
+        7,
+    );
+    let sr = stream
+        .generate_report(now + Duration::from_secs(3))
+        .unwrap();
+    assert_eq!(sr.ntp_time, ntp_time + 3 * NTP_ONE_SECOND);
+    assert_eq!(sr.rtp_time, 7 + 90000);
+}
+
+#[test]
+fn test_sender_stream_rtp_time_wraparound() {
+    let now = Instant::now();
+    let mut stream = SenderStream::new(1234, 48000);
+    stream.process_sender_report(now, NTP_ONE_SECOND, u32::MAX - 999);
+
+    let sr = stream
+        .generate_report(now + Duration::from_millis(100))
+        .unwrap();
+    assert_eq!(sr.rtp_time, 3800);
+}
+
+#[test]
+fn test_sender_report_bind_and_unbind_local_stream() {
+    let four_tuple = FourTuple {
+        local_addr: "127.0.0.1:3478".parse().unwrap(),
+        peer_addr: "127.0.0.1:5000".parse().unwrap(),
+    };
+    let info = StreamInfo {
+        mid: "1-0".to_string(),
+        ssrcs: vec![1234],
+        clock_rate: 90000,
+        ..Default::default()
+    };
+    let mut sender_report = Box::new(
+        SenderReport::builder()
+            .with_interval(Duration::from_secs(1))
+            .build_sr(),
+    );
+
+    let now = Instant::now();
+
+    sender_report.bind_local_stream(&info);
+    // ssrcs which are not bound are ignored
+    sender_report.forward_sender_report(now, 5678, NTP_ONE_SECOND, 1000);
+    sender_report.forward_sender_report(now, 1234, NTP_ONE_SECOND, 1000);
+    let reports = sender_reports(&sender_report.handle_timeout(now, &[four_tuple]));
+    assert_eq!(reports.len(), 1);
+    assert_eq!(reports[0].ssrc, 1234);
+    assert_eq!(reports[0].rtp_time, 1000);
+
+    // no more reports once the stream is no longer forwarded
+    sender_report.unbind_local_stream(&info);
+    let later = now + Duration::from_secs(1);
+    assert!(sender_reports(&sender_report.handle_timeout(later, &[four_tuple])).is_empty());
+}
@@ -1,9 +1,18 @@
+use crate::interceptors::report::sender_stream::SenderStream;
 use crate::interceptors::report::ReportBuilder;
+use crate::interceptors::stream_info::StreamInfo;
 use crate::interceptors::{Interceptor, InterceptorEvent};
 use crate::messages::{MessageEvent, RTPMessageEvent, TaggedMessageEvent};
+use crate::types::FourTuple;
+use retty::transport::TransportContext;
 use rtcp::header::PacketType;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
 
 pub(crate) struct SenderReport {
+    pub(super) interval: Duration,
+    pub(super) eto: Instant,
+    pub(crate) streams: HashMap<u32, SenderStream>,
     pub(super) next: Option<Box<dyn Interceptor>>,
 }
 
@@ -60,4 +69,92 @@ impl Interceptor for SenderReport {
         }
         interceptor_events
     }
+
+    fn write(&mut self, msg: &mut TaggedMessageEvent) -> Vec<InterceptorEvent> {
+        if let MessageEvent::Rtp(RTPMessageEvent::Rtp(rtp_packet)) = &msg.message {
+            if let Some(stream) = self.streams.get_mut(&rtp_packet.header.ssrc) {
+                stream.process_rtp(rtp_packet);
+            }
+        }
+
+        if let Some(next) = self.next() {
+            next.write(msg)
+        } else {
+            vec![]
+        }
+    }
+
+    fn handle_timeout(&mut self, now: Instant, four_tuples: &[FourTuple]) -> Vec<InterceptorEvent> {
+        let mut interceptor_events = vec![];
+
+        if self.eto <= now {
+            self.eto = now + self.interval;
+
+            for stream in self.streams.values() {
+                let Some(sr) = stream.generate_report(now) else {
+                    continue;
+                };
+                for four_tuple in four_tuples {
+                    interceptor_events.push(InterceptorEvent::Outbound(TaggedMessageEvent {
+                        now,
+                        transport: TransportContext {
+                            local_addr: four_tuple.local_addr,
+                            peer_addr: four_tuple.peer_addr,
+                            ecn: None,
+                        },
+                        message: MessageEvent::Rtp(RTPMessageEvent::Rtcp(vec![Box::new(
+                            sr.clone(),
+                        )])),
+                    }));
+                }
+            }
+        }
+
+        if let Some(next) = self.next() {
+            let mut events = next.handle_timeout(now, four_tuples);
+            interceptor_events.append(&mut events);
+        }
+        interceptor_events
+    }
+
+    fn poll_timeout(&mut self, eto: &mut Instant) {
+        if self.eto < *eto {
+            *eto = self.eto
+        }
+
+        if let Some(next) = self.next() {
+            next.poll_timeout(eto);
+        }
+    }
+
+    fn bind_local_stream(&mut self, info: &StreamInfo) {
+        for ssrc in &info.ssrcs {
+            self.streams
+                .insert(*ssrc, SenderStream::new(*ssrc, info.clock_rate));
+        }
+
+        if let Some(next) = self.next() {
+            next.bind_local_stream(info);
+        }
+    }
+
+    fn unbind_local_stream(&mut self, info: &StreamInfo) {
+        for ssrc in &info.ssrcs {
+            self.streams.remove(ssrc);
+        }
+
+        if let Some(next) = self.next() {
+            next.unbind_local_stream(info);
+        }
+    }
+
+    fn forward_sender_report(&mut self, now: Instant, ssrc: u32, ntp_time: u64, rtp_time: u32) {
+        if let Some(stream) = self.streams.get_mut(&ssrc) {
+            stream.process_sender_report(now, ntp_time, rtp_time);
+        }
+
+        if let Some(next) = self.next() {
+            next.forward_sender_report(now, ssrc, ntp_time, rtp_time);
+        }
+    }
 }
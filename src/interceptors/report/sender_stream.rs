@@ -0,0 +1,66 @@
+use std::time::{Duration, Instant};
+
+pub(crate) struct SenderStream {
+    ssrc: u32,
+    clock_rate: f64,
+
+    packet_count: u32,
+    octet_count: u32,
+
+    // NTP time and RTP time reported by the remote sender, and when it was received
+    last_ntp_time: u64,
+    last_rtp_time: u32,
+    last_time: Option<Instant>,
+}
+
+impl SenderStream {
+    pub(crate) fn new(ssrc: u32, clock_rate: u32) -> Self {
+        Self {
+            ssrc,
+            clock_rate: clock_rate as f64,
+
+            packet_count: 0,
+            octet_count: 0,
+
+            last_ntp_time: 0,
+            last_rtp_time: 0,
+            last_time: None,
+        }
+    }
+
+    pub(crate) fn process_rtp(&mut self, pkt: &rtp::packet::Packet) {
+        self.packet_count = self.packet_count.wrapping_add(1);
+        self.octet_count = self.octet_count.wrapping_add(pkt.payload.len() as u32);
+    }
+
+    pub(crate) fn process_sender_report(&mut self, now: Instant, ntp_time: u64, rtp_time: u32) {
+        self.last_ntp_time = ntp_time;
+        self.last_rtp_time = rtp_time;
+        self.last_time = Some(now);
+    }
+
+    /// generate_report returns None until the remote sender reports its timestamp mapping
+    pub(crate) fn generate_report(
+        &self,
+        now: Instant,
+    ) -> Option<rtcp::sender_report::SenderReport> {
+        let last_time = self.last_time?;
+        let elapsed = now.saturating_duration_since(last_time);
+
+        Some(rtcp::sender_report::SenderReport {
+            ssrc: self.ssrc,
+            ntp_time: self.last_ntp_time.wrapping_add(duration_to_ntp(elapsed)),
+            rtp_time: self
+                .last_rtp_time
+                .wrapping_add((elapsed.as_secs_f64() * self.clock_rate) as u32),
+            packet_count: self.packet_count,
+            octet_count: self.octet_count,
+            ..Default::default()
+        })
+    }
+}
+
+/// duration_to_ntp converts duration into 32.32 fixed point NTP format
+fn duration_to_ntp(d: Duration) -> u64 {
+    (d.as_secs() << 32) + ((d.subsec_nanos() as u64) << 32) / 1_000_000_000
+}
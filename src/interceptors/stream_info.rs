@@ -0,0 +1,11 @@
+use crate::description::rtp_codec::RTCRtpHeaderExtensionParameters;
+use crate::types::Mid;
+
+/// StreamInfo is the Context passed when a remote or local stream has been bound to the Interceptor
+#[derive(Default, Debug, Clone)]
+pub struct StreamInfo {
+    pub mid: Mid,
+    pub ssrcs: Vec<u32>,
+    pub rtp_header_extensions: Vec<RTCRtpHeaderExtensionParameters>,
+    pub clock_rate: u32,
+}
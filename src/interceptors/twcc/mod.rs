@@ -1 +1,47 @@
+use crate::interceptors::{Interceptor, InterceptorBuilder};
+use std::time::{Duration, Instant};
 
+pub(crate) mod receiver;
+pub(crate) mod recorder;
+
+use receiver::Receiver;
+use recorder::Recorder;
+
+/// TwccBuilder can be used to configure TWCC Receiver Interceptor.
+#[derive(Default)]
+pub struct TwccBuilder {
+    interval: Option<Duration>,
+}
+
+impl TwccBuilder {
+    /// with_interval sets send interval for the interceptor.
+    pub fn with_interval(mut self, interval: Duration) -> TwccBuilder {
+        self.interval = Some(interval);
+        self
+    }
+
+    fn build_receiver(&self) -> Receiver {
+        let now = Instant::now();
+        Receiver {
+            interval: if let Some(interval) = &self.interval {
+                *interval
+            } else {
+                Duration::from_millis(100)
+            },
+            eto: now,
+            start_time: now,
+            hdr_ext_id: None,
+            recorder: Recorder::new(rand::random::<u32>()),
+            next: None,
+        }
+    }
+}
+
+impl InterceptorBuilder for TwccBuilder {
+    fn build(&self, _id: &str) -> Box<dyn Interceptor> {
+        Box::new(self.build_receiver())
+    }
+}
+
+#[cfg(test)]
+mod twcc_test;
@@ -0,0 +1,122 @@
+use crate::interceptors::stream_info::StreamInfo;
+use crate::interceptors::twcc::{Recorder, TwccBuilder};
+use crate::interceptors::{Interceptor, InterceptorEvent};
+use crate::messages::{MessageEvent, RTPMessageEvent, TaggedMessageEvent};
+use crate::types::FourTuple;
+use log::debug;
+use retty::transport::TransportContext;
+use rtp::extension::transport_cc_extension::TransportCcExtension;
+use shared::marshal::Unmarshal;
+use std::time::{Duration, Instant};
+
+/// Receiver interceptor records the arrival time of incoming RTP packets carrying
+/// the transport wide sequence number header extension and periodically sends
+/// TransportLayerCc feedback back to the remote peer.
+pub(crate) struct Receiver {
+    pub(super) interval: Duration,
+    pub(super) eto: Instant,
+    pub(super) start_time: Instant,
+    pub(super) hdr_ext_id: Option<u8>,
+    pub(super) recorder: Recorder,
+    pub(super) next: Option<Box<dyn Interceptor>>,
+}
+
+impl Receiver {
+    pub(crate) fn builder() -> TwccBuilder {
+        TwccBuilder::default()
+    }
+}
+
+impl Interceptor for Receiver {
+    fn chain(mut self: Box<Self>, next: Box<dyn Interceptor>) -> Box<dyn Interceptor> {
+        self.next = Some(next);
+        self
+    }
+
+    fn next(&mut self) -> Option<&mut Box<dyn Interceptor>> {
+        self.next.as_mut()
+    }
+
+    fn read(&mut self, msg: &mut TaggedMessageEvent) -> Vec<InterceptorEvent> {
+        if let (Some(hdr_ext_id), MessageEvent::Rtp(RTPMessageEvent::Rtp(rtp_packet))) =
+            (self.hdr_ext_id, &msg.message)
+        {
+            if let Some(mut ext) = rtp_packet.header.get_extension(hdr_ext_id) {
+                match TransportCcExtension::unmarshal(&mut ext) {
+                    Ok(tcc_ext) => {
+                        self.recorder.record(
+                            rtp_packet.header.ssrc,
+                            tcc_ext.transport_sequence,
+                            msg.now
+                                .saturating_duration_since(self.start_time)
+                                .as_micros() as i64,
+                        );
+                    }
+                    Err(err) => {
+                        debug!("failed to unmarshal transport cc extension: {}", err);
+                    }
+                }
+            }
+        }
+
+        if let Some(next) = self.next() {
+            next.read(msg)
+        } else {
+            vec![]
+        }
+    }
+
+    fn handle_timeout(&mut self, now: Instant, four_tuples: &[FourTuple]) -> Vec<InterceptorEvent> {
+        let mut interceptor_events = vec![];
+
+        if self.eto <= now {
+            self.eto = now + self.interval;
+
+            let rtcp_packets = self.recorder.build_feedback_packet();
+            if !rtcp_packets.is_empty() {
+                for four_tuple in four_tuples {
+                    interceptor_events.push(InterceptorEvent::Outbound(TaggedMessageEvent {
+                        now,
+                        transport: TransportContext {
+                            local_addr: four_tuple.local_addr,
+                            peer_addr: four_tuple.peer_addr,
+                            ecn: None,
+                        },
+                        message: MessageEvent::Rtp(RTPMessageEvent::Rtcp(rtcp_packets.clone())),
+                    }));
+                }
+            }
+        }
+
+        if let Some(next) = self.next() {
+            let mut events = next.handle_timeout(now, four_tuples);
+            interceptor_events.append(&mut events);
+        }
+        interceptor_events
+    }
+
+    fn poll_timeout(&mut self, eto: &mut Instant) {
+        if self.eto < *eto {
+            *eto = self.eto
+        }
+
+        if let Some(next) = self.next() {
+            next.poll_timeout(eto);
+        }
+    }
+
+    fn bind_remote_stream(&mut self, info: &StreamInfo) {
+        // transport wide sequence number shares the same id across all bundled media sections
+        if let Some(ext) = info
+            .rtp_header_extensions
+            .iter()
+            .find(|ext| ext.uri == sdp::extmap::TRANSPORT_CC_URI)
+        {
+            self.hdr_ext_id = Some(ext.id as u8);
+        }
+
+        if let Some(next) = self.next() {
+            next.bind_remote_stream(info);
+        }
+    }
+}
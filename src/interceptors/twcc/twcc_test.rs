@@ -0,0 +1,219 @@
+use super::recorder::Recorder;
+use rtcp::transport_feedbacks::transport_layer_cc::{
+    PacketStatusChunk, SymbolSizeTypeTcc, SymbolTypeTcc, TransportLayerCc,
+};
+
+fn feedbacks(recorder: &mut Recorder) -> Vec<TransportLayerCc> {
+    recorder
+        .build_feedback_packet()
+        .iter()
+        .map(|p| {
+            p.as_any()
+                .downcast_ref::<TransportLayerCc>()
+                .expect("TWCC feedback")
+                .clone()
+        })
+        .collect()
+}
+
+fn run_length(chunk: &PacketStatusChunk) -> (SymbolTypeTcc, u16) {
+    match chunk {
+        PacketStatusChunk::RunLengthChunk(c) => (c.packet_status_symbol, c.run_length),
+        _ => panic!("expected run length chunk, got {:?}", chunk),
+    }
+}
+
+fn status_vector(chunk: &PacketStatusChunk) -> (SymbolSizeTypeTcc, Vec<SymbolTypeTcc>) {
+    match chunk {
+        PacketStatusChunk::StatusVectorChunk(c) => (c.symbol_size, c.symbol_list.clone()),
+        _ => panic!("expected status vector chunk, got {:?}", chunk),
+    }
+}
+
+#[test]
+fn test_recorder_needs_two_packets() {
+    let mut recorder = Recorder::new(1);
+    assert!(feedbacks(&mut recorder).is_empty());
+    recorder.record(2, 0, 0);
+    assert!(feedbacks(&mut recorder).is_empty());
+}
+
+#[test]
+fn test_recorder_run_length_chunk() {
+    let mut recorder = Recorder::new(1);
+    for seq in 0..5u16 {
+        recorder.record(2, seq, seq as i64 * 1000);
+    }
+
+    let fbs = feedbacks(&mut recorder);
+    assert_eq!(fbs.len(), 1);
+    let fb = &fbs[0];
+    assert_eq!(fb.sender_ssrc, 1);
+    assert_eq!(fb.media_ssrc, 2);
+    assert_eq!(fb.base_sequence_number, 0);
+    assert_eq!(fb.packet_status_count, 5);
+    assert_eq!(fb.packet_chunks.len(), 1);
+    assert_eq!(
+        run_length(&fb.packet_chunks[0]),
+        (SymbolTypeTcc::PacketReceivedSmallDelta, 5)
+    );
+    let deltas: Vec<i64> = fb.recv_deltas.iter().map(|d| d.delta).collect();
+    assert_eq!(deltas, vec![0, 1000, 1000, 1000, 1000]);
+}
+
+#[test]
+fn test_recorder_two_bit_status_vector_chunk() {
+    let mut recorder = Recorder::new(1);
+    recorder.record(2, 0, 0);
+    recorder.record(2, 1, 1000);
+    recorder.record(2, 3, 2000);
+
+    let fbs = feedbacks(&mut recorder);
+    assert_eq!(fbs.len(), 1);
+    let fb = &fbs[0];
+    assert_eq!(fb.packet_status_count, 4);
+    assert_eq!(fb.packet_chunks.len(), 1);
+    assert_eq!(
+        status_vector(&fb.packet_chunks[0]),
+        (
+            SymbolSizeTypeTcc::TwoBit,
+            vec![
+                SymbolTypeTcc::PacketReceivedSmallDelta,
+                SymbolTypeTcc::PacketReceivedSmallDelta,
+                SymbolTypeTcc::PacketNotReceived,
+                SymbolTypeTcc::PacketReceivedSmallDelta,
+            ]
+        )
+    );
+    // lost packets have no delta
+    assert_eq!(fb.recv_deltas.len(), 3);
+}
+
+#[test]
+fn test_recorder_one_bit_status_vector_chunk() {
+    let mut recorder = Recorder::new(1);
+    for seq in (0..16u16).filter(|seq| *seq != 1) {
+        recorder.record(2, seq, seq as i64 * 1000);
+    }
+
+    let fbs = feedbacks(&mut recorder);
+    assert_eq!(fbs.len(), 1);
+    let fb = &fbs[0];
+    assert_eq!(fb.packet_status_count, 16);
+    assert_eq!(fb.packet_chunks.len(), 2);
+
+    let (symbol_size, symbols) = status_vector(&fb.packet_chunks[0]);
+    assert_eq!(symbol_size, SymbolSizeTypeTcc::OneBit);
+    assert_eq!(symbols.len(), 14);
+    assert_eq!(symbols[1], SymbolTypeTcc::PacketNotReceived);
+    assert!(symbols
+        .iter()
+        .enumerate()
+        .all(|(i, s)| i == 1 || *s == SymbolTypeTcc::PacketReceivedSmallDelta));
+    assert_eq!(
+        run_length(&fb.packet_chunks[1]),
+        (SymbolTypeTcc::PacketReceivedSmallDelta, 2)
+    );
+}
+
+#[test]
+fn test_recorder_delta_encoding() {
+    let mut recorder = Recorder::new(1);
+    // small delta: 0..=255 units of 250us
+    recorder.record(2, 0, 64_000);
+    recorder.record(2, 1, 64_000 + 255 * 250);
+    // large delta: above 255 units
+    recorder.record(2, 2, 64_000 + 255 * 250 + 256 * 250);
+    // large delta: negative (reordered arrival)
+    recorder.record(2, 3, 64_000);
+
+    let fbs = feedbacks(&mut recorder);
+    assert_eq!(fbs.len(), 1);
+    let fb = &fbs[0];
+    assert_eq!(fb.reference_time, 1);
+    let deltas: Vec<(SymbolTypeTcc, i64)> = fb
+        .recv_deltas
+        .iter()
+        .map(|d| (d.type_tcc_packet, d.delta))
+        .collect();
+    assert_eq!(
+        deltas,
+        vec![
+            (SymbolTypeTcc::PacketReceivedSmallDelta, 0),
+            (SymbolTypeTcc::PacketReceivedSmallDelta, 255 * 250),
+            (SymbolTypeTcc::PacketReceivedLargeDelta, 256 * 250),
+            (
+                SymbolTypeTcc::PacketReceivedLargeDelta,
+                -(255 * 250 + 256 * 250)
+            ),
+        ]
+    );
+}
+
+#[test]
+fn test_recorder_splits_on_delta_overflow() {
+    let mut recorder = Recorder::new(1);
+    recorder.record(2, 0, 0);
+    recorder.record(2, 1, 1000);
+    // 40000 units of 250us doesn't fit into a 16 bit delta
+    recorder.record(2, 2, 10_000_000);
+    recorder.record(2, 3, 10_001_000);
+
+    let fbs = feedbacks(&mut recorder);
+    assert_eq!(fbs.len(), 2);
+    assert_eq!(fbs[0].fb_pkt_count, 0);
+    assert_eq!(fbs[0].base_sequence_number, 0);
+    assert_eq!(fbs[0].packet_status_count, 2);
+    assert_eq!(fbs[1].fb_pkt_count, 1);
+    assert_eq!(fbs[1].base_sequence_number, 2);
+    assert_eq!(fbs[1].packet_status_count, 2);
+
+    // feedback packet count keeps increasing across reports
+    recorder.record(2, 4, 10_002_000);
+    recorder.record(2, 5, 10_003_000);
+    let fbs = feedbacks(&mut recorder);
+    assert_eq!(fbs.len(), 1);
+    assert_eq!(fbs[0].fb_pkt_count, 2);
+    assert_eq!(fbs[0].base_sequence_number, 4);
+}
+
+#[test]
+fn test_recorder_sequence_number_wraparound() {
+    let mut recorder = Recorder::new(1);
+    recorder.record(2, 65534, 0);
+    recorder.record(2, 0, 2000);
+    recorder.record(2, 65535, 1000);
+    recorder.record(2, 1, 3000);
+
+    let fbs = feedbacks(&mut recorder);
+    assert_eq!(fbs.len(), 1);
+    let fb = &fbs[0];
+    assert_eq!(fb.base_sequence_number, 65534);
+    assert_eq!(fb.packet_status_count, 4);
+    assert_eq!(fb.packet_chunks.len(), 1);
+    assert_eq!(
+        run_length(&fb.packet_chunks[0]),
+        (SymbolTypeTcc::PacketReceivedSmallDelta, 4)
+    );
+    let deltas: Vec<i64> = fb.recv_deltas.iter().map(|d| d.delta).collect();
+    assert_eq!(deltas, vec![0, 1000, 1000, 1000]);
+}
+
+#[test]
+fn test_recorder_many_sequence_number_cycles() {
+    let mut recorder = Recorder::new(1);
+    for _ in 0..=u16::MAX as usize {
+        for (first, second) in [(0x1000, 0x1001), (0xf001, 0xf002), (0xffff, 0)] {
+            recorder.record(2, first, 0);
+            recorder.record(2, second, 1000);
+            feedbacks(&mut recorder);
+        }
+    }
+
+    recorder.record(2, 0xffff, 0);
+    recorder.record(2, 0, 1000);
+    let fbs = feedbacks(&mut recorder);
+    assert_eq!(fbs.len(), 1);
+    assert_eq!(fbs[0].base_sequence_number, 0xffff);
+    assert_eq!(fbs[0].packet_status_count, 2);
+}
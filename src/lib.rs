@@ -12,11 +12,22 @@ pub(crate) mod server;
 pub(crate) mod session;
 pub(crate) mod types;
 
-pub use configs::{media_config::MediaConfig, server_config::ServerConfig};
+pub use configs::{
+    media_config::MediaConfig,
+    server_config::{DataChannelRelayFilter, ServerConfig},
+};
 pub use description::RTCSessionDescription;
+pub use endpoint::transport::{RTCIceConnectionState, SrtpDecryptErrorCounts};
 pub use handlers::{
     datachannel::DataChannelHandler, demuxer::DemuxerHandler, dtls::DtlsHandler,
     exception::ExceptionHandler, gateway::GatewayHandler, interceptor::InterceptorHandler,
-    sctp::SctpHandler, srtp::SrtpHandler, stun::StunHandler,
+    sctp::SctpHandler, srtp::SrtpHandler, stun::StunHandler, tcp_framing::TcpFramingHandler,
+};
+pub use messages::DataChannelOptions;
+pub use server::{
+    certificate::RTCCertificate,
+    events::ServerEvent,
+    signaling::{SignalingEnvelope, SignalingMessage, SIGNALING_VERSION},
+    states::ServerStates,
 };
-pub use server::{certificate::RTCCertificate, states::ServerStates};
+pub use types::FourTuple;
@@ -11,17 +11,51 @@ pub(crate) enum DataChannelMessageType {
     Text,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub(crate) struct DataChannelMessageParams {
     pub(crate) unordered: bool,
     pub(crate) reliability_type: ReliabilityType,
     pub(crate) reliability_parameter: u32,
 }
 
-#[derive(Debug, Clone, Eq, PartialEq)]
+/// DataChannelOptions configures the data channel opened by ServerStates::open_data_channel,
+/// which is reliable and ordered by default
+#[derive(Default, Debug, Clone)]
+pub struct DataChannelOptions {
+    pub protocol: String,
+    pub unordered: bool,
+    pub max_retransmits: Option<u32>,
+    pub max_packet_life_time: Option<u32>,
+}
+
+impl From<&DataChannelOptions> for DataChannelMessageParams {
+    fn from(options: &DataChannelOptions) -> Self {
+        let (reliability_type, reliability_parameter) =
+            if let Some(max_retransmits) = options.max_retransmits {
+                (ReliabilityType::Rexmit, max_retransmits)
+            } else if let Some(max_packet_life_time) = options.max_packet_life_time {
+                (ReliabilityType::Timed, max_packet_life_time)
+            } else {
+                (ReliabilityType::Reliable, 0)
+            };
+
+        Self {
+            unordered: options.unordered,
+            reliability_type,
+            reliability_parameter,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub(crate) enum DataChannelEvent {
-    Open,
-    Message(BytesMut),
+    Open {
+        label: String,
+        protocol: String,
+        params: DataChannelMessageParams,
+    },
+    Ack,
+    Message(DataChannelMessageType, BytesMut),
     Close,
 }
 
@@ -10,6 +10,9 @@ pub(crate) struct Metrics {
     rtcp_packet_out_count: Counter<u64>,
     remote_srtp_context_not_set_count: Counter<u64>,
     local_srtp_context_not_set_count: Counter<u64>,
+    srtp_replayed_count: Counter<u64>,
+    srtp_auth_failed_count: Counter<u64>,
+    srtp_decrypt_failed_count: Counter<u64>,
     rtp_packet_processing_time: ObservableGauge<u64>,
     rtcp_packet_processing_time: ObservableGauge<u64>,
 }
@@ -27,6 +30,9 @@ impl Metrics {
             local_srtp_context_not_set_count: meter
                 .u64_counter("local_srtp_context_not_set_count")
                 .init(),
+            srtp_replayed_count: meter.u64_counter("srtp_replayed_count").init(),
+            srtp_auth_failed_count: meter.u64_counter("srtp_auth_failed_count").init(),
+            srtp_decrypt_failed_count: meter.u64_counter("srtp_decrypt_failed_count").init(),
             rtp_packet_processing_time: meter
                 .u64_observable_gauge("rtp_packet_processing_time")
                 .with_unit(Unit::new("us"))
@@ -71,6 +77,18 @@ impl Metrics {
         self.local_srtp_context_not_set_count.add(value, attributes);
     }
 
+    pub(crate) fn record_srtp_replayed_count(&self, value: u64, attributes: &[KeyValue]) {
+        self.srtp_replayed_count.add(value, attributes);
+    }
+
+    pub(crate) fn record_srtp_auth_failed_count(&self, value: u64, attributes: &[KeyValue]) {
+        self.srtp_auth_failed_count.add(value, attributes);
+    }
+
+    pub(crate) fn record_srtp_decrypt_failed_count(&self, value: u64, attributes: &[KeyValue]) {
+        self.srtp_decrypt_failed_count.add(value, attributes);
+    }
+
     pub(crate) fn record_rtp_packet_processing_time(&self, value: u64, attributes: &[KeyValue]) {
         self.rtp_packet_processing_time.observe(value, attributes);
     }
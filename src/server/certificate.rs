@@ -6,10 +6,19 @@ use ring::rand::SystemRandom;
 use ring::rsa;
 use ring::signature::{EcdsaKeyPair, Ed25519KeyPair};
 use serde::{Deserialize, Serialize};
-use sha2::{Digest, Sha256};
+use sha2::{Digest, Sha256, Sha384, Sha512};
 use shared::error::{Error, Result};
 use std::ops::Add;
+use std::sync::Arc;
 use std::time::{Duration, SystemTime};
+use x509_parser::oid_registry::{
+    OID_PKCS1_SHA384WITHRSA, OID_PKCS1_SHA512WITHRSA, OID_SIG_ECDSA_WITH_SHA384,
+    OID_SIG_ECDSA_WITH_SHA512,
+};
+
+/// Hash function textual names of fingerprint algorithms supported by DTLS certificate
+/// verification, RFC 8122 S5
+pub(crate) const SUPPORTED_FINGERPRINT_ALGORITHMS: [&str; 3] = ["sha-256", "sha-384", "sha-512"];
 
 /// DTLSFingerprint specifies the hash function algorithm and certificate
 /// fingerprint as described in <https://tools.ietf.org/html/rfc4572>.
@@ -41,6 +50,36 @@ impl TryFrom<&str> for RTCDtlsFingerprint {
     }
 }
 
+impl RTCDtlsFingerprint {
+    /// Computes the fingerprint of DER encoded certificate with one of the hash function
+    /// algorithms in SUPPORTED_FINGERPRINT_ALGORITHMS.
+    pub fn new(algorithm: &str, certificate: &[u8]) -> Result<Self> {
+        let algorithm = algorithm.to_lowercase();
+        let hashed = match algorithm.as_str() {
+            "sha-256" => Sha256::digest(certificate).to_vec(),
+            "sha-384" => Sha384::digest(certificate).to_vec(),
+            "sha-512" => Sha512::digest(certificate).to_vec(),
+            _ => {
+                return Err(Error::Other(format!(
+                    "unsupported fingerprint algorithm {}",
+                    algorithm
+                )))
+            }
+        };
+        let values: Vec<String> = hashed.iter().map(|x| format! {"{x:02x}"}).collect();
+
+        Ok(Self {
+            algorithm,
+            value: values.join(":"),
+        })
+    }
+
+    /// is_supported returns whether algorithm is one of SUPPORTED_FINGERPRINT_ALGORITHMS
+    pub(crate) fn is_supported(&self) -> bool {
+        SUPPORTED_FINGERPRINT_ALGORITHMS.contains(&self.algorithm.to_lowercase().as_str())
+    }
+}
+
 /// RTCCertificate represents a X.509 certificate used to authenticate WebRTC communications.
 #[derive(Clone, Debug)]
 pub struct RTCCertificate {
@@ -201,26 +240,130 @@ impl RTCCertificate {
         )
     }
 
-    /// get_fingerprints returns a SHA-256 fingerprint of this certificate.
-    ///
-    /// TODO: return a fingerprint computed with the digest algorithm used in the certificate
-    /// signature.
+    /// get_fingerprints returns fingerprints of this certificate computed with the digest
+    /// algorithm used in the certificate signature, which falls back to SHA-256 for the
+    /// signatures without a SHA-384 or SHA-512 digest, e.g. Ed25519.
     pub fn get_fingerprints(&self) -> Vec<RTCDtlsFingerprint> {
-        let mut fingerprints = Vec::new();
-
-        for c in &self.dtls_certificate.certificate {
-            let mut h = Sha256::new();
-            h.update(c.as_ref());
-            let hashed = h.finalize();
-            let values: Vec<String> = hashed.iter().map(|x| format! {"{x:02x}"}).collect();
-
-            fingerprints.push(RTCDtlsFingerprint {
-                algorithm: "sha-256".to_owned(),
-                value: values.join(":"),
-            });
+        self.dtls_certificate
+            .certificate
+            .iter()
+            .filter_map(|c| {
+                RTCDtlsFingerprint::new(signature_digest_algorithm(c.as_ref()), c.as_ref()).ok()
+            })
+            .collect()
+    }
+
+    /// get_fingerprints_with_algorithm returns fingerprints of this certificate computed with
+    /// algorithm, one of "sha-256", "sha-384" and "sha-512".
+    pub fn get_fingerprints_with_algorithm(
+        &self,
+        algorithm: &str,
+    ) -> Result<Vec<RTCDtlsFingerprint>> {
+        self.dtls_certificate
+            .certificate
+            .iter()
+            .map(|c| RTCDtlsFingerprint::new(algorithm, c.as_ref()))
+            .collect()
+    }
+}
+
+/// signature_digest_algorithm returns the hash function textual name of the digest algorithm
+/// used in signature of DER encoded certificate
+fn signature_digest_algorithm(certificate: &[u8]) -> &'static str {
+    match x509_parser::parse_x509_certificate(certificate) {
+        Ok((_, x509)) => {
+            let oid = &x509.signature_algorithm.algorithm;
+            if *oid == OID_SIG_ECDSA_WITH_SHA384 || *oid == OID_PKCS1_SHA384WITHRSA {
+                "sha-384"
+            } else if *oid == OID_SIG_ECDSA_WITH_SHA512 || *oid == OID_PKCS1_SHA512WITHRSA {
+                "sha-512"
+            } else {
+                "sha-256"
+            }
+        }
+        Err(_) => "sha-256",
+    }
+}
+
+/// CertificateStore keeps DTLS certificates with the handshake configs presenting them. The newest
+/// one is used by new candidates, while existing transports keep the one they are created with.
+pub(crate) struct CertificateStore {
+    fingerprint_algorithm: Option<String>,
+    certificates: Vec<(
+        RTCCertificate,
+        Vec<RTCDtlsFingerprint>,
+        Arc<dtls::config::HandshakeConfig>,
+    )>,
+}
+
+impl CertificateStore {
+    pub(crate) fn new(
+        certificate: RTCCertificate,
+        dtls_handshake_config: Arc<dtls::config::HandshakeConfig>,
+        fingerprint_algorithm: Option<String>,
+    ) -> Result<Self> {
+        let mut certificate_store = Self {
+            fingerprint_algorithm,
+            certificates: vec![],
+        };
+        certificate_store.add(certificate, dtls_handshake_config)?;
+        Ok(certificate_store)
+    }
+
+    /// add makes certificate the newest one
+    pub(crate) fn add(
+        &mut self,
+        certificate: RTCCertificate,
+        dtls_handshake_config: Arc<dtls::config::HandshakeConfig>,
+    ) -> Result<()> {
+        let fingerprints = if let Some(fingerprint_algorithm) = &self.fingerprint_algorithm {
+            certificate.get_fingerprints_with_algorithm(fingerprint_algorithm)?
+        } else {
+            certificate.get_fingerprints()
+        };
+        if fingerprints.is_empty() {
+            return Err(Error::ErrInvalidCertificate);
         }
+        self.certificates
+            .push((certificate, fingerprints, dtls_handshake_config));
+        Ok(())
+    }
+
+    /// newest_fingerprints returns fingerprints of the newest certificate advertised in SDP
+    pub(crate) fn newest_fingerprints(&self) -> &[RTCDtlsFingerprint] {
+        // store is never empty
+        &self.certificates[self.certificates.len() - 1].1
+    }
+
+    /// find_dtls_handshake_config returns the handshake config presenting the certificate with
+    /// fingerprints
+    pub(crate) fn find_dtls_handshake_config(
+        &self,
+        fingerprints: &[RTCDtlsFingerprint],
+    ) -> Option<Arc<dtls::config::HandshakeConfig>> {
+        self.certificates
+            .iter()
+            .rev()
+            .find(|(_, certificate_fingerprints, _)| {
+                certificate_fingerprints
+                    .iter()
+                    .any(|fingerprint| fingerprints.contains(fingerprint))
+            })
+            .map(|(_, _, dtls_handshake_config)| Arc::clone(dtls_handshake_config))
+    }
 
-        fingerprints
+    /// retain keeps the newest certificate and the ones whose fingerprints f returns true for
+    pub(crate) fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&[RTCDtlsFingerprint]) -> bool,
+    {
+        let newest = self.certificates.len() - 1;
+        let mut i = 0;
+        self.certificates.retain(|(_, fingerprints, _)| {
+            let is_retained = i == newest || f(fingerprints);
+            i += 1;
+            is_retained
+        });
     }
 }
 
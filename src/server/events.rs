@@ -0,0 +1,89 @@
+use crate::endpoint::transport::RTCIceConnectionState;
+use crate::types::{EndpointId, Mid, SessionId};
+use bytes::BytesMut;
+use std::net::SocketAddr;
+
+/// ServerEvent reports a lifecycle moment of sessions, endpoints, transports and tracks,
+/// which can be drained by ServerStates::poll_event
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ServerEvent {
+    /// endpoint is connected via a new transport after ICE connectivity check
+    EndpointConnected {
+        session_id: SessionId,
+        endpoint_id: EndpointId,
+        local_addr: SocketAddr,
+        peer_addr: SocketAddr,
+    },
+    /// DTLS handshake is completed for the transport of endpoint
+    DtlsHandshakeCompleted {
+        session_id: SessionId,
+        endpoint_id: EndpointId,
+        peer_addr: SocketAddr,
+    },
+    /// data channel for signaling is ready for the transport of endpoint
+    DataChannelReady {
+        session_id: SessionId,
+        endpoint_id: EndpointId,
+        peer_addr: SocketAddr,
+    },
+    /// data channel opened by ServerStates::open_data_channel is acked by endpoint
+    DataChannelOpened {
+        session_id: SessionId,
+        endpoint_id: EndpointId,
+        label: String,
+    },
+    /// message is received from endpoint on the data channel opened by
+    /// ServerStates::open_data_channel, which is not relayed to other endpoints
+    DataChannelMessage {
+        session_id: SessionId,
+        endpoint_id: EndpointId,
+        label: String,
+        payload: BytesMut,
+        is_binary: bool,
+    },
+    /// endpoint publishes a new track with mid
+    TrackPublished {
+        session_id: SessionId,
+        endpoint_id: EndpointId,
+        mid: Mid,
+    },
+    /// endpoint stops publishing the track with mid, since it closes its signaling data channel
+    /// or is disconnected
+    TrackUnpublished {
+        session_id: SessionId,
+        endpoint_id: EndpointId,
+        mid: Mid,
+    },
+    /// ICE connection state of the transport of endpoint is changed by consent freshness
+    IceConnectionStateChanged {
+        session_id: SessionId,
+        endpoint_id: EndpointId,
+        peer_addr: SocketAddr,
+        state: RTCIceConnectionState,
+    },
+    /// transport of endpoint is migrated to another path, keeping its DTLS and SRTP states
+    TransportMigrated {
+        session_id: SessionId,
+        endpoint_id: EndpointId,
+        previous_local_addr: SocketAddr,
+        previous_peer_addr: SocketAddr,
+        local_addr: SocketAddr,
+        peer_addr: SocketAddr,
+    },
+    /// transport of endpoint is removed, due to idle timeout, consent expiry, DTLS close or
+    /// failure, or endpoint close
+    TransportRemoved {
+        session_id: SessionId,
+        endpoint_id: EndpointId,
+        local_addr: SocketAddr,
+        peer_addr: SocketAddr,
+    },
+    /// endpoint is disconnected since all its transports are removed, or it is closed before
+    /// being connected
+    EndpointDisconnected {
+        session_id: SessionId,
+        endpoint_id: EndpointId,
+    },
+    /// session is closed since all its endpoints are disconnected
+    SessionClosed { session_id: SessionId },
+}
@@ -1,2 +1,7 @@
 pub(crate) mod certificate;
+pub(crate) mod events;
+pub(crate) mod signaling;
 pub(crate) mod states;
+
+#[cfg(test)]
+mod states_test;
@@ -0,0 +1,107 @@
+use crate::description::RTCSessionDescription;
+use crate::types::{EndpointId, Mid, Rid};
+use serde::{Deserialize, Serialize};
+use shared::error::{Error, Result};
+
+/// SIGNALING_VERSION is the version of SignalingEnvelope understood by this SFU
+pub const SIGNALING_VERSION: u32 = 1;
+
+/// SignalingEnvelope wraps a typed SignalingMessage with protocol version for the signaling
+/// data channel, e.g. {"version":1,"type":"subscribe","payload":{"publisher_id":1,"mid":"0"}}
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignalingEnvelope {
+    pub version: u32,
+    #[serde(flatten)]
+    pub message: SignalingMessage,
+}
+
+/// SignalingMessage is the typed payload carried by SignalingEnvelope
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", content = "payload", rename_all = "kebab-case")]
+pub enum SignalingMessage {
+    /// offer to be answered by the remote side
+    Offer(RTCSessionDescription),
+    /// answer to the offer sent before
+    Answer(RTCSessionDescription),
+    /// ask to restart ICE of the endpoint
+    IceRestart,
+    /// mute or unmute the track published by the endpoint with mid
+    Mute { mid: Mid, muted: bool },
+    /// receive the track published by publisher_id with mid
+    Subscribe { publisher_id: EndpointId, mid: Mid },
+    /// stop receiving the track published by publisher_id with mid
+    Unsubscribe { publisher_id: EndpointId, mid: Mid },
+    /// select the simulcast layer with rid of the track published by publisher_id with mid
+    LayerRequest {
+        publisher_id: EndpointId,
+        mid: Mid,
+        rid: Rid,
+    },
+    /// failure of handling the previous message
+    Error { message: String },
+    /// application data relayed to the other endpoints in the same session,
+    /// endpoint_id is the sender, which is filled in by SFU
+    AppData {
+        #[serde(default)]
+        endpoint_id: Option<EndpointId>,
+        data: serde_json::Value,
+    },
+}
+
+/// SignalingRequest is a message received over the signaling data channel, either a bare
+/// RTCSessionDescription from legacy clients or a SignalingEnvelope
+pub(crate) enum SignalingRequest {
+    SessionDescription(RTCSessionDescription),
+    Envelope(Result<SignalingMessage>),
+}
+
+impl SignalingRequest {
+    pub(crate) fn decode(payload: &[u8]) -> Result<Self> {
+        let value = serde_json::from_slice::<serde_json::Value>(payload)
+            .map_err(|err| Error::Other(err.to_string()))?;
+
+        let Some(version) = value.get("version") else {
+            let sdp = serde_json::from_value::<RTCSessionDescription>(value)
+                .map_err(|err| Error::Other(err.to_string()))?;
+            return Ok(SignalingRequest::SessionDescription(sdp));
+        };
+        if version.as_u64() != Some(SIGNALING_VERSION as u64) {
+            return Ok(SignalingRequest::Envelope(Err(Error::Other(format!(
+                "unsupported signaling version {}",
+                version
+            )))));
+        }
+
+        Ok(SignalingRequest::Envelope(
+            serde_json::from_value::<SignalingEnvelope>(value)
+                .map(|envelope| envelope.message)
+                .map_err(|err| Error::Other(err.to_string())),
+        ))
+    }
+}
+
+impl SignalingMessage {
+    /// encode wraps message into SignalingEnvelope, or keeps bare RTCSessionDescription
+    /// for legacy clients, which can't receive any other message
+    pub(crate) fn encode(self, is_envelope: bool) -> Result<String> {
+        let encoded = if is_envelope {
+            serde_json::to_string(&SignalingEnvelope {
+                version: SIGNALING_VERSION,
+                message: self,
+            })
+        } else {
+            match self {
+                SignalingMessage::Offer(sdp) | SignalingMessage::Answer(sdp) => {
+                    serde_json::to_string(&sdp)
+                }
+                _ => {
+                    return Err(Error::Other(format!(
+                        "{:?} requires signaling envelope",
+                        self
+                    )))
+                }
+            }
+        };
+        encoded.map_err(|err| Error::Other(err.to_string()))
+    }
+}
@@ -1,19 +1,31 @@
 use crate::configs::server_config::ServerConfig;
 use crate::configs::session_config::SessionConfig;
-use crate::description::RTCSessionDescription;
+use crate::description::{
+    rtp_transceiver_direction::RTCRtpTransceiverDirection, RTCSessionDescription,
+};
 use crate::endpoint::{
-    candidate::{Candidate, ConnectionCredentials},
-    transport::Transport,
-    Endpoint,
+    candidate::{Candidate, ConnectionCredentials, DTLSRole, RTCIceParameters},
+    transport::{RTCIceConnectionState, SrtpDecryptErrorCounts, Transport},
+    Endpoint, IceRestart,
+};
+use crate::handlers::dtls::DtlsHandler;
+use crate::messages::{
+    ApplicationMessage, DTLSMessageEvent, DataChannelEvent, DataChannelMessageParams,
+    DataChannelMessageType, DataChannelOptions, MessageEvent, RTPMessageEvent, TaggedMessageEvent,
 };
 use crate::metrics::Metrics;
+use crate::server::certificate::{CertificateStore, RTCCertificate};
+use crate::server::events::ServerEvent;
 use crate::session::Session;
-use crate::types::{EndpointId, FourTuple, SessionId, UserName};
+use crate::types::{EndpointId, FourTuple, Mid, Rid, SessionId, UserName};
+use bytes::BytesMut;
 use log::{debug, info};
 use opentelemetry::metrics::Meter;
+use retty::transport::TransportContext;
+use sctp::PayloadProtocolIdentifier;
 use shared::error::{Error, Result};
 use std::collections::hash_map::Entry;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::net::SocketAddr;
 use std::rc::Rc;
 use std::sync::Arc;
@@ -24,10 +36,19 @@ pub struct ServerStates {
     server_config: Arc<ServerConfig>,
     local_addr: SocketAddr,
     metrics: Metrics,
+    certificate_store: CertificateStore,
 
     sessions: HashMap<SessionId, Session>,
     endpoints: HashMap<FourTuple, (SessionId, EndpointId)>,
     candidates: HashMap<UserName, Rc<Candidate>>,
+
+    events: VecDeque<ServerEvent>,
+    // messages pushed by application, which are sent by GatewayHandler
+    transmits: VecDeque<TaggedMessageEvent>,
+    // DTLS records of closed transports, which are sent by DtlsHandler
+    dtls_transmits: VecDeque<TaggedMessageEvent>,
+    // removed transports of ICE-TCP connections, whose framing buffers are dropped by TcpFramingHandler
+    removed_tcp_four_tuples: VecDeque<FourTuple>,
 }
 
 impl ServerStates {
@@ -37,25 +58,50 @@ impl ServerStates {
         local_addr: SocketAddr,
         meter: Meter,
     ) -> Result<Self> {
-        let _ = server_config
+        let srtp_protection_profiles = server_config.srtp_protection_profiles();
+        if srtp_protection_profiles.is_empty() {
+            return Err(Error::Other(
+                "no SRTP protection profile is allowed by ServerConfig".to_string(),
+            ));
+        }
+        for srtp_protection_profile in srtp_protection_profiles {
+            DtlsHandler::srtp_protection_profile(srtp_protection_profile).map_err(|_| {
+                Error::Other(format!(
+                    "SRTP protection profile {:?} is not supported",
+                    srtp_protection_profile
+                ))
+            })?;
+        }
+
+        let certificate = server_config
             .certificates
             .first()
             .ok_or(Error::ErrInvalidCertificate)?
-            .get_fingerprints()
-            .first()
-            .ok_or(Error::ErrInvalidCertificate)?;
+            .clone();
+        let dtls_handshake_config = server_config.dtls_handshake_config(&certificate)?;
+        let certificate_store = CertificateStore::new(
+            certificate,
+            dtls_handshake_config,
+            server_config.fingerprint_algorithm.clone(),
+        )?;
 
         Ok(Self {
             server_config,
             local_addr,
             metrics: Metrics::new(meter),
+            certificate_store,
             sessions: HashMap::new(),
             endpoints: HashMap::new(),
             candidates: HashMap::new(),
+            events: VecDeque::new(),
+            transmits: VecDeque::new(),
+            dtls_transmits: VecDeque::new(),
+            removed_tcp_four_tuples: VecDeque::new(),
         })
     }
 
-    /// accept offer and return answer
+    /// accept offer and return answer, four_tuple selects the transport whose ICE credentials
+    /// are used in answer for an existing endpoint, otherwise any of its transports is used
     pub fn accept_offer(
         &mut self,
         session_id: SessionId,
@@ -67,41 +113,71 @@ impl ServerStates {
         let remote_conn_cred = ConnectionCredentials::from_sdp(&parsed)?;
         offer.parsed = Some(parsed);
 
-        let fingerprints = self
-            .server_config
-            .certificates
-            .first()
-            .unwrap()
-            .get_fingerprints();
+        let fingerprints = self.certificate_store.newest_fingerprints().to_vec();
 
         let session = self.create_or_get_mut_session(session_id);
         let has_endpoint = session.has_endpoint(&endpoint_id);
+        if has_endpoint
+            && session
+                .get_endpoint(&endpoint_id)
+                .is_some_and(|endpoint| endpoint.get_transports().is_empty())
+        {
+            return Err(Error::Other(format!(
+                "endpoint id {} is offered but not connected yet",
+                endpoint_id
+            )));
+        }
+        if !has_endpoint {
+            session.create_endpoint(endpoint_id, &offer)?;
+        }
 
-        let local_conn_cred = if has_endpoint {
-            session.set_remote_description(endpoint_id, &offer)?;
+        let published_mids = ServerStates::get_published_mids(session, &endpoint_id);
+        session.set_remote_description(endpoint_id, &offer)?;
+        let mut new_published_mids: Vec<Mid> =
+            ServerStates::get_published_mids(session, &endpoint_id)
+                .difference(&published_mids)
+                .cloned()
+                .collect();
+        new_published_mids.sort();
 
-            let endpoint = session
-                .get_endpoint(&endpoint_id)
-                .ok_or(Error::Other(format!(
-                    "can't find endpoint id {}",
-                    endpoint_id
-                )))?;
-            let four_tuple = four_tuple.ok_or(Error::Other("missing FourTuple".to_string()))?;
-            let transports = endpoint.get_transports();
-            let transport = transports.get(&four_tuple).ok_or(Error::Other(format!(
-                "can't find transport for endpoint id {} with {:?}",
-                endpoint_id, four_tuple
-            )))?;
-            transport.candidate().local_connection_credentials().clone()
+        let mut previous_candidate = None;
+        let local_conn_cred = if has_endpoint {
+            let local_conn_cred = ServerStates::get_local_connection_credentials(
+                session,
+                endpoint_id,
+                four_tuple.as_ref(),
+            )?;
+            // changed remote ICE credentials indicate ICE restart, RFC 8839 S4.4.1.1.1
+            if ServerStates::get_remote_ice_parameters(session, endpoint_id)?
+                != remote_conn_cred.ice_params
+            {
+                previous_candidate = Some(ServerStates::get_restarting_candidate(
+                    session,
+                    endpoint_id,
+                    four_tuple.as_ref(),
+                )?);
+                local_conn_cred.restart_ice()
+            } else {
+                local_conn_cred
+            }
         } else {
             ConnectionCredentials::new(fingerprints, remote_conn_cred.dtls_params.role)
         };
 
-        let answer = session.create_answer(endpoint_id, &offer, &local_conn_cred.ice_params)?;
-        if has_endpoint {
-            session.set_local_description(endpoint_id, &answer)?;
-        } else {
-            self.add_candidate(Rc::new(Candidate::new(
+        let answer = session.create_answer(endpoint_id, &offer, &local_conn_cred)?;
+        session.set_local_description(endpoint_id, &answer)?;
+        if let Some(endpoint) = session.get_mut_endpoint(&endpoint_id) {
+            if !has_endpoint {
+                endpoint.set_local_description(answer.clone());
+            } else if endpoint.is_offer_pending() {
+                // a remote offer in glare rolls back the pending local offer, so offer it again
+                // later
+                endpoint.set_offer_pending(false);
+                endpoint.set_renegotiation_needed(true);
+            }
+        }
+        if !has_endpoint || previous_candidate.is_some() {
+            let candidate = Rc::new(Candidate::new(
                 session_id,
                 endpoint_id,
                 remote_conn_cred,
@@ -109,34 +185,994 @@ impl ServerStates {
                 offer,
                 answer.clone(),
                 Instant::now() + self.server_config.idle_timeout,
-            )));
+            ));
+            if let Some(previous_candidate) = previous_candidate {
+                self.set_ice_restart(
+                    session_id,
+                    endpoint_id,
+                    IceRestart::Pending(candidate, previous_candidate),
+                )?;
+            } else {
+                self.add_candidate(candidate);
+            }
+        }
+
+        for mid in new_published_mids {
+            self.emit_event(ServerEvent::TrackPublished {
+                session_id,
+                endpoint_id,
+                mid,
+            });
         }
 
         Ok(answer)
     }
 
-    pub(crate) fn metrics(&self) -> &Metrics {
-        &self.metrics
+    /// subscribe lets subscriber_id receive the track published by publisher_id with mid
+    pub fn subscribe(
+        &mut self,
+        session_id: SessionId,
+        subscriber_id: EndpointId,
+        publisher_id: EndpointId,
+        mid: Mid,
+    ) -> Result<()> {
+        if subscriber_id == publisher_id {
+            return Err(Error::Other(format!(
+                "endpoint id {} can't subscribe to itself",
+                subscriber_id
+            )));
+        }
+
+        let session = self
+            .get_mut_session(&session_id)
+            .ok_or(Error::Other(format!(
+                "can't find session id {}",
+                session_id
+            )))?;
+        if !session.has_endpoint(&subscriber_id) {
+            return Err(Error::Other(format!(
+                "can't find endpoint id {}",
+                subscriber_id
+            )));
+        }
+        let publisher = session
+            .get_endpoint(&publisher_id)
+            .ok_or(Error::Other(format!(
+                "can't find endpoint id {}",
+                publisher_id
+            )))?;
+        if !publisher
+            .get_transceivers()
+            .get(&mid)
+            .is_some_and(|transceiver| {
+                transceiver.direction == RTCRtpTransceiverDirection::Recvonly
+            })
+        {
+            return Err(Error::Other(format!(
+                "endpoint id {} doesn't publish track with mid {}",
+                publisher_id, mid
+            )));
+        }
+
+        if session.add_subscription(publisher_id, &mid, subscriber_id) {
+            debug!(
+                "{}/{} subscribes to {}/{}",
+                session_id, subscriber_id, publisher_id, mid
+            );
+        }
+        Ok(())
     }
 
-    pub(crate) fn accept_answer(
+    /// unsubscribe stops forwarding the track published by publisher_id with mid to subscriber_id
+    pub fn unsubscribe(
+        &mut self,
+        session_id: SessionId,
+        subscriber_id: EndpointId,
+        publisher_id: EndpointId,
+        mid: Mid,
+    ) -> Result<()> {
+        let session = self
+            .get_mut_session(&session_id)
+            .ok_or(Error::Other(format!(
+                "can't find session id {}",
+                session_id
+            )))?;
+
+        if session.remove_subscription(publisher_id, &mid, subscriber_id) {
+            debug!(
+                "{}/{} unsubscribes from {}/{}",
+                session_id, subscriber_id, publisher_id, mid
+            );
+        }
+        Ok(())
+    }
+
+    /// select_layer lets subscriber_id receive the simulcast layer with rid of the track
+    /// published by publisher_id with mid, switching on the layer's next keyframe, which is
+    /// requested from publisher
+    pub fn select_layer(
+        &mut self,
+        session_id: SessionId,
+        subscriber_id: EndpointId,
+        publisher_id: EndpointId,
+        mid: Mid,
+        rid: Rid,
+    ) -> Result<()> {
+        let keyframe_request_interval = self.server_config.keyframe_request_interval;
+        let session = self
+            .get_mut_session(&session_id)
+            .ok_or(Error::Other(format!(
+                "can't find session id {}",
+                session_id
+            )))?;
+        let publisher = session
+            .get_endpoint(&publisher_id)
+            .ok_or(Error::Other(format!(
+                "can't find endpoint id {}",
+                publisher_id
+            )))?;
+        if !publisher
+            .get_transceivers()
+            .get(&mid)
+            .is_some_and(|transceiver| transceiver.rids.contains(&rid))
+        {
+            return Err(Error::Other(format!(
+                "endpoint id {} doesn't publish simulcast layer {} with mid {}",
+                publisher_id, rid, mid
+            )));
+        }
+
+        let subscriber = session
+            .get_mut_endpoint(&subscriber_id)
+            .ok_or(Error::Other(format!(
+                "can't find endpoint id {}",
+                subscriber_id
+            )))?;
+        let subscriber_mid = format!("{}-{}", publisher_id, mid);
+        if !subscriber
+            .get_mut_rtp_rewriter(&subscriber_mid)
+            .is_some_and(|rtp_rewriter| rtp_rewriter.set_target_rid(rid.clone()))
+        {
+            return Err(Error::Other(format!(
+                "endpoint id {} doesn't forward track with mid {}",
+                subscriber_id, subscriber_mid
+            )));
+        }
+
+        debug!(
+            "{}/{} selects layer {} of {}/{}",
+            session_id, subscriber_id, rid, publisher_id, mid
+        );
+
+        // the switch happens on the layer's next keyframe, so ask publisher for one
+        let now = Instant::now();
+        let keyframe_request = session
+            .get_mut_endpoint(&publisher_id)
+            .and_then(|publisher| {
+                let ssrc = publisher.get_ssrc_by_rid(&mid, &rid)?;
+                let four_tuple = publisher.find_srtp_ready_four_tuple()?;
+                let rtcp_packet =
+                    publisher.request_keyframe(ssrc, 0, false, now, keyframe_request_interval)?;
+                Some((four_tuple, rtcp_packet))
+            });
+        if let Some((four_tuple, rtcp_packet)) = keyframe_request {
+            self.transmits.push_back(TaggedMessageEvent {
+                now,
+                transport: TransportContext {
+                    local_addr: four_tuple.local_addr,
+                    peer_addr: four_tuple.peer_addr,
+                    ecn: None,
+                },
+                message: MessageEvent::Rtp(RTPMessageEvent::Rtcp(vec![rtcp_packet])),
+            });
+        }
+        Ok(())
+    }
+
+    /// set_track_muted stops or resumes forwarding the track published by publisher_id with mid
+    pub fn set_track_muted(
+        &mut self,
+        session_id: SessionId,
+        publisher_id: EndpointId,
+        mid: Mid,
+        muted: bool,
+    ) -> Result<()> {
+        let session = self
+            .get_mut_session(&session_id)
+            .ok_or(Error::Other(format!(
+                "can't find session id {}",
+                session_id
+            )))?;
+        let publisher = session
+            .get_mut_endpoint(&publisher_id)
+            .ok_or(Error::Other(format!(
+                "can't find endpoint id {}",
+                publisher_id
+            )))?;
+        if !publisher
+            .get_transceivers()
+            .get(&mid)
+            .is_some_and(|transceiver| {
+                transceiver.direction == RTCRtpTransceiverDirection::Recvonly
+            })
+        {
+            return Err(Error::Other(format!(
+                "endpoint id {} doesn't publish track with mid {}",
+                publisher_id, mid
+            )));
+        }
+
+        if publisher.set_track_muted(&mid, muted) {
+            debug!(
+                "{}/{} {} track with mid {}",
+                session_id,
+                publisher_id,
+                if muted { "mutes" } else { "unmutes" },
+                mid
+            );
+        }
+        Ok(())
+    }
+
+    /// accept answer to the offer polled by poll_offer or sent over signaling data channel
+    pub fn accept_answer(
         &mut self,
         session_id: SessionId,
         endpoint_id: EndpointId,
-        _four_tuple: FourTuple,
         mut answer: RTCSessionDescription,
     ) -> Result<()> {
         let parsed = answer.unmarshal()?;
-        answer.parsed = Some(parsed);
+        answer.parsed = Some(parsed.clone());
 
-        let session = self.create_or_get_mut_session(session_id);
-        if session.has_endpoint(&endpoint_id) {
-            session.set_remote_description(endpoint_id, &answer)?;
+        let session = self
+            .get_mut_session(&session_id)
+            .ok_or(Error::Other(format!(
+                "can't find session id {}",
+                session_id
+            )))?;
+        let endpoint = session
+            .get_endpoint(&endpoint_id)
+            .ok_or(Error::Other(format!(
+                "can't find endpoint id {}",
+                endpoint_id
+            )))?;
+        let ice_restart = if let Some(IceRestart::Offered(local_conn_cred, previous_candidate)) =
+            endpoint.ice_restart()
+        {
+            let local_description = endpoint
+                .local_description()
+                .ok_or(Error::Other("local_description is not set".to_string()))?
+                .clone();
+            Some((
+                local_conn_cred.clone(),
+                Rc::clone(previous_candidate),
+                local_description,
+            ))
+        } else {
+            None
         };
+        session.set_remote_description(endpoint_id, &answer)?;
+        if let Some(endpoint) = session.get_mut_endpoint(&endpoint_id) {
+            endpoint.set_offer_pending(false);
+        }
 
+        if let Some((local_conn_cred, previous_candidate, local_description)) = ice_restart {
+            let remote_conn_cred = ConnectionCredentials::from_sdp(&parsed)?;
+            let candidate = Rc::new(Candidate::new(
+                session_id,
+                endpoint_id,
+                remote_conn_cred,
+                local_conn_cred,
+                answer,
+                local_description,
+                Instant::now() + self.server_config.idle_timeout,
+            ));
+            self.set_ice_restart(
+                session_id,
+                endpoint_id,
+                IceRestart::Pending(candidate, previous_candidate),
+            )?;
+        }
         Ok(())
     }
 
+    /// restart_ice returns an offer with new local ICE credentials for endpoint, the answer to it
+    /// should be passed into accept_answer, then the transport selected by four_tuple, otherwise
+    /// any of its transports, migrates to the path nominated with the new credentials
+    pub fn restart_ice(
+        &mut self,
+        session_id: SessionId,
+        endpoint_id: EndpointId,
+        four_tuple: Option<FourTuple>,
+    ) -> Result<RTCSessionDescription> {
+        let session = self.get_session(&session_id).ok_or(Error::Other(format!(
+            "can't find session id {}",
+            session_id
+        )))?;
+        let local_conn_cred = ServerStates::get_local_connection_credentials(
+            session,
+            endpoint_id,
+            four_tuple.as_ref(),
+        )?
+        .restart_ice();
+        let previous_candidate =
+            ServerStates::get_restarting_candidate(session, endpoint_id, four_tuple.as_ref())?;
+        self.set_ice_restart(
+            session_id,
+            endpoint_id,
+            IceRestart::Offered(local_conn_cred, previous_candidate),
+        )?;
+
+        self.create_offer(session_id, endpoint_id, four_tuple)
+    }
+
+    /// poll_offer returns a renegotiation offer for endpoint if its tracks to be received
+    /// are changed and no previous offer is waiting for its answer, the answer to it should be
+    /// passed into accept_answer
+    pub fn poll_offer(
+        &mut self,
+        session_id: SessionId,
+        endpoint_id: EndpointId,
+    ) -> Result<Option<RTCSessionDescription>> {
+        let session = self.get_session(&session_id).ok_or(Error::Other(format!(
+            "can't find session id {}",
+            session_id
+        )))?;
+        let endpoint = session
+            .get_endpoint(&endpoint_id)
+            .ok_or(Error::Other(format!(
+                "can't find endpoint id {}",
+                endpoint_id
+            )))?;
+        if !endpoint.can_renegotiate() {
+            return Ok(None);
+        }
+
+        self.create_offer(session_id, endpoint_id, None).map(Some)
+    }
+
+    /// close_endpoint removes all transports of endpoint, and lets its peers drop the tracks
+    /// published by it through renegotiation
+    pub fn close_endpoint(&mut self, session_id: SessionId, endpoint_id: EndpointId) -> Result<()> {
+        let session = self.get_session(&session_id).ok_or(Error::Other(format!(
+            "can't find session id {}",
+            session_id
+        )))?;
+        let endpoint = session
+            .get_endpoint(&endpoint_id)
+            .ok_or(Error::Other(format!(
+                "can't find endpoint id {}",
+                endpoint_id
+            )))?;
+        let four_tuples: Vec<FourTuple> = endpoint.get_transports().keys().cloned().collect();
+
+        info!("{}/{} is closed", session_id, endpoint_id);
+        if four_tuples.is_empty() {
+            // endpoint is offered but not connected yet
+            let session = self
+                .get_mut_session(&session_id)
+                .ok_or(Error::Other(format!(
+                    "can't find session id {}",
+                    session_id
+                )))?;
+            let mids = session.remove_endpoint(&endpoint_id);
+            let is_session_closed = session.get_endpoints().is_empty();
+            if is_session_closed {
+                self.remove_session(&session_id);
+            }
+            // its candidate must not connect the closed endpoint later
+            let usernames: Vec<UserName> = self
+                .candidates
+                .iter()
+                .filter(|(_, candidate)| {
+                    candidate.session_id() == session_id && candidate.endpoint_id() == endpoint_id
+                })
+                .map(|(username, _)| username.clone())
+                .collect();
+            for username in usernames {
+                self.remove_candidate(&username);
+            }
+            for mid in mids {
+                self.emit_event(ServerEvent::TrackUnpublished {
+                    session_id,
+                    endpoint_id,
+                    mid,
+                });
+            }
+            // same events as the last transport of a connected endpoint is removed
+            self.emit_event(ServerEvent::EndpointDisconnected {
+                session_id,
+                endpoint_id,
+            });
+            if is_session_closed {
+                self.emit_event(ServerEvent::SessionClosed { session_id });
+            }
+        }
+        // peer is notified by SCTP shutdown and DTLS close_notify, before states are dropped
+        let now = Instant::now();
+        for four_tuple in four_tuples {
+            let records = self.get_mut_transport(&four_tuple)?.close(now);
+            for record in records {
+                self.dtls_transmits.push_back(TaggedMessageEvent {
+                    now,
+                    transport: TransportContext {
+                        local_addr: four_tuple.local_addr,
+                        peer_addr: four_tuple.peer_addr,
+                        ecn: None,
+                    },
+                    message: MessageEvent::Dtls(DTLSMessageEvent::Raw(record)),
+                });
+            }
+            self.remove_transport(four_tuple);
+        }
+
+        Ok(())
+    }
+
+    /// open_data_channel opens a data channel with label toward endpoint on its established SCTP
+    /// association, ServerEvent::DataChannelOpened is emitted once endpoint acks it. Messages from
+    /// endpoint on it are emitted as ServerEvent::DataChannelMessage instead of being relayed.
+    pub fn open_data_channel(
+        &mut self,
+        session_id: SessionId,
+        endpoint_id: EndpointId,
+        label: String,
+        options: DataChannelOptions,
+    ) -> Result<()> {
+        let session = self
+            .get_mut_session(&session_id)
+            .ok_or(Error::Other(format!(
+                "can't find session id {}",
+                session_id
+            )))?;
+        let endpoint = session
+            .get_mut_endpoint(&endpoint_id)
+            .ok_or(Error::Other(format!(
+                "can't find endpoint id {}",
+                endpoint_id
+            )))?;
+        let transport = endpoint
+            .get_mut_transports()
+            .values_mut()
+            .find(|transport| !transport.get_sctp_associations().is_empty())
+            .ok_or(Error::Other(format!(
+                "endpoint id {} has no established SCTP association",
+                endpoint_id
+            )))?;
+        if transport.find_data_channel(&label).is_some() {
+            return Err(Error::Other(format!(
+                "data channel {} already exists for endpoint id {}",
+                label, endpoint_id
+            )));
+        }
+
+        // DTLS client uses even stream ids and DTLS server uses odd ones, RFC 8832
+        let first_stream_id = if transport
+            .candidate()
+            .local_connection_credentials()
+            .dtls_params
+            .role
+            == DTLSRole::Client
+        {
+            0
+        } else {
+            1
+        };
+        let (signaling_association_handle, signaling_stream_id) =
+            transport.association_handle_and_stream_id();
+        let used_stream_ids: HashSet<(usize, u16)> = transport
+            .get_data_channels()
+            .keys()
+            .copied()
+            .chain(signaling_association_handle.zip(signaling_stream_id))
+            .collect();
+        let four_tuple = *transport.four_tuple();
+        let (&association_handle, conn) = transport
+            .get_mut_sctp_associations()
+            .iter_mut()
+            .next()
+            .ok_or(Error::ErrAssociationNotExisted)?;
+        let stream_id = (first_stream_id..=u16::MAX)
+            .step_by(2)
+            .find(|&stream_id| {
+                !used_stream_ids.contains(&(association_handle.0, stream_id))
+                    && conn.stream(stream_id).is_err()
+            })
+            .ok_or(Error::Other(format!(
+                "no available stream id for data channel {}",
+                label
+            )))?;
+        // stream exists before DataChannelOpen is flushed, so that SCTP reads in between don't
+        // take the data channel as closed
+        conn.open_stream(stream_id, PayloadProtocolIdentifier::Dcep)?;
+        let params: DataChannelMessageParams = (&options).into();
+        transport.add_data_channel(association_handle.0, stream_id, label.clone(), params);
+        transport.add_pending_data_channel_ack(association_handle.0, stream_id);
+        transport.add_server_data_channel(association_handle.0, stream_id);
+
+        debug!(
+            "{}/{} opens data channel {} with stream id {}",
+            session_id, endpoint_id, label, stream_id
+        );
+        self.transmits.push_back(TaggedMessageEvent {
+            now: Instant::now(),
+            transport: TransportContext {
+                local_addr: four_tuple.local_addr,
+                peer_addr: four_tuple.peer_addr,
+                ecn: None,
+            },
+            message: MessageEvent::Dtls(DTLSMessageEvent::DataChannel(ApplicationMessage {
+                association_handle: association_handle.0,
+                stream_id,
+                data_channel_event: DataChannelEvent::Open {
+                    label,
+                    protocol: options.protocol.clone(),
+                    params,
+                },
+            })),
+        });
+        Ok(())
+    }
+
+    /// send_data_channel_message sends payload as text, or binary if is_binary, on the data
+    /// channel with label toward endpoint
+    pub fn send_data_channel_message(
+        &mut self,
+        session_id: SessionId,
+        endpoint_id: EndpointId,
+        label: &str,
+        payload: BytesMut,
+        is_binary: bool,
+    ) -> Result<()> {
+        let session = self.get_session(&session_id).ok_or(Error::Other(format!(
+            "can't find session id {}",
+            session_id
+        )))?;
+        let endpoint = session
+            .get_endpoint(&endpoint_id)
+            .ok_or(Error::Other(format!(
+                "can't find endpoint id {}",
+                endpoint_id
+            )))?;
+        let (four_tuple, (association_handle, stream_id)) = endpoint
+            .get_transports()
+            .iter()
+            .find_map(|(four_tuple, transport)| {
+                transport
+                    .find_data_channel(label)
+                    .map(|id| (*four_tuple, id))
+            })
+            .ok_or(Error::Other(format!(
+                "can't find data channel {} for endpoint id {}",
+                label, endpoint_id
+            )))?;
+
+        self.transmits.push_back(TaggedMessageEvent {
+            now: Instant::now(),
+            transport: TransportContext {
+                local_addr: four_tuple.local_addr,
+                peer_addr: four_tuple.peer_addr,
+                ecn: None,
+            },
+            message: MessageEvent::Dtls(DTLSMessageEvent::DataChannel(ApplicationMessage {
+                association_handle,
+                stream_id,
+                data_channel_event: DataChannelEvent::Message(
+                    if is_binary {
+                        DataChannelMessageType::Binary
+                    } else {
+                        DataChannelMessageType::Text
+                    },
+                    payload,
+                ),
+            })),
+        });
+        Ok(())
+    }
+
+    /// ice_connection_states returns ICE connection state of each transport of endpoint by its
+    /// four tuple
+    pub fn ice_connection_states(
+        &self,
+        session_id: SessionId,
+        endpoint_id: EndpointId,
+    ) -> Result<HashMap<FourTuple, RTCIceConnectionState>> {
+        let session = self.get_session(&session_id).ok_or(Error::Other(format!(
+            "can't find session id {}",
+            session_id
+        )))?;
+        let endpoint = session
+            .get_endpoint(&endpoint_id)
+            .ok_or(Error::Other(format!(
+                "can't find endpoint id {}",
+                endpoint_id
+            )))?;
+        Ok(endpoint
+            .get_transports()
+            .iter()
+            .map(|(four_tuple, transport)| (*four_tuple, transport.ice_connection_state()))
+            .collect())
+    }
+
+    /// srtp_decrypt_error_counts returns counts of SRTP and SRTCP packets failed to decrypt of
+    /// each transport of endpoint by its four tuple
+    pub fn srtp_decrypt_error_counts(
+        &self,
+        session_id: SessionId,
+        endpoint_id: EndpointId,
+    ) -> Result<HashMap<FourTuple, SrtpDecryptErrorCounts>> {
+        let session = self.get_session(&session_id).ok_or(Error::Other(format!(
+            "can't find session id {}",
+            session_id
+        )))?;
+        let endpoint = session
+            .get_endpoint(&endpoint_id)
+            .ok_or(Error::Other(format!(
+                "can't find endpoint id {}",
+                endpoint_id
+            )))?;
+        Ok(endpoint
+            .get_transports()
+            .iter()
+            .map(|(four_tuple, transport)| (*four_tuple, transport.srtp_decrypt_error_counts()))
+            .collect())
+    }
+
+    /// rotate_certificate makes certificate be presented to new endpoints with the DTLS config of
+    /// ServerConfig, while connected endpoints keep their certificates until they are closed
+    pub fn rotate_certificate(&mut self, certificate: RTCCertificate) -> Result<()> {
+        let dtls_handshake_config = self.server_config.dtls_handshake_config(&certificate)?;
+        self.certificate_store
+            .add(certificate, dtls_handshake_config)?;
+
+        // certificates are no longer needed once no candidate can create transport with them
+        let fingerprints: Vec<_> = self
+            .candidates
+            .values()
+            .flat_map(|candidate| {
+                candidate
+                    .local_connection_credentials()
+                    .dtls_params
+                    .fingerprints
+                    .iter()
+            })
+            .collect();
+        self.certificate_store.retain(|certificate_fingerprints| {
+            certificate_fingerprints
+                .iter()
+                .any(|fingerprint| fingerprints.contains(&fingerprint))
+        });
+        info!("DTLS certificate is rotated on {}", self.local_addr);
+        Ok(())
+    }
+
+    /// poll_event returns the next queued ServerEvent, if emit events is enabled in ServerConfig
+    pub fn poll_event(&mut self) -> Option<ServerEvent> {
+        self.events.pop_front()
+    }
+
+    pub(crate) fn emit_event(&mut self, event: ServerEvent) {
+        if self.server_config.emit_events {
+            self.events.push_back(event);
+        }
+    }
+
+    fn get_published_mids(session: &Session, endpoint_id: &EndpointId) -> HashSet<Mid> {
+        session
+            .get_endpoint(endpoint_id)
+            .map(|endpoint| {
+                endpoint
+                    .get_transceivers()
+                    .values()
+                    .filter(|transceiver| {
+                        transceiver.direction == RTCRtpTransceiverDirection::Recvonly
+                    })
+                    .map(|transceiver| transceiver.mid.clone())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// refresh_consent records consent granted to the transport by a valid STUN binding request
+    pub(crate) fn refresh_consent(&mut self, four_tuple: &FourTuple, now: Instant) {
+        let Some((session_id, endpoint_id)) = self.find_endpoint(four_tuple) else {
+            return;
+        };
+        let Ok(transport) = self.get_mut_transport(four_tuple) else {
+            return;
+        };
+        if let Some(state) = transport.refresh_consent(now) {
+            self.on_ice_connection_state_change(session_id, endpoint_id, four_tuple, state);
+        }
+    }
+
+    /// update_ice_connection_states checks consent freshness of all transports, and removes
+    /// the transports whose consent is expired
+    pub(crate) fn update_ice_connection_states(&mut self, now: Instant) {
+        let disconnected_timeout = self.server_config.ice_disconnected_timeout;
+        let failed_timeout = self.server_config.ice_failed_timeout;
+        let mut changes = vec![];
+        for (session_id, session) in self.sessions.iter_mut() {
+            for (endpoint_id, endpoint) in session.get_mut_endpoints().iter_mut() {
+                for (four_tuple, transport) in endpoint.get_mut_transports().iter_mut() {
+                    if let Some(state) = transport.update_ice_connection_state(
+                        now,
+                        disconnected_timeout,
+                        failed_timeout,
+                    ) {
+                        changes.push((*session_id, *endpoint_id, *four_tuple, state));
+                    }
+                }
+            }
+        }
+
+        for (session_id, endpoint_id, four_tuple, state) in changes {
+            self.on_ice_connection_state_change(session_id, endpoint_id, &four_tuple, state);
+            if state == RTCIceConnectionState::Failed {
+                self.remove_transport(four_tuple);
+            }
+        }
+    }
+
+    /// next_consent_timeout returns when ICE connection state of any transport changes
+    pub(crate) fn next_consent_timeout(&self) -> Option<Instant> {
+        let disconnected_timeout = self.server_config.ice_disconnected_timeout;
+        let failed_timeout = self.server_config.ice_failed_timeout;
+        self.sessions
+            .values()
+            .flat_map(|session| session.get_endpoints().values())
+            .flat_map(|endpoint| endpoint.get_transports().values())
+            .filter_map(|transport| {
+                transport.next_consent_timeout(disconnected_timeout, failed_timeout)
+            })
+            .min()
+    }
+
+    /// poll_deferred_keyframe_requests returns the keyframe requests throttled for publishers,
+    /// whose interval ends by now
+    pub(crate) fn poll_deferred_keyframe_requests(
+        &mut self,
+        now: Instant,
+    ) -> Vec<TaggedMessageEvent> {
+        let interval = self.server_config.keyframe_request_interval;
+        let mut messages = vec![];
+        for session in self.sessions.values_mut() {
+            for endpoint in session.get_mut_endpoints().values_mut() {
+                let rtcp_packets = endpoint.poll_deferred_keyframe_requests(now, interval);
+                if rtcp_packets.is_empty() {
+                    continue;
+                }
+                let Some(four_tuple) = endpoint.find_srtp_ready_four_tuple() else {
+                    continue;
+                };
+                messages.push(TaggedMessageEvent {
+                    now,
+                    transport: TransportContext {
+                        local_addr: four_tuple.local_addr,
+                        peer_addr: four_tuple.peer_addr,
+                        ecn: None,
+                    },
+                    message: MessageEvent::Rtp(RTPMessageEvent::Rtcp(rtcp_packets)),
+                });
+            }
+        }
+        messages
+    }
+
+    pub(crate) fn next_deferred_keyframe_request_timeout(&self) -> Option<Instant> {
+        let interval = self.server_config.keyframe_request_interval;
+        self.sessions
+            .values()
+            .flat_map(|session| session.get_endpoints().values())
+            .filter_map(|endpoint| endpoint.next_deferred_keyframe_request_timeout(interval))
+            .min()
+    }
+
+    fn on_ice_connection_state_change(
+        &mut self,
+        session_id: SessionId,
+        endpoint_id: EndpointId,
+        four_tuple: &FourTuple,
+        state: RTCIceConnectionState,
+    ) {
+        info!(
+            "{}/{} ICE connection state of {:?} is changed to {}",
+            session_id, endpoint_id, four_tuple, state
+        );
+        self.emit_event(ServerEvent::IceConnectionStateChanged {
+            session_id,
+            endpoint_id,
+            peer_addr: four_tuple.peer_addr,
+            state,
+        });
+    }
+
+    pub(crate) fn poll_transmit(&mut self) -> Option<TaggedMessageEvent> {
+        self.transmits.pop_front()
+    }
+
+    pub(crate) fn poll_dtls_transmit(&mut self) -> Option<TaggedMessageEvent> {
+        self.dtls_transmits.pop_front()
+    }
+
+    pub(crate) fn poll_removed_tcp_four_tuple(&mut self) -> Option<FourTuple> {
+        self.removed_tcp_four_tuples.pop_front()
+    }
+
+    pub(crate) fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// create_offer creates renegotiation offer for endpoint and sets it as local description
+    pub(crate) fn create_offer(
+        &mut self,
+        session_id: SessionId,
+        endpoint_id: EndpointId,
+        four_tuple: Option<FourTuple>,
+    ) -> Result<RTCSessionDescription> {
+        let session = self
+            .get_mut_session(&session_id)
+            .ok_or(Error::Other(format!(
+                "can't find session id {}",
+                session_id
+            )))?;
+        let local_conn_cred = ServerStates::get_local_connection_credentials(
+            session,
+            endpoint_id,
+            four_tuple.as_ref(),
+        )?;
+
+        let endpoint = session
+            .get_mut_endpoint(&endpoint_id)
+            .ok_or(Error::Other(format!(
+                "can't find endpoint id {}",
+                endpoint_id
+            )))?;
+        endpoint.set_renegotiation_needed(false); //clean renegotiation_needed flag
+        endpoint.set_offer_pending(true);
+
+        let remote_description = endpoint
+            .remote_description()
+            .ok_or(Error::Other("remote_description is not set".to_string()))?
+            .clone();
+
+        let offer = session.create_offer(endpoint_id, &remote_description, &local_conn_cred)?;
+        session.set_local_description(endpoint_id, &offer)?;
+
+        Ok(offer)
+    }
+
+    fn get_local_connection_credentials(
+        session: &Session,
+        endpoint_id: EndpointId,
+        four_tuple: Option<&FourTuple>,
+    ) -> Result<ConnectionCredentials> {
+        let endpoint = session
+            .get_endpoint(&endpoint_id)
+            .ok_or(Error::Other(format!(
+                "can't find endpoint id {}",
+                endpoint_id
+            )))?;
+        // restarted credentials are used until the new path is nominated
+        if let Some(ice_restart) = endpoint.ice_restart() {
+            return Ok(ice_restart.local_connection_credentials().clone());
+        }
+        let transports = endpoint.get_transports();
+        let transport = if let Some(four_tuple) = four_tuple {
+            transports.get(four_tuple)
+        } else {
+            transports.values().next()
+        }
+        .ok_or(Error::Other(format!(
+            "can't find transport for endpoint id {} with {:?}",
+            endpoint_id, four_tuple
+        )))?;
+        Ok(transport.candidate().local_connection_credentials().clone())
+    }
+
+    /// get_restarting_candidate returns the candidate of the transport which restarts ICE, and
+    /// keeps it when ICE is restarted again before the restarted path is nominated
+    fn get_restarting_candidate(
+        session: &Session,
+        endpoint_id: EndpointId,
+        four_tuple: Option<&FourTuple>,
+    ) -> Result<Rc<Candidate>> {
+        let endpoint = session
+            .get_endpoint(&endpoint_id)
+            .ok_or(Error::Other(format!(
+                "can't find endpoint id {}",
+                endpoint_id
+            )))?;
+        if let Some(ice_restart) = endpoint.ice_restart() {
+            return Ok(Rc::clone(ice_restart.previous_candidate()));
+        }
+        let transports = endpoint.get_transports();
+        let transport = if let Some(four_tuple) = four_tuple {
+            transports.get(four_tuple)
+        } else {
+            transports.values().next()
+        }
+        .ok_or(Error::Other(format!(
+            "can't find transport for endpoint id {} with {:?}",
+            endpoint_id, four_tuple
+        )))?;
+        Ok(Rc::clone(transport.candidate()))
+    }
+
+    fn get_remote_ice_parameters(
+        session: &Session,
+        endpoint_id: EndpointId,
+    ) -> Result<RTCIceParameters> {
+        let endpoint = session
+            .get_endpoint(&endpoint_id)
+            .ok_or(Error::Other(format!(
+                "can't find endpoint id {}",
+                endpoint_id
+            )))?;
+        let candidate = if let Some(IceRestart::Pending(candidate, _)) = endpoint.ice_restart() {
+            candidate
+        } else {
+            endpoint
+                .get_transports()
+                .values()
+                .next()
+                .ok_or(Error::Other(format!(
+                    "can't find transport for endpoint id {}",
+                    endpoint_id
+                )))?
+                .candidate()
+        };
+        Ok(candidate.get_remote_parameters().clone())
+    }
+
+    /// set_ice_restart starts ICE restart of endpoint, and registers its candidate if pending
+    fn set_ice_restart(
+        &mut self,
+        session_id: SessionId,
+        endpoint_id: EndpointId,
+        ice_restart: IceRestart,
+    ) -> Result<()> {
+        let endpoint = self
+            .get_mut_session(&session_id)
+            .and_then(|session| session.get_mut_endpoint(&endpoint_id))
+            .ok_or(Error::Other(format!(
+                "can't find endpoint id {}",
+                endpoint_id
+            )))?;
+        let candidate = if let IceRestart::Pending(candidate, _) = &ice_restart {
+            Some(Rc::clone(candidate))
+        } else {
+            None
+        };
+        // restarting again before nomination abandons the previous restart
+        if let Some(IceRestart::Pending(previous, _)) = endpoint.set_ice_restart(Some(ice_restart))
+        {
+            self.remove_candidate(&previous.username());
+        }
+
+        debug!("{}/{} restarts ICE", session_id, endpoint_id);
+        if let Some(candidate) = candidate {
+            self.add_candidate(candidate);
+        }
+        Ok(())
+    }
+
+    /// find_dtls_handshake_config returns the handshake config presenting the certificate whose
+    /// fingerprints are advertised to candidate
+    pub(crate) fn find_dtls_handshake_config(
+        &self,
+        candidate: &Candidate,
+    ) -> Result<Arc<dtls::config::HandshakeConfig>> {
+        self.certificate_store
+            .find_dtls_handshake_config(
+                &candidate
+                    .local_connection_credentials()
+                    .dtls_params
+                    .fingerprints,
+            )
+            .ok_or(Error::ErrInvalidCertificate)
+    }
+
     pub(crate) fn server_config(&self) -> &Arc<ServerConfig> {
         &self.server_config
     }
@@ -208,7 +1244,13 @@ impl ServerStates {
         info!(
             "{}/{} is connected via {:?}",
             session_id, endpoint_id, four_tuple
-        )
+        );
+        self.emit_event(ServerEvent::EndpointConnected {
+            session_id,
+            endpoint_id,
+            local_addr: four_tuple.local_addr,
+            peer_addr: four_tuple.peer_addr,
+        });
     }
 
     pub(crate) fn remove_endpoint(&mut self, four_tuple: &FourTuple) {
@@ -266,8 +1308,107 @@ impl ServerStates {
         Ok(transport)
     }
 
+    /// migrate_transport moves transport from four_tuple to new_four_tuple nominated with
+    /// candidate, keeping its DTLS, SCTP and SRTP states
+    pub(crate) fn migrate_transport(
+        &mut self,
+        four_tuple: FourTuple,
+        new_four_tuple: FourTuple,
+        candidate: Rc<Candidate>,
+    ) -> Result<()> {
+        let (session_id, endpoint_id) = self.find_endpoint(&four_tuple).ok_or(Error::Other(
+            format!("can't find endpoint with four_tuple {:?}", four_tuple),
+        ))?;
+        let endpoint = self.get_mut_endpoint(&four_tuple)?;
+        let mut transport = endpoint
+            .remove_transport(&four_tuple)
+            .ok_or(Error::Other(format!(
+                "can't find transport with four_tuple {:?} for endpoint id {}",
+                four_tuple, endpoint_id,
+            )))?;
+        let previous_candidate = Rc::clone(transport.candidate());
+        transport.migrate(new_four_tuple, Rc::clone(&candidate));
+        endpoint.add_transport(transport);
+
+        if matches!(endpoint.ice_restart(),
+            Some(IceRestart::Pending(pending, _)) if Rc::ptr_eq(pending, &candidate))
+        {
+            endpoint.set_ice_restart(None);
+        }
+        let is_previous_candidate_used = endpoint
+            .get_transports()
+            .values()
+            .any(|transport| Rc::ptr_eq(transport.candidate(), &previous_candidate));
+        if !is_previous_candidate_used {
+            self.remove_candidate(&previous_candidate.username());
+        }
+
+        self.endpoints.remove(&four_tuple);
+        self.endpoints
+            .insert(new_four_tuple, (session_id, endpoint_id));
+        info!(
+            "{}/{} is migrated from {:?} to {:?}",
+            session_id, endpoint_id, four_tuple, new_four_tuple
+        );
+        self.emit_event(ServerEvent::TransportMigrated {
+            session_id,
+            endpoint_id,
+            previous_local_addr: four_tuple.local_addr,
+            previous_peer_addr: four_tuple.peer_addr,
+            local_addr: new_four_tuple.local_addr,
+            peer_addr: new_four_tuple.peer_addr,
+        });
+        Ok(())
+    }
+
+    /// remove_expired_candidates removes the candidates which are not nominated before they
+    /// expire, together with the endpoints which are never connected by them
+    pub(crate) fn remove_expired_candidates(&mut self, now: Instant) {
+        let expired: Vec<Rc<Candidate>> = self
+            .candidates
+            .values()
+            .filter(|candidate| candidate.expired_time() <= now)
+            .cloned()
+            .collect();
+
+        let mut events = vec![];
+        for candidate in expired {
+            let (session_id, endpoint_id) = (candidate.session_id(), candidate.endpoint_id());
+            if let Some(session) = self.sessions.get_mut(&session_id) {
+                if let Some(endpoint) = session.get_endpoint(&endpoint_id) {
+                    let is_used = endpoint
+                        .get_transports()
+                        .values()
+                        .any(|transport| Rc::ptr_eq(transport.candidate(), &candidate))
+                        || matches!(endpoint.ice_restart(),
+                            Some(IceRestart::Pending(pending, _)) if Rc::ptr_eq(pending, &candidate));
+                    if is_used {
+                        continue;
+                    }
+                    if endpoint.get_transports().is_empty() {
+                        debug!("{}/{} is never connected", session_id, endpoint_id);
+                        for mid in session.remove_endpoint(&endpoint_id) {
+                            events.push(ServerEvent::TrackUnpublished {
+                                session_id,
+                                endpoint_id,
+                                mid,
+                            });
+                        }
+                    }
+                }
+                if session.get_endpoints().is_empty() {
+                    self.remove_session(&session_id);
+                }
+            }
+            self.remove_candidate(&candidate.username());
+        }
+        for event in events {
+            self.emit_event(event);
+        }
+    }
+
     pub(crate) fn remove_transport(&mut self, four_tuple: FourTuple) {
-        debug!("remove idle transport {:?}", four_tuple);
+        debug!("remove transport {:?}", four_tuple);
 
         let Some((session_id, endpoint_id)) = self.find_endpoint(&four_tuple) else {
             return;
@@ -280,15 +1421,53 @@ impl ServerStates {
         };
 
         let transport = endpoint.remove_transport(&four_tuple);
+        let mut events = vec![];
+        if transport.is_some() {
+            events.push(ServerEvent::TransportRemoved {
+                session_id,
+                endpoint_id,
+                local_addr: four_tuple.local_addr,
+                peer_addr: four_tuple.peer_addr,
+            });
+        }
+        let mut candidates = vec![];
+        if let Some(transport) = &transport {
+            candidates.push(Rc::clone(transport.candidate()));
+        }
         if endpoint.get_transports().is_empty() {
-            session.remove_endpoint(&endpoint_id);
+            if let Some(IceRestart::Pending(candidate, _)) = endpoint.set_ice_restart(None) {
+                candidates.push(candidate);
+            }
+            for mid in session.remove_endpoint(&endpoint_id) {
+                events.push(ServerEvent::TrackUnpublished {
+                    session_id,
+                    endpoint_id,
+                    mid,
+                });
+            }
+            events.push(ServerEvent::EndpointDisconnected {
+                session_id,
+                endpoint_id,
+            });
             if session.get_endpoints().is_empty() {
                 self.remove_session(&session_id);
+                events.push(ServerEvent::SessionClosed { session_id });
             }
-            self.remove_endpoint(&four_tuple);
         }
-        if let Some(transport) = transport {
-            self.remove_candidate(&transport.candidate().username());
+        self.remove_endpoint(&four_tuple);
+        if transport.is_some()
+            && self
+                .server_config
+                .tcp_addrs
+                .contains(&four_tuple.local_addr)
+        {
+            self.removed_tcp_four_tuples.push_back(four_tuple);
+        }
+        for candidate in candidates {
+            self.remove_candidate(&candidate.username());
+        }
+        for event in events {
+            self.emit_event(event);
         }
     }
 }
@@ -0,0 +1,918 @@
+use super::states::ServerStates;
+use crate::description::rtp_codec::{RTCRtpHeaderExtensionCapability, RTPCodecType};
+use crate::description::rtp_transceiver_direction::RTCRtpTransceiverDirection;
+use crate::description::RTCSessionDescription;
+use crate::endpoint::candidate::Candidate;
+use crate::endpoint::IceRestart;
+use crate::messages::{
+    ApplicationMessage, DTLSMessageEvent, DataChannelEvent, DataChannelMessageParams,
+    DataChannelMessageType, MessageEvent, RTPMessageEvent, TaggedMessageEvent,
+};
+use crate::server::certificate::RTCDtlsFingerprint;
+use crate::server::events::ServerEvent;
+use crate::types::{EndpointId, FourTuple};
+use crate::{
+    DataChannelOptions, GatewayHandler, MediaConfig, RTCCertificate, RTCIceConnectionState,
+    SctpHandler, ServerConfig, SrtpDecryptErrorCounts, SrtpHandler,
+};
+use bytes::BytesMut;
+use dtls::extension::extension_use_srtp::SrtpProtectionProfile;
+use retty::channel::{Context, Handler, InboundPipeline, Pipeline};
+use retty::transport::TransportContext;
+use shared::error::Error;
+use shared::marshal::Marshal;
+use srtp::option::srtp_replay_protection;
+use srtp::protection_profile::ProtectionProfile;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const OFFER_WITH_AUDIO: &str = "v=0\r
+o=- 1 2 IN IP4 127.0.0.1\r
+s=-\r
+t=0 0\r
+a=group:BUNDLE 0 1\r
+m=audio 9 UDP/TLS/RTP/SAVPF 111\r
+c=IN IP4 0.0.0.0\r
+a=ice-ufrag:ufrag\r
+a=ice-pwd:abcdefghijklmnopqrstuvwxyz\r
+a=fingerprint:sha-256 AB:AB:AB:AB:AB:AB:AB:AB:AB:AB:AB:AB:AB:AB:AB:AB:AB:AB:AB:AB:AB:AB:AB:AB:AB:AB:AB:AB:AB:AB:AB:AB\r
+a=setup:actpass\r
+a=mid:0\r
+a=sendonly\r
+a=rtcp-mux\r
+a=rtpmap:111 opus/48000/2\r
+a=ssrc:1111 cname:cname\r
+a=ssrc:1111 msid:stream track\r
+a=msid:stream track\r
+m=application 9 UDP/DTLS/SCTP webrtc-datachannel\r
+c=IN IP4 0.0.0.0\r
+a=ice-ufrag:ufrag\r
+a=ice-pwd:abcdefghijklmnopqrstuvwxyz\r
+a=fingerprint:sha-256 AB:AB:AB:AB:AB:AB:AB:AB:AB:AB:AB:AB:AB:AB:AB:AB:AB:AB:AB:AB:AB:AB:AB:AB:AB:AB:AB:AB:AB:AB:AB:AB\r
+a=setup:actpass\r
+a=mid:1\r
+a=sctp-port:5000\r
+";
+
+fn server_states() -> ServerStates {
+    let key_pair = rcgen::KeyPair::generate(&rcgen::PKCS_ECDSA_P256_SHA256).unwrap();
+    let certificates = vec![RTCCertificate::from_key_pair(key_pair).unwrap()];
+    let server_config = ServerConfig::new(certificates).with_emit_events(true);
+    ServerStates::new(
+        Arc::new(server_config),
+        "127.0.0.1:3478".parse().unwrap(),
+        opentelemetry::global::meter("test"),
+    )
+    .unwrap()
+}
+
+fn offer() -> RTCSessionDescription {
+    RTCSessionDescription::offer(OFFER_WITH_AUDIO.to_string()).unwrap()
+}
+
+/// candidate accepted for endpoint_id
+fn candidate(server_states: &ServerStates, endpoint_id: EndpointId) -> Rc<Candidate> {
+    server_states
+        .get_candidates()
+        .values()
+        .find(|candidate| candidate.endpoint_id() == endpoint_id)
+        .cloned()
+        .unwrap()
+}
+
+/// connect nominates the candidate of endpoint_id on a transport from peer_addr
+fn connect(
+    server_states: &mut ServerStates,
+    endpoint_id: EndpointId,
+    peer_addr: &str,
+) -> FourTuple {
+    let candidate = candidate(server_states, endpoint_id);
+    let transport_context = TransportContext {
+        local_addr: "127.0.0.1:3478".parse().unwrap(),
+        peer_addr: peer_addr.parse().unwrap(),
+        ecn: None,
+    };
+    let dtls_handshake_config = server_states
+        .find_dtls_handshake_config(&candidate)
+        .unwrap();
+    server_states
+        .get_mut_session(&candidate.session_id())
+        .unwrap()
+        .add_endpoint(&candidate, &transport_context, dtls_handshake_config)
+        .unwrap();
+    let four_tuple: FourTuple = (&transport_context).into();
+    server_states.add_endpoint(four_tuple, candidate.session_id(), endpoint_id);
+    four_tuple
+}
+
+/// DTLS handshake with a client over memory, driven by retransmission timers
+fn dtls_handshake(
+    server_states: &mut ServerStates,
+    four_tuple: FourTuple,
+) -> (dtls::endpoint::Endpoint, Instant) {
+    let key_pair = rcgen::KeyPair::generate(&rcgen::PKCS_ECDSA_P256_SHA256).unwrap();
+    let certificate = RTCCertificate::from_key_pair(key_pair).unwrap();
+    let client_config = dtls::config::ConfigBuilder::default()
+        .with_certificates(vec![certificate.dtls_certificate])
+        .with_insecure_skip_verify(true);
+    let (client, now, is_handshake_completed) =
+        drive_dtls_handshake(server_states, four_tuple, client_config);
+    assert!(is_handshake_completed);
+    (client, now)
+}
+
+/// drives DTLS handshake of a client built from client_config, returning whether the client
+/// completes it
+fn drive_dtls_handshake(
+    server_states: &mut ServerStates,
+    four_tuple: FourTuple,
+    client_config: dtls::config::ConfigBuilder,
+) -> (dtls::endpoint::Endpoint, Instant, bool) {
+    let server_addr: SocketAddr = four_tuple.local_addr;
+    let client_config = Arc::new(client_config.build(true, Some(server_addr)).unwrap());
+    let mut client = dtls::endpoint::Endpoint::new(None);
+    client.connect(server_addr, client_config, None).unwrap();
+    let mut now = Instant::now();
+    let mut is_handshake_completed = false;
+    for _ in 0..10 {
+        now += Duration::from_secs(1);
+        let transport = server_states.get_mut_transport(&four_tuple).unwrap();
+        let server = transport.get_mut_dtls_endpoint();
+        let _ = server.handle_timeout(four_tuple.peer_addr, now);
+        let _ = client.handle_timeout(server_addr, now);
+        while let Some(transmit) = client.poll_transmit() {
+            let _ = server.read(now, four_tuple.peer_addr, None, None, transmit.payload);
+        }
+        while let Some(transmit) = server.poll_transmit() {
+            if let Ok(events) = client.read(now, server_addr, None, None, transmit.payload) {
+                is_handshake_completed |= events
+                    .iter()
+                    .any(|event| matches!(event, dtls::endpoint::EndpointEvent::HandshakeComplete));
+            }
+        }
+    }
+    (client, now, is_handshake_completed)
+}
+
+type Reads = Rc<RefCell<Vec<TaggedMessageEvent>>>;
+
+/// collects the messages read out of the handlers under test
+struct ReadCollector {
+    reads: Reads,
+}
+
+impl Handler for ReadCollector {
+    type Rin = TaggedMessageEvent;
+    type Rout = Self::Rin;
+    type Win = TaggedMessageEvent;
+    type Wout = Self::Win;
+
+    fn name(&self) -> &str {
+        "ReadCollector"
+    }
+
+    fn handle_read(
+        &mut self,
+        _ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+        msg: Self::Rin,
+    ) {
+        self.reads.borrow_mut().push(msg);
+    }
+
+    fn poll_write(
+        &mut self,
+        ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+    ) -> Option<Self::Wout> {
+        ctx.fire_poll_write()
+    }
+}
+
+fn tagged_message(four_tuple: FourTuple, message: MessageEvent) -> TaggedMessageEvent {
+    TaggedMessageEvent {
+        now: Instant::now(),
+        transport: TransportContext {
+            local_addr: four_tuple.local_addr,
+            peer_addr: four_tuple.peer_addr,
+            ecn: None,
+        },
+        message,
+    }
+}
+
+/// SCTP association with a client over memory, established through pipeline of SctpHandler,
+/// returning the client association and its last packet sent to server
+fn sctp_associate(
+    pipeline: &Rc<Pipeline<TaggedMessageEvent, TaggedMessageEvent>>,
+    four_tuple: FourTuple,
+) -> (sctp::Association, BytesMut) {
+    let mut client = sctp::Endpoint::new(Arc::new(sctp::EndpointConfig::default()), None);
+    let (_, mut association) = client
+        .connect(sctp::ClientConfig::default(), four_tuple.local_addr)
+        .unwrap();
+    let mut last_packet = BytesMut::new();
+    for _ in 0..10 {
+        let now = Instant::now();
+        while let Some(transmit) = association.poll_transmit(now) {
+            if let sctp::Payload::RawEncode(contents) = transmit.payload {
+                for content in contents {
+                    last_packet = BytesMut::from(&content[..]);
+                    pipeline.read(tagged_message(
+                        four_tuple,
+                        MessageEvent::Dtls(DTLSMessageEvent::Raw(last_packet.clone())),
+                    ));
+                }
+            }
+        }
+        while let Some(transmit) = pipeline.poll_transmit() {
+            if let MessageEvent::Dtls(DTLSMessageEvent::Raw(content)) = transmit.message {
+                if let Some((_, sctp::DatagramEvent::AssociationEvent(event))) =
+                    client.handle(now, four_tuple.local_addr, None, None, content.freeze())
+                {
+                    association.handle_event(event);
+                }
+            }
+        }
+    }
+    assert!(!association.is_handshaking());
+    (association, last_packet)
+}
+
+fn events(server_states: &mut ServerStates) -> Vec<ServerEvent> {
+    std::iter::from_fn(|| server_states.poll_event()).collect()
+}
+
+#[test]
+fn test_answer_keeps_offered_transport_cc_id() {
+    let mut server_states = server_states();
+    let answer = server_states.accept_offer(1, 2, None, offer()).unwrap();
+    assert!(!answer.sdp.contains(sdp::extmap::TRANSPORT_CC_URI));
+
+    let sdp = OFFER_WITH_AUDIO.replace(
+        "a=rtcp-mux\r\n",
+        &format!(
+            "a=rtcp-mux\r\na=extmap:7 {}\r\n",
+            sdp::extmap::TRANSPORT_CC_URI
+        ),
+    );
+    let answer = server_states
+        .accept_offer(1, 3, None, RTCSessionDescription::offer(sdp).unwrap())
+        .unwrap();
+    assert!(answer
+        .sdp
+        .contains(&format!("a=extmap:7 {}", sdp::extmap::TRANSPORT_CC_URI)));
+}
+
+#[test]
+fn test_answer_keeps_configured_header_extensions() {
+    let mut media_config = MediaConfig::default();
+    media_config
+        .register_header_extension(
+            RTCRtpHeaderExtensionCapability {
+                uri: sdp::extmap::AUDIO_LEVEL_URI.to_owned(),
+            },
+            RTPCodecType::Audio,
+            None,
+        )
+        .unwrap();
+    let key_pair = rcgen::KeyPair::generate(&rcgen::PKCS_ECDSA_P256_SHA256).unwrap();
+    let certificates = vec![RTCCertificate::from_key_pair(key_pair).unwrap()];
+    let server_config = ServerConfig::new(certificates).with_media_config(media_config);
+    let mut server_states = ServerStates::new(
+        Arc::new(server_config),
+        "127.0.0.1:3478".parse().unwrap(),
+        opentelemetry::global::meter("test"),
+    )
+    .unwrap();
+
+    // header extensions other than transport-cc are advertised as configured
+    let answer = server_states.accept_offer(1, 2, None, offer()).unwrap();
+    assert!(answer.sdp.contains(sdp::extmap::AUDIO_LEVEL_URI));
+}
+
+#[test]
+fn test_initial_offer_publishes_tracks() {
+    let mut server_states = server_states();
+    let answer = server_states.accept_offer(1, 2, None, offer()).unwrap();
+    assert!(answer.sdp.contains("m=audio"));
+    assert!(answer.sdp.contains("a=recvonly"));
+
+    assert_eq!(
+        events(&mut server_states),
+        vec![ServerEvent::TrackPublished {
+            session_id: 1,
+            endpoint_id: 2,
+            mid: "0".to_string(),
+        }]
+    );
+
+    // offered again before it is connected
+    assert!(server_states.accept_offer(1, 2, None, offer()).is_err());
+}
+
+#[test]
+fn test_close_unconnected_endpoint_unpublishes_tracks() {
+    let mut server_states = server_states();
+    server_states.accept_offer(1, 2, None, offer()).unwrap();
+    events(&mut server_states);
+
+    server_states.close_endpoint(1, 2).unwrap();
+    assert_eq!(
+        events(&mut server_states),
+        vec![
+            ServerEvent::TrackUnpublished {
+                session_id: 1,
+                endpoint_id: 2,
+                mid: "0".to_string(),
+            },
+            ServerEvent::EndpointDisconnected {
+                session_id: 1,
+                endpoint_id: 2,
+            },
+            ServerEvent::SessionClosed { session_id: 1 },
+        ]
+    );
+    assert!(server_states.get_session(&1).is_none());
+    assert!(server_states.get_candidates().is_empty());
+}
+
+#[test]
+fn test_close_unconnected_endpoint_keeps_session() {
+    let mut server_states = server_states();
+    server_states.accept_offer(1, 2, None, offer()).unwrap();
+    server_states.accept_offer(1, 3, None, offer()).unwrap();
+    events(&mut server_states);
+
+    // session is kept for the other endpoint
+    server_states.close_endpoint(1, 3).unwrap();
+    let events = events(&mut server_states);
+    assert_eq!(
+        events.last(),
+        Some(&ServerEvent::EndpointDisconnected {
+            session_id: 1,
+            endpoint_id: 3,
+        })
+    );
+    assert!(!events.contains(&ServerEvent::SessionClosed { session_id: 1 }));
+    assert!(server_states.get_session(&1).is_some());
+}
+
+#[test]
+fn test_remove_expired_candidates() {
+    let mut server_states = server_states();
+    server_states.accept_offer(1, 2, None, offer()).unwrap();
+    events(&mut server_states);
+    assert_eq!(server_states.get_candidates().len(), 1);
+
+    // not expired yet
+    server_states.remove_expired_candidates(Instant::now());
+    assert_eq!(server_states.get_candidates().len(), 1);
+    assert!(events(&mut server_states).is_empty());
+
+    server_states.remove_expired_candidates(Instant::now() + Duration::from_secs(60));
+    assert!(server_states.get_candidates().is_empty());
+    assert!(server_states.get_session(&1).is_none());
+    assert_eq!(
+        events(&mut server_states),
+        vec![ServerEvent::TrackUnpublished {
+            session_id: 1,
+            endpoint_id: 2,
+            mid: "0".to_string(),
+        }]
+    );
+}
+
+#[test]
+fn test_remove_transport() {
+    let mut server_states = server_states();
+    server_states.accept_offer(1, 2, None, offer()).unwrap();
+    server_states.accept_offer(1, 3, None, offer()).unwrap();
+    connect(&mut server_states, 2, "127.0.0.1:5002");
+    let four_tuple = connect(&mut server_states, 3, "127.0.0.1:5003");
+    events(&mut server_states);
+
+    {
+        let subscriber = server_states
+            .get_session(&1)
+            .unwrap()
+            .get_endpoint(&2)
+            .unwrap();
+        assert_eq!(
+            subscriber.get_transceivers()["3-0"].direction,
+            RTCRtpTransceiverDirection::Sendonly
+        );
+    }
+
+    server_states.remove_transport(four_tuple);
+    assert_eq!(
+        events(&mut server_states),
+        vec![
+            ServerEvent::TransportRemoved {
+                session_id: 1,
+                endpoint_id: 3,
+                local_addr: four_tuple.local_addr,
+                peer_addr: four_tuple.peer_addr,
+            },
+            ServerEvent::TrackUnpublished {
+                session_id: 1,
+                endpoint_id: 3,
+                mid: "0".to_string(),
+            },
+            ServerEvent::EndpointDisconnected {
+                session_id: 1,
+                endpoint_id: 3,
+            },
+        ]
+    );
+    assert!(server_states.find_endpoint(&four_tuple).is_none());
+    assert_eq!(server_states.get_candidates().len(), 1);
+
+    // the subscriber drops the departed track by renegotiation
+    let session = server_states.get_session(&1).unwrap();
+    assert!(!session.has_endpoint(&3));
+    let subscriber = session.get_endpoint(&2).unwrap();
+    assert_eq!(
+        subscriber.get_transceivers()["3-0"].direction,
+        RTCRtpTransceiverDirection::Inactive
+    );
+    assert!(subscriber.is_renegotiation_needed());
+
+    // removing an unknown transport is a no-op
+    server_states.remove_transport(four_tuple);
+    assert!(events(&mut server_states).is_empty());
+}
+
+#[test]
+fn test_close_endpoint_sends_close_notify() {
+    let mut server_states = server_states();
+    server_states.accept_offer(1, 2, None, offer()).unwrap();
+    let four_tuple = connect(&mut server_states, 2, "127.0.0.1:5002");
+    events(&mut server_states);
+
+    let (mut client, now) = dtls_handshake(&mut server_states, four_tuple);
+    let server_addr = four_tuple.local_addr;
+
+    server_states.close_endpoint(1, 2).unwrap();
+    let transmit = server_states.poll_dtls_transmit().unwrap();
+    assert_eq!(transmit.transport.peer_addr, four_tuple.peer_addr);
+    let MessageEvent::Dtls(DTLSMessageEvent::Raw(dtls_record)) = transmit.message else {
+        panic!("expected DTLS record");
+    };
+    // peer reads close_notify alert
+    assert!(matches!(
+        client.read(now, server_addr, None, None, dtls_record),
+        Err(Error::ErrAlertFatalOrClose)
+    ));
+    assert!(server_states.get_session(&1).is_none());
+}
+
+#[test]
+fn test_dtls_handshake_requires_client_certificate() {
+    let mut server_states = server_states();
+    server_states.accept_offer(1, 2, None, offer()).unwrap();
+    let four_tuple = connect(&mut server_states, 2, "127.0.0.1:5002");
+
+    // client certificate is required to be verified against SDP fingerprints
+    let client_config = dtls::config::ConfigBuilder::default().with_insecure_skip_verify(true);
+    let (_, _, is_handshake_completed) =
+        drive_dtls_handshake(&mut server_states, four_tuple, client_config);
+    assert!(!is_handshake_completed);
+}
+
+#[test]
+fn test_dtls_handshake_offers_srtp_policy() {
+    let key_pair = rcgen::KeyPair::generate(&rcgen::PKCS_ECDSA_P256_SHA256).unwrap();
+    let certificates = vec![RTCCertificate::from_key_pair(key_pair).unwrap()];
+    let server_config = ServerConfig::new(certificates).with_srtp_require_aead(true);
+    let mut server_states = ServerStates::new(
+        Arc::new(server_config),
+        "127.0.0.1:3478".parse().unwrap(),
+        opentelemetry::global::meter("test"),
+    )
+    .unwrap();
+    server_states.accept_offer(1, 2, None, offer()).unwrap();
+    server_states.accept_offer(1, 3, None, offer()).unwrap();
+
+    // only AEAD_AES_128_GCM is offered by server when AEAD is required
+    for (endpoint_id, peer_addr, srtp_protection_profile, is_allowed) in [
+        (
+            2,
+            "127.0.0.1:5002",
+            SrtpProtectionProfile::Srtp_Aes128_Cm_Hmac_Sha1_80,
+            false,
+        ),
+        (
+            3,
+            "127.0.0.1:5003",
+            SrtpProtectionProfile::Srtp_Aead_Aes_128_Gcm,
+            true,
+        ),
+    ] {
+        let four_tuple = connect(&mut server_states, endpoint_id, peer_addr);
+        let key_pair = rcgen::KeyPair::generate(&rcgen::PKCS_ECDSA_P256_SHA256).unwrap();
+        let certificate = RTCCertificate::from_key_pair(key_pair).unwrap();
+        let client_config = dtls::config::ConfigBuilder::default()
+            .with_certificates(vec![certificate.dtls_certificate])
+            .with_srtp_protection_profiles(vec![srtp_protection_profile])
+            .with_insecure_skip_verify(true);
+        let (client, _, is_handshake_completed) =
+            drive_dtls_handshake(&mut server_states, four_tuple, client_config);
+        assert_eq!(is_handshake_completed, is_allowed);
+        if is_allowed {
+            let state = client.get_connection_state(four_tuple.local_addr).unwrap();
+            assert_eq!(state.srtp_protection_profile(), srtp_protection_profile);
+        }
+    }
+}
+
+#[test]
+fn test_ice_connection_states_by_four_tuple() {
+    let mut server_states = server_states();
+    server_states.accept_offer(1, 2, None, offer()).unwrap();
+    let udp_four_tuple = connect(&mut server_states, 2, "127.0.0.1:5002");
+    // same peer address reaches another listener of server
+    let candidate = candidate(&server_states, 2);
+    let transport_context = TransportContext {
+        local_addr: "127.0.0.1:3479".parse().unwrap(),
+        peer_addr: udp_four_tuple.peer_addr,
+        ecn: None,
+    };
+    let dtls_handshake_config = server_states
+        .find_dtls_handshake_config(&candidate)
+        .unwrap();
+    server_states
+        .get_mut_session(&1)
+        .unwrap()
+        .add_endpoint(&candidate, &transport_context, dtls_handshake_config)
+        .unwrap();
+    let tcp_four_tuple: FourTuple = (&transport_context).into();
+    server_states.add_endpoint(tcp_four_tuple, 1, 2);
+    server_states.refresh_consent(&udp_four_tuple, Instant::now());
+
+    assert_eq!(
+        server_states.ice_connection_states(1, 2).unwrap(),
+        HashMap::from([
+            (udp_four_tuple, RTCIceConnectionState::Connected),
+            (tcp_four_tuple, RTCIceConnectionState::Checking),
+        ])
+    );
+}
+
+#[test]
+fn test_srtp_decrypt_error_counts() {
+    let server_states = Rc::new(RefCell::new(server_states()));
+    server_states
+        .borrow_mut()
+        .accept_offer(1, 2, None, offer())
+        .unwrap();
+    let four_tuple = connect(&mut server_states.borrow_mut(), 2, "127.0.0.1:5002");
+    let srtp_context = || {
+        srtp::context::Context::new(
+            &[1u8; 16],
+            &[2u8; 14],
+            ProtectionProfile::Aes128CmHmacSha1_80,
+            Some(srtp_replay_protection(64)),
+            None,
+        )
+        .unwrap()
+    };
+    server_states
+        .borrow_mut()
+        .get_mut_transport(&four_tuple)
+        .unwrap()
+        .set_remote_srtp_context(srtp_context());
+
+    let pipeline: Pipeline<TaggedMessageEvent, TaggedMessageEvent> = Pipeline::new();
+    pipeline.add_back(SrtpHandler::new(Rc::clone(&server_states)));
+    let pipeline = pipeline.finalize();
+    let packet = rtp::Packet {
+        header: rtp::header::Header {
+            version: 2,
+            payload_type: 111,
+            sequence_number: 1,
+            ssrc: 1111,
+            ..Default::default()
+        },
+        payload: vec![0u8; 10].into(),
+    };
+    let encrypted = srtp_context()
+        .encrypt_rtp(&packet.marshal().unwrap())
+        .unwrap();
+    let mut tampered = encrypted.clone();
+    tampered[12] ^= 1;
+    for message in [tampered, encrypted.clone(), encrypted] {
+        pipeline.read(TaggedMessageEvent {
+            now: Instant::now(),
+            transport: TransportContext {
+                local_addr: four_tuple.local_addr,
+                peer_addr: four_tuple.peer_addr,
+                ecn: None,
+            },
+            message: MessageEvent::Rtp(RTPMessageEvent::Raw(message)),
+        });
+    }
+
+    // tampered and replayed packets are counted on their transport
+    assert_eq!(
+        server_states
+            .borrow()
+            .srtp_decrypt_error_counts(1, 2)
+            .unwrap(),
+        HashMap::from([(
+            four_tuple,
+            SrtpDecryptErrorCounts {
+                replayed: 1,
+                auth_failed: 1,
+                decrypt_failed: 0,
+            }
+        )])
+    );
+}
+
+#[test]
+fn test_open_data_channel_survives_sctp_read_before_flush() {
+    let server_states = Rc::new(RefCell::new(server_states()));
+    server_states
+        .borrow_mut()
+        .accept_offer(1, 2, None, offer())
+        .unwrap();
+    let four_tuple = connect(&mut server_states.borrow_mut(), 2, "127.0.0.1:5002");
+
+    let reads = Rc::new(RefCell::new(vec![]));
+    let pipeline: Pipeline<TaggedMessageEvent, TaggedMessageEvent> = Pipeline::new();
+    pipeline.add_back(SctpHandler::new(Rc::clone(&server_states)));
+    pipeline.add_back(ReadCollector {
+        reads: Rc::clone(&reads),
+    });
+    let pipeline = pipeline.finalize();
+    let (_association, last_packet) = sctp_associate(&pipeline, four_tuple);
+
+    // SCTP packet is read before DataChannelOpen is flushed through poll_write
+    server_states
+        .borrow_mut()
+        .open_data_channel(1, 2, "server".to_string(), DataChannelOptions::default())
+        .unwrap();
+    reads.borrow_mut().clear();
+    pipeline.read(tagged_message(
+        four_tuple,
+        MessageEvent::Dtls(DTLSMessageEvent::Raw(last_packet)),
+    ));
+    assert!(!reads.borrow().iter().any(|read| matches!(
+        &read.message,
+        MessageEvent::Dtls(DTLSMessageEvent::DataChannel(ApplicationMessage {
+            data_channel_event: DataChannelEvent::Close,
+            ..
+        }))
+    )));
+}
+
+#[test]
+fn test_server_data_channel_messages_are_not_relayed() {
+    let server_states = Rc::new(RefCell::new(server_states()));
+    let mut four_tuples = vec![];
+    for (endpoint_id, peer_addr) in [(2, "127.0.0.1:5002"), (3, "127.0.0.1:5003")] {
+        server_states
+            .borrow_mut()
+            .accept_offer(1, endpoint_id, None, offer())
+            .unwrap();
+        four_tuples.push(connect(
+            &mut server_states.borrow_mut(),
+            endpoint_id,
+            peer_addr,
+        ));
+    }
+    // "server" is opened by server toward endpoint 2, and "chat" toward endpoint 3
+    let params: DataChannelMessageParams = (&DataChannelOptions::default()).into();
+    for (four_tuple, server_label) in [(four_tuples[0], "server"), (four_tuples[1], "chat")] {
+        let mut server_states = server_states.borrow_mut();
+        let transport = server_states.get_mut_transport(&four_tuple).unwrap();
+        for (stream_id, label) in [(1, "server"), (3, "chat")] {
+            transport.add_data_channel(0, stream_id, label.to_string(), params);
+            if label == server_label {
+                transport.add_server_data_channel(0, stream_id);
+            }
+        }
+    }
+    events(&mut server_states.borrow_mut());
+
+    let pipeline: Pipeline<TaggedMessageEvent, TaggedMessageEvent> = Pipeline::new();
+    pipeline.add_back(GatewayHandler::new(Rc::clone(&server_states)));
+    let pipeline = pipeline.finalize();
+    for stream_id in [1, 3] {
+        pipeline.read(tagged_message(
+            four_tuples[0],
+            MessageEvent::Dtls(DTLSMessageEvent::DataChannel(ApplicationMessage {
+                association_handle: 0,
+                stream_id,
+                data_channel_event: DataChannelEvent::Message(
+                    DataChannelMessageType::Text,
+                    BytesMut::from("hello"),
+                ),
+            })),
+        ));
+    }
+
+    // message on server's channel goes to application, and no server's channel is relayed to
+    assert!(pipeline.poll_transmit().is_none());
+    assert_eq!(
+        events(&mut server_states.borrow_mut()),
+        vec![ServerEvent::DataChannelMessage {
+            session_id: 1,
+            endpoint_id: 2,
+            label: "server".to_string(),
+            payload: BytesMut::from("hello"),
+            is_binary: false,
+        }]
+    );
+}
+
+#[test]
+fn test_migrate_transport_to_nominated_path() {
+    let mut server_states = server_states();
+    server_states.accept_offer(1, 2, None, offer()).unwrap();
+    let four_tuple = connect(&mut server_states, 2, "127.0.0.1:5002");
+    let new_four_tuple = FourTuple {
+        local_addr: four_tuple.local_addr,
+        peer_addr: "127.0.0.1:6002".parse().unwrap(),
+    };
+    events(&mut server_states);
+
+    // nomination on the selected path keeps the transport, another path takes it over
+    let endpoint = server_states
+        .get_session(&1)
+        .unwrap()
+        .get_endpoint(&2)
+        .unwrap();
+    let candidate = Rc::clone(endpoint.get_transports()[&four_tuple].candidate());
+    assert_eq!(
+        endpoint.find_migrated_transport(&four_tuple, &candidate),
+        None
+    );
+    assert_eq!(
+        endpoint.find_migrated_transport(&new_four_tuple, &candidate),
+        Some(four_tuple)
+    );
+
+    server_states
+        .migrate_transport(four_tuple, new_four_tuple, Rc::clone(&candidate))
+        .unwrap();
+    assert_eq!(
+        events(&mut server_states),
+        vec![ServerEvent::TransportMigrated {
+            session_id: 1,
+            endpoint_id: 2,
+            previous_local_addr: four_tuple.local_addr,
+            previous_peer_addr: four_tuple.peer_addr,
+            local_addr: new_four_tuple.local_addr,
+            peer_addr: new_four_tuple.peer_addr,
+        }]
+    );
+    assert!(server_states.find_endpoint(&four_tuple).is_none());
+    assert_eq!(server_states.find_endpoint(&new_four_tuple), Some((1, 2)));
+    let endpoint = server_states
+        .get_session(&1)
+        .unwrap()
+        .get_endpoint(&2)
+        .unwrap();
+    assert!(!endpoint.has_transport(&four_tuple));
+    assert_eq!(
+        endpoint.find_migrated_transport(&new_four_tuple, &candidate),
+        None
+    );
+}
+
+#[test]
+fn test_migrate_transport_on_ice_restart() {
+    let mut server_states = server_states();
+    server_states.accept_offer(1, 2, None, offer()).unwrap();
+    let four_tuple = connect(&mut server_states, 2, "127.0.0.1:5002");
+    let new_four_tuple = FourTuple {
+        local_addr: four_tuple.local_addr,
+        peer_addr: "127.0.0.1:6002".parse().unwrap(),
+    };
+
+    // changed remote ICE credentials restart ICE
+    let restart_offer = RTCSessionDescription::offer(
+        OFFER_WITH_AUDIO
+            .replace("ice-ufrag:ufrag", "ice-ufrag:restarted")
+            .replace(
+                "ice-pwd:abcdefghijklmnopqrstuvwxyz",
+                "ice-pwd:zyxwvutsrqponmlkjihgfedcba",
+            ),
+    )
+    .unwrap();
+    server_states
+        .accept_offer(1, 2, Some(four_tuple), restart_offer)
+        .unwrap();
+    assert_eq!(server_states.get_candidates().len(), 2);
+
+    let endpoint = server_states
+        .get_session(&1)
+        .unwrap()
+        .get_endpoint(&2)
+        .unwrap();
+    let Some(IceRestart::Pending(restarted_candidate, previous_candidate)) = endpoint.ice_restart()
+    else {
+        panic!("ICE restart is not pending");
+    };
+    let restarted_candidate = Rc::clone(restarted_candidate);
+    assert!(Rc::ptr_eq(
+        previous_candidate,
+        endpoint.get_transports()[&four_tuple].candidate()
+    ));
+
+    // the transport of the previous candidate migrates to the restarted path
+    assert_eq!(
+        endpoint.find_migrated_transport(&four_tuple, &restarted_candidate),
+        Some(four_tuple)
+    );
+    assert_eq!(
+        endpoint.find_migrated_transport(&new_four_tuple, &restarted_candidate),
+        Some(four_tuple)
+    );
+
+    server_states
+        .migrate_transport(four_tuple, new_four_tuple, Rc::clone(&restarted_candidate))
+        .unwrap();
+    let endpoint = server_states
+        .get_session(&1)
+        .unwrap()
+        .get_endpoint(&2)
+        .unwrap();
+    assert!(endpoint.ice_restart().is_none());
+    assert!(Rc::ptr_eq(
+        endpoint.get_transports()[&new_four_tuple].candidate(),
+        &restarted_candidate
+    ));
+    // the previous candidate is unused after migration
+    assert_eq!(server_states.get_candidates().len(), 1);
+}
+
+#[test]
+fn test_rotate_certificate() {
+    let mut server_states = server_states();
+    server_states.accept_offer(1, 2, None, offer()).unwrap();
+    let key_pair = rcgen::KeyPair::generate(&rcgen::PKCS_ECDSA_P256_SHA256).unwrap();
+    let certificate = RTCCertificate::from_key_pair(key_pair).unwrap();
+    server_states
+        .rotate_certificate(certificate.clone())
+        .unwrap();
+    server_states.accept_offer(1, 3, None, offer()).unwrap();
+    assert_eq!(
+        candidate(&server_states, 3)
+            .local_connection_credentials()
+            .dtls_params
+            .fingerprints,
+        certificate.get_fingerprints()
+    );
+
+    // each endpoint is presented the certificate whose fingerprints are advertised to it
+    for (endpoint_id, peer_addr) in [(2, "127.0.0.1:5002"), (3, "127.0.0.1:5003")] {
+        let fingerprints = candidate(&server_states, endpoint_id)
+            .local_connection_credentials()
+            .dtls_params
+            .fingerprints
+            .clone();
+        let four_tuple = connect(&mut server_states, endpoint_id, peer_addr);
+        let (client, _) = dtls_handshake(&mut server_states, four_tuple);
+        let state = client.get_connection_state(four_tuple.local_addr).unwrap();
+        let fingerprint = RTCDtlsFingerprint::new("sha-256", &state.peer_certificates[0]).unwrap();
+        assert!(fingerprints.contains(&fingerprint));
+    }
+}
+
+#[test]
+fn test_rotate_certificate_prunes_unused_certificates() {
+    let mut server_states = server_states();
+    server_states.accept_offer(1, 2, None, offer()).unwrap();
+    let first_candidate = candidate(&server_states, 2);
+
+    // the first certificate is kept while a candidate may still connect with it
+    let key_pair = rcgen::KeyPair::generate(&rcgen::PKCS_ECDSA_P256_SHA256).unwrap();
+    let second = RTCCertificate::from_key_pair(key_pair).unwrap();
+    server_states.rotate_certificate(second).unwrap();
+    server_states.accept_offer(1, 3, None, offer()).unwrap();
+    let second_candidate = candidate(&server_states, 3);
+    assert!(server_states
+        .find_dtls_handshake_config(&first_candidate)
+        .is_ok());
+    assert!(server_states
+        .find_dtls_handshake_config(&second_candidate)
+        .is_ok());
+
+    // the first and second certificates are advertised to no candidate anymore
+    server_states.close_endpoint(1, 2).unwrap();
+    server_states.close_endpoint(1, 3).unwrap();
+    let key_pair = rcgen::KeyPair::generate(&rcgen::PKCS_ECDSA_P256_SHA256).unwrap();
+    let third = RTCCertificate::from_key_pair(key_pair).unwrap();
+    server_states.rotate_certificate(third).unwrap();
+    for candidate in [first_candidate, second_candidate] {
+        assert!(matches!(
+            server_states.find_dtls_handshake_config(&candidate),
+            Err(Error::ErrInvalidCertificate)
+        ));
+    }
+}
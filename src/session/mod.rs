@@ -1,16 +1,19 @@
 use retty::transport::TransportContext;
+use sdp::description::media::MediaDescription;
 use sdp::description::session::Origin;
 use sdp::util::ConnectionRole;
 use sdp::SessionDescription;
 use shared::error::{Error, Result};
 use std::collections::{HashMap, HashSet};
 use std::rc::Rc;
+use std::sync::Arc;
 
 use crate::configs::session_config::SessionConfig;
 use crate::description::{
     codecs_from_media_description, get_cname, get_mid_value, get_msid, get_peer_direction,
-    get_rids, get_ssrc_groups, get_ssrcs, populate_sdp, rtp_extensions_from_media_description,
-    update_sdp_origin, MediaSection, RTCSessionDescription, MEDIA_SECTION_APPLICATION,
+    get_rids, get_send_rids, get_ssrc_groups, get_ssrcs, populate_sdp,
+    rtp_extensions_from_media_description, update_sdp_origin, MediaSection, RTCSessionDescription,
+    MEDIA_SECTION_APPLICATION,
 };
 use crate::description::{
     rtp_codec::{RTCRtpParameters, RTPCodecType},
@@ -19,16 +22,19 @@ use crate::description::{
     sdp_type::RTCSdpType,
 };
 use crate::endpoint::{
-    candidate::{Candidate, DTLSRole, RTCIceParameters, DEFAULT_DTLS_ROLE_OFFER},
+    candidate::{Candidate, ConnectionCredentials, DTLSRole, DEFAULT_DTLS_ROLE_OFFER},
     transport::Transport,
     Endpoint,
 };
+use crate::interceptors::stream_info::StreamInfo;
 use crate::types::{EndpointId, Mid, SessionId};
 
 pub(crate) struct Session {
     session_config: SessionConfig,
     session_id: SessionId,
     endpoints: HashMap<EndpointId, Endpoint>,
+    // publisher's (endpoint id, mid) to subscribers' endpoint ids
+    subscriptions: HashMap<(EndpointId, Mid), HashSet<EndpointId>>,
 }
 
 impl Session {
@@ -37,6 +43,7 @@ impl Session {
             session_config,
             session_id,
             endpoints: HashMap::new(),
+            subscriptions: HashMap::new(),
         }
     }
 
@@ -52,12 +59,8 @@ impl Session {
         &mut self,
         candidate: &Rc<Candidate>,
         transport_context: &TransportContext,
+        dtls_handshake_config: Arc<dtls::config::HandshakeConfig>,
     ) -> Result<bool> {
-        let dtls_handshake_config = self
-            .session_config
-            .server_config
-            .dtls_handshake_config
-            .clone();
         let sctp_endpoint_config = self
             .session_config
             .server_config
@@ -82,9 +85,7 @@ impl Session {
                 Ok(true)
             }
         } else {
-            let registry = self.session_config.server_config.media_config.registry();
-            let interceptor = registry.build(""); //TODO: use named registry id
-            let mut endpoint = Endpoint::new(endpoint_id, interceptor);
+            let mut endpoint = self.new_endpoint(endpoint_id);
             let transport = Transport::new(
                 four_tuple,
                 Rc::clone(candidate),
@@ -100,6 +101,31 @@ impl Session {
         }
     }
 
+    /// create_endpoint adds endpoint_id with its initial offer before it is connected, so that
+    /// the tracks of the initial offer are answered and published
+    pub(crate) fn create_endpoint(
+        &mut self,
+        endpoint_id: EndpointId,
+        offer: &RTCSessionDescription,
+    ) -> Result<()> {
+        if self.has_endpoint(&endpoint_id) {
+            return Err(Error::Other(format!(
+                "endpoint id {} already exists",
+                endpoint_id
+            )));
+        }
+        let mut endpoint = self.new_endpoint(endpoint_id);
+        endpoint.set_remote_description(offer.clone());
+        self.endpoints.insert(endpoint_id, endpoint);
+        Ok(())
+    }
+
+    fn new_endpoint(&self, endpoint_id: EndpointId) -> Endpoint {
+        let registry = self.session_config.server_config.media_config.registry();
+        let interceptor = registry.build(""); //TODO: use named registry id
+        Endpoint::new(endpoint_id, interceptor)
+    }
+
     pub(crate) fn get_endpoint(&self, endpoint_id: &EndpointId) -> Option<&Endpoint> {
         self.endpoints.get(endpoint_id)
     }
@@ -108,8 +134,17 @@ impl Session {
         self.endpoints.get_mut(endpoint_id)
     }
 
-    pub(crate) fn remove_endpoint(&mut self, endpoint_id: &EndpointId) -> Option<Endpoint> {
-        self.endpoints.remove(endpoint_id)
+    /// remove_endpoint removes endpoint_id with its subscriptions, and returns the mids of the
+    /// tracks still published by it
+    pub(crate) fn remove_endpoint(&mut self, endpoint_id: &EndpointId) -> Vec<Mid> {
+        let mids = self.remove_published_tracks(*endpoint_id);
+        for subscribers in self.subscriptions.values_mut() {
+            subscribers.remove(endpoint_id);
+        }
+        self.subscriptions
+            .retain(|_, subscribers| !subscribers.is_empty());
+        self.endpoints.remove(endpoint_id);
+        mids
     }
 
     pub(crate) fn has_endpoint(&self, endpoint_id: &EndpointId) -> bool {
@@ -124,6 +159,72 @@ impl Session {
         &mut self.endpoints
     }
 
+    /// add_subscription lets subscriber_id receive the track published by publisher_id with mid,
+    /// returns false if the subscription already exists.
+    pub(crate) fn add_subscription(
+        &mut self,
+        publisher_id: EndpointId,
+        mid: &Mid,
+        subscriber_id: EndpointId,
+    ) -> bool {
+        self.subscriptions
+            .entry((publisher_id, mid.to_string()))
+            .or_default()
+            .insert(subscriber_id)
+    }
+
+    /// remove_subscription stops forwarding the track published by publisher_id with mid to subscriber_id,
+    /// returns false if the subscription doesn't exist.
+    pub(crate) fn remove_subscription(
+        &mut self,
+        publisher_id: EndpointId,
+        mid: &Mid,
+        subscriber_id: EndpointId,
+    ) -> bool {
+        let key = (publisher_id, mid.to_string());
+        let Some(subscribers) = self.subscriptions.get_mut(&key) else {
+            return false;
+        };
+        let removed = subscribers.remove(&subscriber_id);
+        if subscribers.is_empty() {
+            self.subscriptions.remove(&key);
+        }
+        removed
+    }
+
+    /// remove_published_tracks stops forwarding all tracks published by publisher_id, marks
+    /// other endpoints whose transceivers are deactivated as renegotiation needed, and returns
+    /// the mids of the tracks which are unpublished by this call.
+    pub(crate) fn remove_published_tracks(&mut self, publisher_id: EndpointId) -> Vec<Mid> {
+        self.subscriptions
+            .retain(|(endpoint_id, _), _| *endpoint_id != publisher_id);
+        let mut mids = vec![];
+        for (&endpoint_id, endpoint) in self.endpoints.iter_mut() {
+            if endpoint_id == publisher_id {
+                // published transceivers become inactive, so endpoints joining later don't
+                // receive them
+                for transceiver in endpoint.get_mut_transceivers().values_mut() {
+                    if transceiver.direction == RTCRtpTransceiverDirection::Recvonly {
+                        transceiver.direction = RTCRtpTransceiverDirection::Inactive;
+                        mids.push(transceiver.mid.clone());
+                    }
+                }
+            } else if endpoint.deactivate_forwarding_transceivers(publisher_id) {
+                endpoint.set_renegotiation_needed(true);
+            }
+        }
+        mids.sort();
+        mids
+    }
+
+    pub(crate) fn get_subscribers(
+        &self,
+        publisher_id: EndpointId,
+        mid: &Mid,
+    ) -> Option<&HashSet<EndpointId>> {
+        self.subscriptions.get(&(publisher_id, mid.to_string()))
+    }
+
     pub(crate) fn set_remote_description(
         &mut self,
         endpoint_id: EndpointId,
@@ -178,8 +279,14 @@ impl Session {
                     .contains_key(mid_value);
 
                 if !has_mid_value {
-                    let cname = get_cname(media);
+                    let rids = get_send_rids(media);
                     let msid = get_msid(media);
+                    // simulcast encodings may be signaled by rids only, without any ssrc lines
+                    let cname = get_cname(media).or_else(|| {
+                        msid.as_ref()
+                            .filter(|_| !rids.is_empty())
+                            .map(|msid| msid.stream_id.clone())
+                    });
                     let ssrc_groups = get_ssrc_groups(media)?;
                     let ssrcs = get_ssrcs(media)?;
                     let codecs = codecs_from_media_description(media)?;
@@ -212,6 +319,7 @@ impl Session {
                         direction: local_direction,
                         current_direction: RTCRtpTransceiverDirection::Unspecified,
                         rtp_params: rtp_params.clone(),
+                        rids,
                         kind,
                     };
 
@@ -220,40 +328,70 @@ impl Session {
                         endpoint.get_mut_mids().push(mid_value.to_string());
                         endpoint
                             .get_mut_transceivers()
-                            .insert(mid_value.to_string(), transceiver);
+                            .insert(mid_value.to_string(), transceiver.clone());
+
+                        if direction.has_send() {
+                            endpoint
+                                .get_mut_interceptor()
+                                .bind_remote_stream(&StreamInfo {
+                                    mid: mid_value.to_string(),
+                                    ssrcs: sender
+                                        .as_ref()
+                                        .map(|sender| sender.ssrcs.clone())
+                                        .unwrap_or_default(),
+                                    rtp_header_extensions: rtp_params.header_extensions.clone(),
+                                    clock_rate: rtp_params
+                                        .codecs
+                                        .first()
+                                        .map(|codec| codec.capability.clock_rate)
+                                        .unwrap_or_default(),
+                                });
+                        }
                     }
 
                     // add it to other endpoints' transceivers as send only
-
+                    let codecs = self
+                        .session_config
+                        .server_config
+                        .media_config
+                        .get_codecs_by_kind(kind)
+                        .to_vec();
+                    let mut subscriber_ids = vec![];
                     for (&other_endpoint_id, other_endpoint) in self.get_mut_endpoints().iter_mut()
                     {
                         if other_endpoint_id != endpoint_id {
                             let other_mid_value = format!("{}-{}", endpoint_id, mid_value);
-                            let (other_mids, other_transceivers) =
-                                other_endpoint.get_mut_mids_and_transceivers();
-                            if let Some(other_transceiver) =
-                                other_transceivers.get_mut(&other_mid_value)
+                            if let Some(other_transceiver) = other_endpoint
+                                .get_mut_transceivers()
+                                .get_mut(&other_mid_value)
                             {
                                 if other_transceiver.direction != direction {
                                     other_transceiver.direction = direction;
                                     other_endpoint.set_renegotiation_needed(true);
                                 }
                             } else if direction == RTCRtpTransceiverDirection::Sendonly {
-                                let other_transceiver = RTCRtpTransceiver {
-                                    mid: other_mid_value.clone(),
-                                    sender: sender.clone(),
-                                    direction,
-                                    current_direction: RTCRtpTransceiverDirection::Unspecified,
-                                    rtp_params: rtp_params.clone(),
-                                    kind,
-                                };
-
-                                other_mids.push(other_mid_value.clone());
-                                other_transceivers.insert(other_mid_value, other_transceiver);
+                                other_endpoint.add_forwarding_transceiver(
+                                    endpoint_id,
+                                    &transceiver,
+                                    &codecs,
+                                );
                                 other_endpoint.set_renegotiation_needed(true);
+                                subscriber_ids.push(other_endpoint_id);
                             }
                         }
                     }
+
+                    if self.session_config.server_config.auto_subscribe {
+                        for subscriber_id in subscriber_ids {
+                            self.add_subscription(
+                                endpoint_id,
+                                &mid_value.to_string(),
+                                subscriber_id,
+                            );
+                        }
+                    }
+                } else {
+                    self.update_published_transceiver(endpoint_id, mid_value, media)?;
                 }
             } else {
                 // This is an answer from the remote.
@@ -272,12 +410,75 @@ impl Session {
                     // Set transceiver.[[CurrentDirection]] and transceiver.[[Direction]]s to direction.
                     transceiver.set_current_direction(reversed_direction);
                 }
+                if let Some(rtp_rewriter) = endpoint.get_mut_rtp_rewriter(mid_value) {
+                    rtp_rewriter.set_codecs(&codecs_from_media_description(media)?);
+                }
             }
         }
 
         Ok(())
     }
 
+    /// update_published_transceiver applies the ssrcs renegotiated by publisher endpoint_id to
+    /// its transceiver with mid, and rebinds the transceivers forwarding it to other endpoints.
+    fn update_published_transceiver(
+        &mut self,
+        endpoint_id: EndpointId,
+        mid: &Mid,
+        media: &MediaDescription,
+    ) -> Result<()> {
+        let ssrcs = get_ssrcs(media)?;
+        let Some(endpoint) = self.get_mut_endpoint(&endpoint_id) else {
+            return Ok(());
+        };
+        let Some(transceiver) = endpoint.get_mut_transceivers().get_mut(mid) else {
+            return Ok(());
+        };
+        if transceiver.direction != RTCRtpTransceiverDirection::Recvonly {
+            return Ok(());
+        }
+        let Some(sender) = transceiver.sender.as_mut() else {
+            return Ok(());
+        };
+        if sender.ssrcs == ssrcs {
+            return Ok(());
+        }
+        sender.ssrcs = ssrcs;
+        sender.ssrc_groups = get_ssrc_groups(media)?;
+        transceiver.rtp_params = RTCRtpParameters {
+            header_extensions: rtp_extensions_from_media_description(media)?,
+            codecs: codecs_from_media_description(media)?,
+        };
+        let transceiver = transceiver.clone();
+
+        endpoint
+            .get_mut_interceptor()
+            .bind_remote_stream(&StreamInfo {
+                mid: mid.clone(),
+                ssrcs: transceiver
+                    .sender
+                    .as_ref()
+                    .map(|sender| sender.ssrcs.clone())
+                    .unwrap_or_default(),
+                rtp_header_extensions: transceiver.rtp_params.header_extensions.clone(),
+                clock_rate: transceiver
+                    .rtp_params
+                    .codecs
+                    .first()
+                    .map(|codec| codec.capability.clock_rate)
+                    .unwrap_or_default(),
+            });
+
+        for (&other_endpoint_id, other_endpoint) in self.get_mut_endpoints().iter_mut() {
+            if other_endpoint_id != endpoint_id
+                && other_endpoint.update_forwarding_transceiver(endpoint_id, &transceiver)
+            {
+                other_endpoint.set_renegotiation_needed(true);
+            }
+        }
+        Ok(())
+    }
+
     pub(crate) fn set_local_description(
         &mut self,
         endpoint_id: EndpointId,
@@ -339,14 +540,14 @@ impl Session {
         &self,
         endpoint_id: EndpointId,
         remote_description: &RTCSessionDescription,
-        local_ice_params: &RTCIceParameters,
+        local_conn_cred: &ConnectionCredentials,
     ) -> Result<RTCSessionDescription> {
         let use_identity = false; //TODO: self.config.idp_login_url.is_some();
 
         let mut d = self.generate_matched_sdp(
             endpoint_id,
             remote_description,
-            local_ice_params,
+            local_conn_cred,
             use_identity,
             true, /*includeUnmatched */
             DEFAULT_DTLS_ROLE_OFFER.to_connection_role(),
@@ -370,13 +571,13 @@ impl Session {
         &self,
         endpoint: EndpointId,
         remote_description: &RTCSessionDescription,
-        local_ice_params: &RTCIceParameters,
+        local_conn_cred: &ConnectionCredentials,
     ) -> Result<RTCSessionDescription> {
         let use_identity = false; //TODO: self.config.idp_login_url.is_some();
         let mut d = self.generate_matched_sdp(
             endpoint,
             remote_description,
-            local_ice_params,
+            local_conn_cred,
             use_identity,
             false, /*includeUnmatched */
             DTLSRole::Server.to_connection_role(),
@@ -402,7 +603,7 @@ impl Session {
         &self,
         endpoint_id: EndpointId,
         remote_description: &RTCSessionDescription,
-        local_ice_params: &RTCIceParameters,
+        local_conn_cred: &ConnectionCredentials,
         use_identity: bool,
         include_unmatched: bool,
         connection_role: ConnectionRole,
@@ -485,12 +686,11 @@ impl Session {
             media_sections
         };
 
-        let dtls_fingerprints =
-            if let Some(cert) = self.session_config.server_config.certificates.first() {
-                cert.get_fingerprints()
-            } else {
-                return Err(Error::Other("ErrNonCertificate".to_string()));
-            };
+        // fingerprints of the certificate presented by the endpoint's transport
+        let dtls_fingerprints = &local_conn_cred.dtls_params.fingerprints;
+        if dtls_fingerprints.is_empty() {
+            return Err(Error::Other("ErrNonCertificate".to_string()));
+        }
 
         let transceivers = if let Some(endpoint) = self.get_endpoint(&endpoint_id) {
             endpoint.get_transceivers()
@@ -500,9 +700,9 @@ impl Session {
 
         populate_sdp(
             d,
-            &dtls_fingerprints,
+            dtls_fingerprints,
             &self.session_config,
-            local_ice_params,
+            &local_conn_cred.ice_params,
             connection_role,
             &media_sections,
             transceivers,
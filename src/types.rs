@@ -5,6 +5,7 @@ pub type SessionId = u64;
 pub type EndpointId = u64;
 pub type UserName = String;
 pub type Mid = String;
+pub type Rid = String;
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
 pub struct FourTuple {
@@ -28,7 +28,7 @@ use webrtc::track::track_local::track_local_static_rtp::TrackLocalStaticRTP;
 use webrtc::track::track_local::TrackLocal;
 use webrtc::track::track_remote::TrackRemote;
 
-pub const HOST: &'static str = "127.0.0.1";
+pub const HOST: &str = "127.0.0.1";
 pub const SIGNAL_PORT: u16 = 8080;
 
 fn pretty_sdp(input: &str) -> String {
@@ -131,7 +131,7 @@ pub async fn teardown_peer_connections(pcs: Vec<Arc<RTCPeerConnection>>) -> Resu
     Ok(())
 }
 
-async fn signaling(
+pub async fn signaling(
     host: &str,
     signal_port: u16,
     session_id: u64,
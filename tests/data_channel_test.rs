@@ -1,8 +1,18 @@
+#![allow(clippy::assertions_on_constants)]
+
 use crate::common::{HOST, SIGNAL_PORT};
 use log::error;
 use rand::random;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+use webrtc::data_channel::data_channel_init::RTCDataChannelInit;
+use webrtc::data_channel::data_channel_message::DataChannelMessage;
+use webrtc::ice_transport::ice_connection_state::RTCIceConnectionState;
 use webrtc::ice_transport::ice_server::RTCIceServer;
 use webrtc::peer_connection::configuration::RTCConfiguration;
+use webrtc::peer_connection::offer_answer_options::RTCOfferOptions;
 
 // importing common module.
 mod common;
@@ -24,7 +34,7 @@ async fn test_data_channel() -> anyhow::Result<()> {
         Ok(ok) => ok,
         Err(err) => {
             error!("error: {}", err);
-            return Err(err.into());
+            return Err(err);
         }
     };
 
@@ -32,7 +42,7 @@ async fn test_data_channel() -> anyhow::Result<()> {
         Ok(ok) => ok,
         Err(err) => {
             error!("error: {}", err);
-            return Err(err.into());
+            return Err(err);
         }
     };
 
@@ -40,7 +50,7 @@ async fn test_data_channel() -> anyhow::Result<()> {
         Ok(ok) => ok,
         Err(err) => {
             error!("error: {}", err);
-            return Err(err.into());
+            return Err(err);
         }
     }
     Ok(())
@@ -70,7 +80,7 @@ async fn test_data_channels() -> anyhow::Result<()> {
         Ok(ok) => ok,
         Err(err) => {
             error!("{}: error {}", session_id, err);
-            return Err(err.into());
+            return Err(err);
         }
     };
 
@@ -87,7 +97,7 @@ async fn test_data_channels() -> anyhow::Result<()> {
             Ok(ok) => ok,
             Err(err) => {
                 error!("{}/{}: error {}", session_id, endpoint_id, err);
-                return Err(err.into());
+                return Err(err);
             }
         };
     }
@@ -96,7 +106,496 @@ async fn test_data_channels() -> anyhow::Result<()> {
         Ok(ok) => ok,
         Err(err) => {
             error!("{}: error {}", session_id, err);
-            return Err(err.into());
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_signaling_envelope() -> anyhow::Result<()> {
+    // Prepare the configuration
+    let session_id: u64 = random::<u64>();
+    let endpoint_id = 0;
+    let config = RTCConfiguration {
+        ice_servers: vec![RTCIceServer {
+            urls: vec!["stun:stun.l.google.com:19302".to_owned()],
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+
+    let peer_connection = match common::setup_peer_connection(config, endpoint_id).await {
+        Ok(ok) => ok,
+        Err(err) => {
+            error!("error: {}", err);
+            return Err(err);
+        }
+    };
+
+    let (data_channel, _) =
+        match common::connect(HOST, SIGNAL_PORT, session_id, endpoint_id, &peer_connection).await {
+            Ok(ok) => ok,
+            Err(err) => {
+                error!("error: {}", err);
+                return Err(err);
+            }
+        };
+
+    // replace SDP message handling to receive raw envelopes
+    let (envelope_tx, mut envelope_rx) = tokio::sync::mpsc::unbounded_channel::<String>();
+    data_channel.on_message(Box::new(move |msg: DataChannelMessage| {
+        let _ = envelope_tx.send(String::from_utf8(msg.data.to_vec()).unwrap());
+        Box::pin(async {})
+    }));
+
+    for request in [
+        r#"{"version":1,"type":"subscribe","payload":{"publisher_id":1,"mid":"0"}}"#,
+        r#"{"version":1,"type":"unknown"}"#,
+        r#"{"version":2,"type":"ice-restart"}"#,
+    ] {
+        data_channel.send_text(request.to_string()).await?;
+        let response = envelope_rx.recv().await.unwrap();
+        let envelope = serde_json::from_str::<serde_json::Value>(&response)?;
+        assert_eq!(envelope["version"], 1, "{}", response);
+        assert_eq!(envelope["type"], "error", "{}", response);
+    }
+
+    match common::teardown_peer_connection(peer_connection).await {
+        Ok(ok) => ok,
+        Err(err) => {
+            error!("error: {}", err);
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_data_channel_relay() -> anyhow::Result<()> {
+    // Prepare the configuration
+    let endpoint_count: usize = 2;
+    let session_id: u64 = random::<u64>();
+    let config = RTCConfiguration {
+        ice_servers: vec![RTCIceServer {
+            urls: vec!["stun:stun.l.google.com:19302".to_owned()],
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+
+    let mut configs = vec![];
+    let mut endpoint_ids = vec![];
+    for endpoint_id in 0..endpoint_count {
+        configs.push(config.clone());
+        endpoint_ids.push(endpoint_id);
+    }
+
+    let peer_connections = match common::setup_peer_connections(configs, &endpoint_ids).await {
+        Ok(ok) => ok,
+        Err(err) => {
+            error!("{}: error {}", session_id, err);
+            return Err(err);
+        }
+    };
+
+    let mut chat_channels = vec![];
+    for (endpoint_id, peer_connection) in peer_connections.iter().enumerate() {
+        match common::connect(
+            HOST,
+            SIGNAL_PORT,
+            session_id,
+            endpoint_id as u64,
+            peer_connection,
+        )
+        .await
+        {
+            Ok(ok) => ok,
+            Err(err) => {
+                error!("{}/{}: error {}", session_id, endpoint_id, err);
+                return Err(err);
+            }
+        };
+
+        // open application data channel after the signaling one
+        let chat_channel = peer_connection.create_data_channel("chat", None).await?;
+        let chat_channel_opened_notify_tx = Arc::new(Notify::new());
+        let chat_channel_opened_notify_rx = chat_channel_opened_notify_tx.clone();
+        chat_channel.on_open(Box::new(move || {
+            chat_channel_opened_notify_tx.notify_waiters();
+            Box::pin(async {})
+        }));
+        let (chat_tx, chat_rx) = tokio::sync::mpsc::unbounded_channel::<String>();
+        chat_channel.on_message(Box::new(move |msg: DataChannelMessage| {
+            let _ = chat_tx.send(String::from_utf8(msg.data.to_vec()).unwrap());
+            Box::pin(async {})
+        }));
+        chat_channel_opened_notify_rx.notified().await;
+        chat_channels.push((chat_channel, chat_rx));
+    }
+
+    // a data channel is opened on client before its DCEP open reaches the SFU, so messages
+    // sent meanwhile are not relayed yet, retry until one is delivered
+    let message = loop {
+        chat_channels[0].0.send_text("hello".to_string()).await?;
+        if let Ok(message) =
+            tokio::time::timeout(Duration::from_millis(500), chat_channels[1].1.recv()).await
+        {
+            break message.unwrap();
+        }
+    };
+    assert_eq!(message, "hello");
+
+    match common::teardown_peer_connections(peer_connections).await {
+        Ok(ok) => ok,
+        Err(err) => {
+            error!("{}: error {}", session_id, err);
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_data_channel_relay_with_different_params() -> anyhow::Result<()> {
+    // Prepare the configuration
+    let endpoint_count: usize = 2;
+    let session_id: u64 = random::<u64>();
+    let config = RTCConfiguration {
+        ice_servers: vec![RTCIceServer {
+            urls: vec!["stun:stun.l.google.com:19302".to_owned()],
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+
+    let mut configs = vec![];
+    let mut endpoint_ids = vec![];
+    for endpoint_id in 0..endpoint_count {
+        configs.push(config.clone());
+        endpoint_ids.push(endpoint_id);
+    }
+
+    let peer_connections = match common::setup_peer_connections(configs, &endpoint_ids).await {
+        Ok(ok) => ok,
+        Err(err) => {
+            error!("{}: error {}", session_id, err);
+            return Err(err);
+        }
+    };
+
+    let mut channels = vec![];
+    for (endpoint_id, peer_connection) in peer_connections.iter().enumerate() {
+        match common::connect(
+            HOST,
+            SIGNAL_PORT,
+            session_id,
+            endpoint_id as u64,
+            peer_connection,
+        )
+        .await
+        {
+            Ok(ok) => ok,
+            Err(err) => {
+                error!("{}/{}: error {}", session_id, endpoint_id, err);
+                return Err(err);
+            }
+        };
+
+        // "events" is unordered on the first endpoint and ordered on the second one, while
+        // "chat" is ordered on both
+        let mut endpoint_channels = vec![];
+        for (label, ordered) in [("events", endpoint_id != 0), ("chat", true)] {
+            let data_channel = peer_connection
+                .create_data_channel(
+                    label,
+                    Some(RTCDataChannelInit {
+                        ordered: Some(ordered),
+                        ..Default::default()
+                    }),
+                )
+                .await?;
+            let opened_notify_tx = Arc::new(Notify::new());
+            let opened_notify_rx = opened_notify_tx.clone();
+            data_channel.on_open(Box::new(move || {
+                opened_notify_tx.notify_waiters();
+                Box::pin(async {})
+            }));
+            let (tx, rx) = tokio::sync::mpsc::unbounded_channel::<String>();
+            data_channel.on_message(Box::new(move |msg: DataChannelMessage| {
+                let _ = tx.send(String::from_utf8(msg.data.to_vec()).unwrap());
+                Box::pin(async {})
+            }));
+            opened_notify_rx.notified().await;
+            endpoint_channels.push((data_channel, rx));
+        }
+        channels.push(endpoint_channels);
+    }
+
+    // "chat" is relayed once the DCEP opens of both channels reach the SFU
+    let message = loop {
+        channels[0][1].0.send_text("hello".to_string()).await?;
+        if let Ok(message) =
+            tokio::time::timeout(Duration::from_millis(500), channels[1][1].1.recv()).await
+        {
+            break message.unwrap();
+        }
+    };
+    assert_eq!(message, "hello");
+
+    // "events" isn't relayed between channels with different ordering
+    channels[0][0].0.send_text("dropped".to_string()).await?;
+    assert!(
+        tokio::time::timeout(Duration::from_millis(500), channels[1][0].1.recv())
+            .await
+            .is_err()
+    );
+
+    match common::teardown_peer_connections(peer_connections).await {
+        Ok(ok) => ok,
+        Err(err) => {
+            error!("{}: error {}", session_id, err);
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+fn ice_ufrag(sdp: &str) -> Option<String> {
+    sdp.lines()
+        .find_map(|line| line.strip_prefix("a=ice-ufrag:"))
+        .map(|ufrag| ufrag.trim().to_string())
+}
+
+#[tokio::test]
+async fn test_ice_restart() -> anyhow::Result<()> {
+    // Prepare the configuration
+    let session_id: u64 = random::<u64>();
+    let endpoint_id = 0;
+    let config = RTCConfiguration {
+        ice_servers: vec![RTCIceServer {
+            urls: vec!["stun:stun.l.google.com:19302".to_owned()],
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+
+    let peer_connection = match common::setup_peer_connection(config, endpoint_id).await {
+        Ok(ok) => ok,
+        Err(err) => {
+            error!("error: {}", err);
+            return Err(err);
+        }
+    };
+
+    let (data_channel, mut data_channel_rx) =
+        match common::connect(HOST, SIGNAL_PORT, session_id, endpoint_id, &peer_connection).await {
+            Ok(ok) => ok,
+            Err(err) => {
+                error!("error: {}", err);
+                return Err(err);
+            }
+        };
+    let ufrag = peer_connection
+        .remote_description()
+        .await
+        .and_then(|answer| ice_ufrag(&answer.sdp));
+    assert!(ufrag.is_some());
+
+    let ice_ready_notify_tx = Arc::new(Notify::new());
+    let ice_ready_notify_rx = ice_ready_notify_tx.clone();
+    peer_connection.on_ice_connection_state_change(Box::new(
+        move |connection_state: RTCIceConnectionState| {
+            if connection_state == RTCIceConnectionState::Connected {
+                ice_ready_notify_tx.notify_waiters();
+            }
+            Box::pin(async {})
+        },
+    ));
+
+    // restart ICE with new credentials, the old path is closed once local description is set,
+    // so the offer can't be sent over the data channel
+    let offer = peer_connection
+        .create_offer(Some(RTCOfferOptions {
+            ice_restart: true,
+            ..Default::default()
+        }))
+        .await?;
+    let offer_payload = serde_json::to_string(&offer)?;
+    peer_connection.set_local_description(offer).await?;
+    let ice_ready = ice_ready_notify_rx.notified();
+    let answer =
+        common::signaling(HOST, SIGNAL_PORT, session_id, endpoint_id, offer_payload).await?;
+    let restarted_ufrag = ice_ufrag(&answer.sdp);
+    assert!(restarted_ufrag.is_some());
+    assert_ne!(ufrag, restarted_ufrag);
+    peer_connection.set_remote_description(answer).await?;
+    ice_ready.await;
+
+    // DTLS and SCTP survive ICE restart, so renegotiation still works over the data channel
+    match common::renegotiate(
+        HOST,
+        SIGNAL_PORT,
+        session_id,
+        endpoint_id,
+        &peer_connection,
+        Some(&data_channel),
+    )
+    .await
+    {
+        Ok(ok) => ok,
+        Err(err) => {
+            error!("error: {}", err);
+            return Err(err);
+        }
+    }
+    let answer = data_channel_rx.recv().await.unwrap();
+    assert_eq!(restarted_ufrag, ice_ufrag(&answer.sdp));
+
+    match common::teardown_peer_connection(peer_connection).await {
+        Ok(ok) => ok,
+        Err(err) => {
+            error!("error: {}", err);
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_dtls_fingerprint_mismatch() -> anyhow::Result<()> {
+    // Prepare the configuration
+    let session_id: u64 = random::<u64>();
+    let endpoint_id = 0;
+    let config = RTCConfiguration {
+        ice_servers: vec![RTCIceServer {
+            urls: vec!["stun:stun.l.google.com:19302".to_owned()],
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+
+    let peer_connection = match common::setup_peer_connection(config, endpoint_id).await {
+        Ok(ok) => ok,
+        Err(err) => {
+            error!("error: {}", err);
+            return Err(err);
+        }
+    };
+
+    let data_channel = peer_connection.create_data_channel("data", None).await?;
+    let data_channel_opened = Arc::new(AtomicBool::new(false));
+    let data_channel_opened_clone = data_channel_opened.clone();
+    data_channel.on_open(Box::new(move || {
+        data_channel_opened_clone.store(true, Ordering::SeqCst);
+        Box::pin(async {})
+    }));
+
+    let ice_ready_notify_tx = Arc::new(Notify::new());
+    let ice_ready_notify_rx = ice_ready_notify_tx.clone();
+    let ice_lost_notify_tx = Arc::new(Notify::new());
+    let ice_lost_notify_rx = ice_lost_notify_tx.clone();
+    peer_connection.on_ice_connection_state_change(Box::new(
+        move |connection_state: RTCIceConnectionState| {
+            match connection_state {
+                RTCIceConnectionState::Connected => ice_ready_notify_tx.notify_waiters(),
+                RTCIceConnectionState::Disconnected | RTCIceConnectionState::Failed => {
+                    ice_lost_notify_tx.notify_one()
+                }
+                _ => {}
+            }
+            Box::pin(async {})
+        },
+    ));
+
+    // advertise a fingerprint which doesn't match the certificate presented in DTLS handshake
+    let mut offer = peer_connection.create_offer(None).await?;
+    peer_connection.set_local_description(offer.clone()).await?;
+    offer.sdp = offer
+        .sdp
+        .lines()
+        .map(|line| {
+            if line.starts_with("a=fingerprint:sha-256 ") {
+                format!("a=fingerprint:sha-256 {}", ["AA"; 32].join(":"))
+            } else {
+                line.to_string()
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("\r\n")
+        + "\r\n";
+    let offer_payload = serde_json::to_string(&offer)?;
+    let ice_ready = ice_ready_notify_rx.notified();
+    let answer =
+        common::signaling(HOST, SIGNAL_PORT, session_id, endpoint_id, offer_payload).await?;
+    peer_connection.set_remote_description(answer).await?;
+    ice_ready.await;
+
+    // transport is removed once DTLS handshake completes, so SCTP never gets established and
+    // server stops answering consent checks of its candidate
+    tokio::time::timeout(Duration::from_secs(30), ice_lost_notify_rx.notified()).await?;
+    assert!(!data_channel_opened.load(Ordering::SeqCst));
+
+    match common::teardown_peer_connection(peer_connection).await {
+        Ok(ok) => ok,
+        Err(err) => {
+            error!("error: {}", err);
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_server_data_channel() -> anyhow::Result<()> {
+    // Prepare the configuration
+    let session_id: u64 = random::<u64>();
+    let endpoint_id = 0;
+    let config = RTCConfiguration {
+        ice_servers: vec![RTCIceServer {
+            urls: vec!["stun:stun.l.google.com:19302".to_owned()],
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+
+    let peer_connection = match common::setup_peer_connection(config, endpoint_id).await {
+        Ok(ok) => ok,
+        Err(err) => {
+            error!("error: {}", err);
+            return Err(err);
+        }
+    };
+
+    // server opens a "server" data channel and sends "welcome" once it is acked
+    let (welcome_tx, mut welcome_rx) = tokio::sync::mpsc::unbounded_channel::<String>();
+    peer_connection.on_data_channel(Box::new(move |data_channel| {
+        if data_channel.label() == "server" {
+            let welcome_tx = welcome_tx.clone();
+            data_channel.on_message(Box::new(move |msg: DataChannelMessage| {
+                let _ = welcome_tx.send(String::from_utf8_lossy(&msg.data).to_string());
+                Box::pin(async {})
+            }));
+        }
+        Box::pin(async {})
+    }));
+
+    match common::connect(HOST, SIGNAL_PORT, session_id, endpoint_id, &peer_connection).await {
+        Ok(ok) => ok,
+        Err(err) => {
+            error!("error: {}", err);
+            return Err(err);
+        }
+    };
+
+    let welcome = tokio::time::timeout(Duration::from_secs(10), welcome_rx.recv()).await?;
+    assert_eq!(welcome.as_deref(), Some("welcome"));
+
+    match common::teardown_peer_connection(peer_connection).await {
+        Ok(ok) => ok,
+        Err(err) => {
+            error!("error: {}", err);
+            return Err(err);
         }
     }
     Ok(())